@@ -0,0 +1,111 @@
+"""
+Generate golden fixtures for the Rust preprocessing parity tests
+(sonicsearch-app/src-tauri/src/audio_index/parity_tests.rs).
+
+For each test clip, this writes:
+- `<name>.wav`: the 48kHz mono float32 input
+- `<name>.input_features.f32`: the HF `ClapFeatureExtractor` output, shape [1, 1001, 64]
+- `<name>.embedding.f32`: the ONNX audio model output for those features, shape [512]
+
+All .f32 files are raw little-endian float32. `manifest.json` lists the cases.
+
+Usage:
+    python generate_parity_fixtures.py \
+        --onnx-model ../sonicsearch-app/src-tauri/onnx_models/clap-htsat-unfused_audio_with_projection.onnx
+"""
+
+import argparse
+import json
+from pathlib import Path
+from unittest import mock
+
+import numpy as np
+import onnxruntime as ort
+import soundfile as sf
+import transformers
+from transformers import ClapFeatureExtractor
+
+CHECKPOINT = "laion/clap-htsat-unfused"
+SAMPLE_RATE = 48000
+DEFAULT_OUTPUT_DIR = (
+    Path(__file__).parent.parent
+    / "sonicsearch-app"
+    / "src-tauri"
+    / "test_resources"
+    / "parity"
+)
+
+
+def make_cases(rng):
+    """Returns a list of (name, description, waveform, crop_start)"""
+    t_3s = np.arange(int(SAMPLE_RATE * 3.3)) / SAMPLE_RATE
+    chirp = np.sin(2 * np.pi * (200 + 800 * t_3s) * t_3s)
+    tone_with_noise = 0.4 * chirp + 0.05 * rng.standard_normal(t_3s.shape)
+
+    t_10s = np.arange(SAMPLE_RATE * 10) / SAMPLE_RATE
+    envelope = (np.sin(2 * np.pi * 0.7 * t_10s) > 0.6).astype(np.float64)
+    noise_bursts = 0.3 * envelope * rng.standard_normal(t_10s.shape)
+
+    t_14s = np.arange(SAMPLE_RATE * 14) / SAMPLE_RATE
+    long_sweep = 0.5 * np.sin(2 * np.pi * (100 + 300 * t_14s) * t_14s)
+
+    return [
+        ("repeatpad_3s", "3.3s chirp with noise, repeat-padded", tone_with_noise, None),
+        ("exact_10s", "Exactly 10s of noise bursts", noise_bursts, None),
+        ("truncation_14s", "14s sweep, truncated to 10s", long_sweep, SAMPLE_RATE * 2),
+        ("silence_10s", "10s of digital silence", np.zeros(SAMPLE_RATE * 10), None),
+        ("silence_1s", "1s of digital silence, repeat-padded", np.zeros(SAMPLE_RATE), None),
+    ]
+
+
+def extract_features(feature_extractor, waveform, crop_start):
+    # rand_trunc picks the crop with np.random.randint. Pin it so the Rust side can match.
+    with mock.patch.object(np.random, "randint", return_value=crop_start or 0):
+        features = feature_extractor(
+            waveform, sampling_rate=SAMPLE_RATE, return_tensors="np"
+        )
+    # [batch, channels, frames, mels] -> [channels, frames, mels]
+    return features["input_features"][0].astype(np.float32)
+
+
+def main():
+    parser = argparse.ArgumentParser()
+    parser.add_argument("--onnx-model", required=True, type=Path)
+    parser.add_argument("--output-dir", default=DEFAULT_OUTPUT_DIR, type=Path)
+    parser.add_argument("--seed", default=0, type=int)
+    args = parser.parse_args()
+
+    args.output_dir.mkdir(parents=True, exist_ok=True)
+    feature_extractor = ClapFeatureExtractor.from_pretrained(CHECKPOINT)
+    session = ort.InferenceSession(str(args.onnx_model))
+    input_name = session.get_inputs()[0].name
+
+    manifest = {
+        "checkpoint": CHECKPOINT,
+        "transformers_version": transformers.__version__,
+        "onnx_model": args.onnx_model.name,
+        "cases": [],
+    }
+    for name, description, waveform, crop_start in make_cases(
+        np.random.default_rng(args.seed)
+    ):
+        waveform = waveform.astype(np.float32)
+        sf.write(args.output_dir / f"{name}.wav", waveform, SAMPLE_RATE, subtype="FLOAT")
+
+        input_features = extract_features(feature_extractor, waveform, crop_start)
+        input_features.astype("<f4").tofile(args.output_dir / f"{name}.input_features.f32")
+
+        (embedding,) = session.run(None, {input_name: input_features[np.newaxis]})[:1]
+        embedding[0].astype("<f4").tofile(args.output_dir / f"{name}.embedding.f32")
+
+        manifest["cases"].append(
+            {"name": name, "description": description, "crop_start": crop_start}
+        )
+        print(f"Wrote {name}: features {input_features.shape}, embedding {embedding[0].shape}")
+
+    with open(args.output_dir / "manifest.json", "w") as manifest_file:
+        json.dump(manifest, manifest_file, indent=2)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1 @@
+ALTER TABLE audio_file_segment DROP COLUMN ending_timestamp;
@@ -0,0 +1,35 @@
+-- Rebuilt rather than altered, so ending_timestamp can be NOT NULL.
+-- Nothing references audio_file_segment yet, so it's safe to drop.
+CREATE TABLE audio_file_segment_new(
+    rowid INTEGER PRIMARY KEY ASC,
+    file_hash text NOT NULL,
+    starting_timestamp real NOT NULL,
+    embedding BLOB NOT NULL,
+    ending_timestamp real NOT NULL,
+    FOREIGN KEY (file_hash) REFERENCES audio_file(file_hash),
+    UNIQUE(file_hash, starting_timestamp)
+);
+
+-- Segments indexed before this migration were 10 second windows at a 5 second step,
+-- with as many windows as whole steps fit in the file. Files' durations weren't stored,
+-- but a file is at least a step longer than its last window's start, so ending windows
+-- there keeps them inside the file. The exception is a file shorter than a step,
+-- whose single window may still end a little past the end of the file.
+INSERT INTO audio_file_segment_new (rowid, file_hash, starting_timestamp, embedding, ending_timestamp)
+SELECT
+    rowid,
+    file_hash,
+    starting_timestamp,
+    embedding,
+    MIN(
+        starting_timestamp + 10.0,
+        (
+            SELECT MAX(last_segment.starting_timestamp)
+            FROM audio_file_segment AS last_segment
+            WHERE last_segment.file_hash = audio_file_segment.file_hash
+        ) + 5.0
+    )
+FROM audio_file_segment;
+
+DROP TABLE audio_file_segment;
+ALTER TABLE audio_file_segment_new RENAME TO audio_file_segment;
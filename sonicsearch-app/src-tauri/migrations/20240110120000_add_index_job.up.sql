@@ -0,0 +1,7 @@
+-- The files queued by the current indexing run, so an interrupted run can be resumed
+CREATE TABLE IF NOT EXISTS index_job (
+  file_path TEXT PRIMARY KEY NOT NULL,
+  file_hash TEXT NOT NULL,
+  state TEXT NOT NULL CHECK (state IN ('pending', 'embedding', 'done', 'failed')),
+  updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
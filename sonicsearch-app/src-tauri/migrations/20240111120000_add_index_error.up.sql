@@ -0,0 +1,13 @@
+-- Files that failed to index, so they can be reported and retried with backoff.
+-- Timestamps are unix seconds. A null next_retry_at means the file is only
+-- retried once its contents change or the user asks.
+CREATE TABLE IF NOT EXISTS index_error (
+  file_path TEXT PRIMARY KEY NOT NULL,
+  file_hash TEXT NOT NULL,
+  stage TEXT NOT NULL,
+  error_kind TEXT NOT NULL,
+  message TEXT NOT NULL,
+  attempts INTEGER NOT NULL DEFAULT 1,
+  last_failed_at INTEGER NOT NULL,
+  next_retry_at INTEGER
+);
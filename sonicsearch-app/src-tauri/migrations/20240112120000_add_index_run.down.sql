@@ -0,0 +1,2 @@
+ALTER TABLE audio_file DROP COLUMN duration_seconds;
+DROP TABLE IF EXISTS index_run;
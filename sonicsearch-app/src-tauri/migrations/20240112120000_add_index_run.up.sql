@@ -0,0 +1,18 @@
+-- One row per indexing run. Timestamps are unix seconds.
+-- A run without an ended_at was interrupted, or is still going.
+CREATE TABLE IF NOT EXISTS index_run (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  started_at INTEGER NOT NULL,
+  ended_at INTEGER,
+  resumed BOOLEAN NOT NULL DEFAULT FALSE,
+  files_scanned INTEGER NOT NULL DEFAULT 0,
+  files_new INTEGER NOT NULL DEFAULT 0,
+  files_moved INTEGER NOT NULL DEFAULT 0,
+  -- Indexed files that the scan didn't find at their recorded path
+  files_removed INTEGER NOT NULL DEFAULT 0,
+  files_failed INTEGER NOT NULL DEFAULT 0,
+  segments_embedded INTEGER NOT NULL DEFAULT 0
+);
+
+-- Null for files indexed before this migration
+ALTER TABLE audio_file ADD COLUMN duration_seconds REAL;
@@ -0,0 +1,7 @@
+ALTER TABLE dir_paths DROP COLUMN max_duration_seconds;
+ALTER TABLE dir_paths DROP COLUMN min_duration_seconds;
+ALTER TABLE dir_paths DROP COLUMN follow_symlinks;
+ALTER TABLE dir_paths DROP COLUMN max_depth;
+ALTER TABLE dir_paths DROP COLUMN exclude_globs;
+ALTER TABLE dir_paths DROP COLUMN include_globs;
+ALTER TABLE dir_paths DROP COLUMN enabled;
@@ -0,0 +1,9 @@
+-- Per-root scanning options. Globs are JSON arrays of patterns matched against
+-- paths relative to the root. Null limits mean unlimited.
+ALTER TABLE dir_paths ADD COLUMN enabled BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE dir_paths ADD COLUMN include_globs TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE dir_paths ADD COLUMN exclude_globs TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE dir_paths ADD COLUMN max_depth INTEGER;
+ALTER TABLE dir_paths ADD COLUMN follow_symlinks BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE dir_paths ADD COLUMN min_duration_seconds REAL;
+ALTER TABLE dir_paths ADD COLUMN max_duration_seconds REAL;
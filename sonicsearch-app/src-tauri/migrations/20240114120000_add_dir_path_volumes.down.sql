@@ -0,0 +1,5 @@
+ALTER TABLE dir_paths DROP COLUMN volume_fingerprint;
+ALTER TABLE dir_paths DROP COLUMN volume_relative_path;
+ALTER TABLE dir_paths DROP COLUMN volume_mount_point;
+ALTER TABLE dir_paths DROP COLUMN last_seen_at;
+ALTER TABLE dir_paths DROP COLUMN online;
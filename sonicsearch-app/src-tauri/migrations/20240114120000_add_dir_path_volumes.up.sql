@@ -0,0 +1,9 @@
+-- Which volume each root lives on, so it can be told apart from a deleted
+-- folder while the volume is unmounted, and found again at a new mount point.
+-- The fingerprint is a JSON array of a few files under the root with their sizes.
+-- last_seen_at is in unix seconds.
+ALTER TABLE dir_paths ADD COLUMN online BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE dir_paths ADD COLUMN last_seen_at INTEGER;
+ALTER TABLE dir_paths ADD COLUMN volume_mount_point TEXT;
+ALTER TABLE dir_paths ADD COLUMN volume_relative_path TEXT;
+ALTER TABLE dir_paths ADD COLUMN volume_fingerprint TEXT NOT NULL DEFAULT '[]';
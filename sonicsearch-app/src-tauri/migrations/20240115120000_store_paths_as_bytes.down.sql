@@ -0,0 +1,67 @@
+-- Non-UTF-8 paths can't be converted back without loss
+
+ALTER TABLE audio_file ADD COLUMN file_path_text TEXT NOT NULL DEFAULT '';
+UPDATE audio_file SET file_path_text = CAST(file_path AS TEXT);
+ALTER TABLE audio_file DROP COLUMN file_path;
+ALTER TABLE audio_file RENAME COLUMN file_path_text TO file_path;
+
+CREATE TABLE dir_paths_text (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  path TEXT NOT NULL,
+  created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  enabled BOOLEAN NOT NULL DEFAULT TRUE,
+  include_globs TEXT NOT NULL DEFAULT '[]',
+  exclude_globs TEXT NOT NULL DEFAULT '[]',
+  max_depth INTEGER,
+  follow_symlinks BOOLEAN NOT NULL DEFAULT FALSE,
+  min_duration_seconds REAL,
+  max_duration_seconds REAL,
+  online BOOLEAN NOT NULL DEFAULT TRUE,
+  last_seen_at INTEGER,
+  volume_mount_point TEXT,
+  volume_relative_path TEXT,
+  volume_fingerprint TEXT NOT NULL DEFAULT '[]',
+  UNIQUE(path)
+);
+INSERT INTO dir_paths_text (
+  id, path, created_at, updated_at, enabled, include_globs, exclude_globs, max_depth,
+  follow_symlinks, min_duration_seconds, max_duration_seconds, online, last_seen_at,
+  volume_mount_point, volume_relative_path
+)
+SELECT
+  id, CAST(path AS TEXT), created_at, updated_at, enabled, include_globs, exclude_globs,
+  max_depth, follow_symlinks, min_duration_seconds, max_duration_seconds, online,
+  last_seen_at, CAST(volume_mount_point AS TEXT), CAST(volume_relative_path AS TEXT)
+FROM dir_paths;
+DROP TABLE dir_paths;
+ALTER TABLE dir_paths_text RENAME TO dir_paths;
+
+CREATE TABLE index_error_text (
+  file_path TEXT PRIMARY KEY NOT NULL,
+  file_hash TEXT NOT NULL,
+  stage TEXT NOT NULL,
+  error_kind TEXT NOT NULL,
+  message TEXT NOT NULL,
+  attempts INTEGER NOT NULL DEFAULT 1,
+  last_failed_at INTEGER NOT NULL,
+  next_retry_at INTEGER
+);
+INSERT INTO index_error_text
+SELECT
+  CAST(file_path AS TEXT), file_hash, stage, error_kind, message, attempts,
+  last_failed_at, next_retry_at
+FROM index_error;
+DROP TABLE index_error;
+ALTER TABLE index_error_text RENAME TO index_error;
+
+CREATE TABLE index_job_text (
+  file_path TEXT PRIMARY KEY NOT NULL,
+  file_hash TEXT NOT NULL,
+  state TEXT NOT NULL CHECK (state IN ('pending', 'embedding', 'done', 'failed')),
+  updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO index_job_text
+SELECT CAST(file_path AS TEXT), file_hash, state, updated_at FROM index_job;
+DROP TABLE index_job;
+ALTER TABLE index_job_text RENAME TO index_job;
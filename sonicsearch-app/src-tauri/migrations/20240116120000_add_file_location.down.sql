@@ -0,0 +1,9 @@
+-- Only one location per file can be kept
+ALTER TABLE audio_file ADD COLUMN file_path BLOB NOT NULL DEFAULT x'';
+UPDATE audio_file SET file_path = (
+  SELECT MIN(file_path) FROM file_location WHERE file_location.file_hash = audio_file.file_hash
+)
+WHERE EXISTS (SELECT 1 FROM file_location WHERE file_location.file_hash = audio_file.file_hash);
+
+DROP INDEX IF EXISTS file_location_file_hash;
+DROP TABLE IF EXISTS file_location;
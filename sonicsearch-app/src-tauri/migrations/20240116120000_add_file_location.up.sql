@@ -0,0 +1,10 @@
+-- The same content can live at several paths, so paths get their own table
+CREATE TABLE IF NOT EXISTS file_location (
+  file_path BLOB PRIMARY KEY NOT NULL,
+  file_hash TEXT NOT NULL,
+  FOREIGN KEY (file_hash) REFERENCES audio_file(file_hash)
+);
+CREATE INDEX IF NOT EXISTS file_location_file_hash ON file_location (file_hash);
+
+INSERT INTO file_location (file_path, file_hash) SELECT file_path, file_hash FROM audio_file;
+ALTER TABLE audio_file DROP COLUMN file_path;
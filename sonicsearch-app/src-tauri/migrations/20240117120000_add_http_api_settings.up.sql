@@ -0,0 +1,8 @@
+-- A single row, created with a random token so the API is never open without one
+CREATE TABLE IF NOT EXISTS http_api_settings (
+  id INTEGER PRIMARY KEY CHECK (id = 1),
+  enabled BOOLEAN NOT NULL DEFAULT FALSE,
+  port INTEGER NOT NULL DEFAULT 47800,
+  token TEXT NOT NULL DEFAULT (lower(hex(randomblob(16))))
+);
+INSERT OR IGNORE INTO http_api_settings (id) VALUES (1);
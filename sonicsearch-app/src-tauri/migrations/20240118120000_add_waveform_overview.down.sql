@@ -0,0 +1 @@
+DROP TABLE IF EXISTS waveform_overview;
@@ -0,0 +1,7 @@
+-- A downsampled waveform of each file, so the UI can draw it without decoding the file
+CREATE TABLE IF NOT EXISTS waveform_overview (
+  file_hash TEXT PRIMARY KEY NOT NULL,
+  peaks BLOB NOT NULL,
+  rms BLOB NOT NULL,
+  FOREIGN KEY (file_hash) REFERENCES audio_file(file_hash)
+);
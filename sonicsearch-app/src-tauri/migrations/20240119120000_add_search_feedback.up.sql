@@ -0,0 +1,10 @@
+-- Results the user marked as relevant or not to a query, kept as training signal.
+-- Timestamps are unix seconds.
+CREATE TABLE IF NOT EXISTS search_feedback (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  query_embedding BLOB NOT NULL,
+  segment_id INTEGER NOT NULL,
+  relevant BOOLEAN NOT NULL,
+  created_at INTEGER NOT NULL,
+  FOREIGN KEY (segment_id) REFERENCES audio_file_segment(rowid)
+);
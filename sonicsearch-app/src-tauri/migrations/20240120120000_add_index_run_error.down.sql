@@ -0,0 +1 @@
+ALTER TABLE index_run DROP COLUMN error;
@@ -0,0 +1,2 @@
+-- Why a run stopped early. Null if it finished, or is still going.
+ALTER TABLE index_run ADD COLUMN error TEXT;
@@ -0,0 +1,10 @@
+CREATE TABLE index_job_without_copies (
+  file_path BLOB PRIMARY KEY NOT NULL,
+  file_hash TEXT NOT NULL,
+  state TEXT NOT NULL CHECK (state IN ('pending', 'embedding', 'done', 'failed')),
+  updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO index_job_without_copies
+  SELECT file_path, file_hash, state, updated_at FROM index_job WHERE state != 'copy';
+DROP TABLE index_job;
+ALTER TABLE index_job_without_copies RENAME TO index_job;
@@ -0,0 +1,11 @@
+-- Copies of the queued files are kept in the job too, so a resumed run still locates them.
+-- SQLite can't alter a CHECK constraint, so the table is rebuilt.
+CREATE TABLE index_job_with_copies (
+  file_path BLOB PRIMARY KEY NOT NULL,
+  file_hash TEXT NOT NULL,
+  state TEXT NOT NULL CHECK (state IN ('pending', 'embedding', 'done', 'failed', 'copy')),
+  updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO index_job_with_copies SELECT file_path, file_hash, state, updated_at FROM index_job;
+DROP TABLE index_job;
+ALTER TABLE index_job_with_copies RENAME TO index_job;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS search_feedback_original_query_segment;
+ALTER TABLE search_feedback DROP COLUMN original_query_embedding;
@@ -0,0 +1,7 @@
+-- The query the user searched with before refining it. Feedback from every round of
+-- refining is relative to it, while query_embedding is the query that found the marked result.
+-- Null for feedback saved before it was recorded.
+ALTER TABLE search_feedback ADD COLUMN original_query_embedding BLOB;
+-- Results stay marked across rounds, so marking one again updates its feedback
+CREATE UNIQUE INDEX search_feedback_original_query_segment
+  ON search_feedback (original_query_embedding, segment_id);
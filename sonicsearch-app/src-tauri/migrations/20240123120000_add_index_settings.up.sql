@@ -0,0 +1,7 @@
+-- A single row of settings that apply to every indexed file
+CREATE TABLE IF NOT EXISTS index_settings (
+  id INTEGER PRIMARY KEY CHECK (id = 1),
+  segmentation TEXT NOT NULL DEFAULT 'fixed_window'
+    CHECK (segmentation IN ('fixed_window', 'event_aligned'))
+);
+INSERT OR IGNORE INTO index_settings (id) VALUES (1);
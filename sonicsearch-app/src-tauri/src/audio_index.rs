@@ -1,9 +1,7 @@
 use hound::{SampleFormat, WavReader};
 use log::{debug, info, log_enabled, trace, warn};
-use mel_spec::config::MelConfig;
-use mel_spec_pipeline::{Pipeline, PipelineConfig};
-use ndarray::{concatenate, Array2, Array3, Axis};
 use rubato::{FftFixedIn, Resampler};
+use std::collections::HashSet;
 use std::hash::Hasher;
 use std::path::PathBuf;
 use std::{cmp, result};
@@ -15,20 +13,48 @@ use std::{
 use tokio::join;
 
 use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
 use futures::future::join_all;
 use sqlx::SqlitePool;
 use tauri::State;
 use twox_hash::XxHash64;
-use walkdir::WalkDir;
 
 use crate::audio_index::indexing_status::Status;
-use crate::index_paths::get_paths_from_index;
-use crate::state::database::{encode_embedding, vector_index};
+use crate::clap::ClapModel;
+use crate::embeddings::mean_embedding;
+use crate::index_paths::{find_innermost_root, get_index_roots_from_db, IndexRoot};
+use crate::paths::{decode_path, display_path, encode_path};
+use crate::state::database::encode_embedding;
 use crate::state::{audio_embedder::AudioEmbedder, AppState};
+use crate::volumes::{record_volume_identity, refresh_root_availability};
 
+use self::feature_extractor::{clap_feature_extractor, AudioFeatures};
+use self::index_error::UnsupportedFormatError;
+use self::index_run::IndexRunCounts;
 use self::indexing_status::IndexingStatus;
+use self::pipeline::{run_indexing_pipeline, FileFailure, PipelineConfig, PipelineStage};
+use self::segmentation::{
+    get_segmentation_strategy_from_db, split_audio_into_event_segments, SegmentationStrategy,
+};
+use self::waveform::{save_overview_in_transaction, WaveformOverview};
 
+pub mod feature_extractor;
+pub mod file_location;
+pub mod index_error;
+mod index_job;
+pub mod index_run;
 pub mod indexing_status;
+#[cfg(test)]
+mod parity_tests;
+mod pipeline;
+mod scanner;
+pub mod segmentation;
+pub mod waveform;
+
+/// Timestamps are stored as unix seconds
+pub(crate) fn timestamp_to_date_time(timestamp: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(timestamp, 0).unwrap_or_default()
+}
 
 fn compute_hash(file: &File) -> io::Result<String> {
     let hash_seed = 1023489u64;
@@ -62,7 +88,6 @@ pub struct UpdateAudioIndex(());
 pub async fn update_audio_index(app_state: State<'_, AppState>) -> result::Result<bool, String> {
     debug!("\n--- Updating audio file index... ---");
     let indexing_status = &app_state.indexing_status;
-    let vector_index = &app_state.vector_index;
 
     let current_indexing_status = indexing_status.get_status().await;
     if matches!(current_indexing_status, Status::InProgress(_)) {
@@ -77,28 +102,192 @@ pub async fn update_audio_index(app_state: State<'_, AppState>) -> result::Resul
     }
 
     indexing_status
-        .set_started()
+        .set_scanning()
         .await
-        .map_err(|err| format!("Failed to set indexing status to preindexing: {:?}", err))?;
+        .map_err(|err| format!("Failed to set indexing status to scanning: {:?}", err))?;
 
-    let user_audio_dirs = get_paths_from_index(app_state.clone())
+    // Whatever happened, indexing is over
+    let update_result = update_index(&app_state).await;
+    let idle_result = indexing_status.set_idle().await.map_err(|err| {
+        format!(
+            "Failed to set indexing status to idle after indexing: {:?}",
+            err
+        )
+    });
+    update_result?;
+    idle_result?;
+    debug!("\nAudio file index updated.");
+    Ok(true)
+}
+
+/// Index the user's directories, recording the run whether or not it succeeds
+async fn update_index(app_state: &AppState) -> result::Result<(), String> {
+    let pool = &app_state.pool;
+    refresh_root_availability(pool)
+        .await
+        .map_err(|err| format!("Failed to check which roots are online: {:?}", err))?;
+    let roots = get_index_roots_from_db(pool)
         .await
         .map_err(|err| format!("Failed to get user-defined directories: {:?}", err))?;
-    debug!("Updating index for {} paths", user_audio_dirs.len());
-    let audio_embedder = &app_state.clap_model_audio_embedder;
-    let pool = app_state.pool.clone();
 
-    let indexable_files = user_audio_dirs
+    // Files on offline volumes are left for the scan once they're back
+    let unfinished_files = index_job::get_unfinished_files(pool)
+        .await
+        .map_err(|err| format!("Failed to check for an interrupted index job: {:?}", err))?
         .into_iter()
-        .flat_map(WalkDir::new)
-        .filter(|dir| {
-            dir.as_ref()
-                .is_ok_and(|ok_dir| ok_dir.path().is_file() && is_audio_file(ok_dir.path()))
-        })
-        .map(|dir| dir.map_err(|err| anyhow::anyhow!("Failed to walk dir: {:?}", err)))
-        .collect::<Result<Vec<_>>>()
-        .context("Failed to collect audio files")
+        .filter(|audio_file| is_on_online_root(&audio_file.file_path, &roots))
+        .collect::<Vec<_>>();
+    let resumed = !unfinished_files.is_empty();
+    let run_id = index_run::start_run(pool, resumed, Utc::now())
+        .await
+        .map_err(|err| format!("Failed to start index run: {:?}", err))?;
+    let mut run_counts = IndexRunCounts::default();
+    let run_result = run_index(app_state, &roots, unfinished_files, &mut run_counts).await;
+    index_run::finish_run(
+        pool,
+        run_id,
+        run_counts,
+        run_result.as_ref().err().map(String::as_str),
+        Utc::now(),
+    )
+    .await
+    .map_err(|err| format!("Failed to finish index run: {:?}", err))?;
+    run_result
+}
+
+/// Scan and index the user's directories, or resume `unfinished_files` if there are any
+async fn run_index(
+    app_state: &AppState,
+    roots: &[IndexRoot],
+    unfinished_files: Vec<LoadedAudioFile>,
+    run_counts: &mut IndexRunCounts,
+) -> result::Result<(), String> {
+    let pool = &app_state.pool;
+    let indexing_status = &app_state.indexing_status;
+    let vector_index = &app_state.vector_index;
+    let audio_embedder = &app_state.clap_model_audio_embedder;
+    let segmentation = get_segmentation_strategy_from_db(pool)
+        .await
+        .map_err(|err| format!("Failed to get segmentation strategy: {:?}", err))?;
+    let (audio_files_to_index, copies_to_locate) = if unfinished_files.is_empty() {
+        let (audio_files_to_index, copies_to_locate) =
+            find_audio_files_to_index(pool, roots, indexing_status, run_counts).await?;
+        index_job::create_job(pool, &audio_files_to_index, &copies_to_locate)
+            .await
+            .map_err(|err| format!("Failed to create index job: {:?}", err))?;
+
+        debug!("Indexing {} new files", audio_files_to_index.len());
+        indexing_status
+            .set_indexing_started(audio_files_to_index.len() as u32)
+            .await
+            .map_err(|err| {
+                format!(
+                    "Failed to set indexing status to indexing_started: {:?}",
+                    err
+                )
+            })?;
+        (audio_files_to_index, copies_to_locate)
+    } else {
+        info!(
+            "Resuming interrupted indexing of {} files",
+            unfinished_files.len()
+        );
+        indexing_status
+            .set_resumed_indexing_started(unfinished_files.len() as u32)
+            .await
+            .map_err(|err| {
+                format!(
+                    "Failed to set indexing status to resumed indexing: {:?}",
+                    err
+                )
+            })?;
+        run_counts.files_scanned = unfinished_files.len() as u32;
+        run_counts.files_new = unfinished_files.len() as u32;
+        let copies_to_locate = index_job::get_copies(pool)
+            .await
+            .map_err(|err| format!("Failed to get copies of interrupted index job: {:?}", err))?;
+        (unfinished_files, copies_to_locate)
+    };
+
+    let pipeline_future = async move {
+        let pipeline_result = run_indexing_pipeline(
+            audio_files_to_index,
+            pool,
+            audio_embedder,
+            indexing_status,
+            vector_index,
+            PipelineConfig {
+                segmentation,
+                ..PipelineConfig::default()
+            },
+        )
+        .await;
+        info!("All indexing completed. Stopping audio embedder.");
+        audio_embedder.stop_processing_queue();
+        pipeline_result
+    };
+
+    let embedder_future = audio_embedder.begin_processing_queue();
+    let (embedder_result, pipeline_result) = join!(embedder_future, pipeline_future);
+
+    embedder_result
+        .context("Model should run successfully")
+        .map_err(|err| format!("Failed to run audio embedder: {:?}", err))?;
+    let pipeline_summary =
+        pipeline_result.map_err(|err| format!("Failed to run indexing pipeline: {:?}", err))?;
+    debug!(
+        "Indexed {} files total. Success: {}, Failures: {}",
+        pipeline_summary.n_indexed + pipeline_summary.failures.len(),
+        pipeline_summary.n_indexed,
+        pipeline_summary.failures.len()
+    );
+    run_counts.files_failed += pipeline_summary.failures.len() as u32;
+    run_counts.segments_embedded = pipeline_summary.n_segments as u32;
+    file_location::add_indexed_locations(pool, &copies_to_locate)
+        .await
+        .map_err(|err| format!("Failed to add locations of copied files: {:?}", err))?;
+    index_job::clear_job(pool)
+        .await
+        .map_err(|err| format!("Failed to clear finished index job: {:?}", err))?;
+    Ok(())
+}
+
+/// Whether the last indexing run was interrupted before it finished,
+/// in which case the next call to update_audio_index resumes it
+pub async fn has_interrupted_index_job(pool: &SqlitePool) -> Result<bool> {
+    Ok(!index_job::get_unfinished_files(pool).await?.is_empty())
+}
+
+/// Walk the user's enabled directories and hash every audio file their options let through.
+/// Returns the files that are not in the index yet, once per hash, along with
+/// the other copies of those files, which get their locations once the content is indexed.
+async fn find_audio_files_to_index(
+    pool: &SqlitePool,
+    roots: &[IndexRoot],
+    indexing_status: &IndexingStatus,
+    run_counts: &mut IndexRunCounts,
+) -> result::Result<(Vec<LoadedAudioFile>, Vec<LoadedAudioFile>), String> {
+    debug!(
+        "Updating index for {} of {} paths",
+        roots
+            .iter()
+            .filter(|root| root.options.enabled && root.online)
+            .count(),
+        roots.len()
+    );
+
+    let indexable_files = scanner::scan_roots(roots)
         .map_err(|err| format!("Failed to collect audio files: {:?}", err))?;
+    for root in roots.iter().filter(|root| root.online) {
+        let root_files = indexable_files
+            .iter()
+            .filter(|path| path.starts_with(&root.path))
+            .cloned()
+            .collect::<Vec<_>>();
+        record_volume_identity(pool, &root.path, &root_files)
+            .await
+            .map_err(|err| format!("Failed to record volume of {:?}: {:?}", root.path, err))?;
+    }
     debug!("Found {} indexable files", indexable_files.len());
     indexing_status
         .set_preindexing_started(indexable_files.len() as u32)
@@ -112,183 +301,170 @@ pub async fn update_audio_index(app_state: State<'_, AppState>) -> result::Resul
 
     let audio_files_to_index_futures = indexable_files
         .iter()
-        .map(|dir| (dir, pool.clone()))
-        .map(move |(dir, pool)| {
+        .map(|path| (path, pool.clone()))
+        .map(move |(path, pool)| {
             Box::pin(preindex_files(
                 pool.to_owned(),
-                dir.path().to_owned(),
+                path.to_owned(),
+                roots,
                 indexing_status,
             ))
         })
         .collect::<Vec<_>>();
-    let audio_files_to_index = join_all(audio_files_to_index_futures)
-        .await
-        .into_iter()
-        .collect::<Result<Vec<_>>>()
-        .map_err(|err| format!("Failed to get audio files to index: {:?}", err))?
-        .into_iter()
-        .flatten()
-        .collect::<Vec<_>>();
-
-    debug!("Indexing {} new files", audio_files_to_index.len());
-    indexing_status
-        .set_indexing_started(audio_files_to_index.len() as u32)
-        .await
-        .map_err(|err| {
-            format!(
-                "Failed to set indexing status to indexing_started: {:?}",
-                err
-            )
-        })?;
-
-    // Break up into batches to improve checkpointing
-    // Each batch will be processed in parallel,
-    // but the batches will be processed sequentially
-    const INDEX_BATCH_SIZE: usize = 10;
-    let audio_file_chunks = audio_files_to_index.chunks(INDEX_BATCH_SIZE);
-    let audio_file_chunks_len = audio_file_chunks.len();
-    let index_future = async move {
-        let mut index_results = vec![];
-        info!("Indexing {} batches sequentially", audio_file_chunks_len);
-        for (batch_i, audio_file_chunk) in audio_file_chunks.enumerate() {
-            debug!("Indexing batch {} of {}", batch_i, audio_file_chunks_len);
-            let pool = pool.clone();
-            let intra_batch_futures = audio_file_chunk
-                .iter()
-                .enumerate()
-                .map(|(file_i, audio_file)| {
-                    debug!(
-                        "Indexing file {} of {} in batch {} of {}",
-                        file_i,
-                        audio_file_chunk.len(),
-                        batch_i,
-                        audio_file_chunks_len
-                    );
-                    index_new_file(pool.clone(), audio_embedder, audio_file)
-                })
-                .collect::<Vec<_>>();
-            index_results.append(
-                // Process intra-batch futures in parallel
-                &mut join_all(intra_batch_futures)
-                    .await
-                    .into_iter()
-                    .collect::<Vec<Result<_>>>(),
-            );
-            info!(
-                "Indexed {} of {} batches",
-                batch_i + 1,
-                audio_file_chunks_len
-            );
-            let mut vector_index_lock = vector_index.write().await;
-            let locked_index = &mut *vector_index_lock;
-
-            match vector_index::synchronize_index(&pool, locked_index)
-                .await {
-                Ok(_) => (),
-                Err(err) => {
-                    warn!(
-                        "Failed to synchronize index after batch {} of {}: {:?}",
-                        batch_i, audio_file_chunks_len, err
-                    );
-                },
+    let preindexed_files = join_all(audio_files_to_index_futures).await;
+
+    run_counts.files_scanned = indexable_files.len() as u32;
+    let mut audio_files_to_index = vec![];
+    let mut copies_to_locate = vec![];
+    let mut hashes_to_index = HashSet::new();
+    for preindexed_file in preindexed_files {
+        let preindexed_file = match preindexed_file {
+            Ok(preindexed_file) => preindexed_file,
+            // Recorded like pipeline failures, so they're reported and retried
+            Err(failure) => {
+                warn!(
+                    "Failed to index {:?} at the {} stage: {:?}",
+                    failure.file_path, failure.stage, failure.error
+                );
+                if let Err(err) = index_error::record_failure(pool, &failure, Utc::now()).await {
+                    warn!("{:?}", err);
+                }
+                run_counts.files_failed += 1;
+                continue;
             }
-            drop(vector_index_lock);
-
-            match indexing_status
-                .increment_n_indexed(audio_file_chunk.len() as u32)
-                .await
-            {
-                Ok(_) => (),
-                Err(err) => {
-                    warn!(
-                        "Failed to increment indexing status for batch {} of {}: {:?}",
-                        batch_i, audio_file_chunks_len, err
-                    );
+        };
+        match preindexed_file {
+            PreindexedFile::New(audio_file) => {
+                // Identical new files are only embedded once
+                if hashes_to_index.insert(audio_file.file_hash.clone()) {
+                    audio_files_to_index.push(audio_file);
+                } else {
+                    copies_to_locate.push(audio_file);
                 }
-            };
+            }
+            PreindexedFile::Moved => run_counts.files_moved += 1,
+            PreindexedFile::Copied
+            | PreindexedFile::Restored
+            | PreindexedFile::Unchanged
+            | PreindexedFile::WaitingToRetry => {}
         }
+    }
+    run_counts.files_new = audio_files_to_index.len() as u32;
 
-        info!("All indexing completed. Stopping audio embedder.");
-        audio_embedder.stop_processing_queue();
-        index_results
-    };
-
-    let embedder_future = audio_embedder.begin_processing_queue();
-    let (embedder_result, upsert_results) = join!(embedder_future, index_future);
+    let scanned_paths = indexable_files.iter().collect::<HashSet<_>>();
+    run_counts.files_removed = remove_missing_locations(pool, &scanned_paths, roots)
+        .await
+        .map_err(|err| format!("Failed to remove missing files: {:?}", err))?;
+    Ok((audio_files_to_index, copies_to_locate))
+}
 
-    embedder_result
-        .context("Model should run successfully")
-        .map_err(|err| format!("Failed to run audio embedder: {:?}", err))?;
-    let upsert_results: (usize, Vec<String>) = upsert_results
-        .into_iter()
-        .map(|res| res.map_err(|err| format!("Failed to update audio index: {:?}", err)))
-        .map(|res| match res {
-            Ok(ok) => Ok(ok),
-            Err(err) => {
-                debug!("{}", err);
-                Err(err)
-            }
+/// Remove the locations of indexed files where the scan didn't find them, and count them.
+/// Their content is kept in the index, since they may turn up again.
+/// Files under roots that weren't scanned, because they're offline or disabled, are kept.
+async fn remove_missing_locations(
+    pool: &SqlitePool,
+    scanned_paths: &HashSet<&PathBuf>,
+    roots: &[IndexRoot],
+) -> Result<u32> {
+    let indexed_paths = sqlx::query_scalar!(r#"SELECT file_path FROM file_location"#)
+        .fetch_all(pool)
+        .await
+        .context("Failed to get indexed paths")?;
+    let missing_paths = indexed_paths
+        .iter()
+        .map(|path| decode_path(path))
+        .filter(|path| !scanned_paths.contains(path))
+        .filter(|path| {
+            find_innermost_root(path, roots)
+                .map(|root| root.options.enabled && root.online)
+                .unwrap_or(true)
         })
-        .fold((0, vec![]), |mut acc, res| {
-            match res {
-                Ok(_) => acc.0 += 1,
-                Err(err) => acc.1.push(err),
-            }
-            acc
-        });
-    debug!(
-        "Indexed {} files total. Success: {}, Failures: {}",
-        upsert_results.0 + upsert_results.1.len(),
-        upsert_results.0,
-        upsert_results.1.len()
-    );
+        .collect::<Vec<_>>();
+    file_location::remove_locations(pool, &missing_paths).await?;
+    Ok(missing_paths.len() as u32)
+}
 
-    debug!("\nAudio file index updated.");
-    indexing_status.set_idle().await.map_err(|err| {
-        format!(
-            "Failed to set indexing status to idle after indexing: {:?}",
-            err
-        )
-    })?;
-    Ok(true)
+/// Files that aren't under any root count as online, so they aren't skipped forever
+fn is_on_online_root(path: &Path, roots: &[IndexRoot]) -> bool {
+    find_innermost_root(path, roots)
+        .map(|root| root.online)
+        .unwrap_or(true)
 }
 
 struct LoadedAudioFile {
     file_hash: String,
-    file_path: String,
+    file_path: PathBuf,
 }
 
-#[derive(sqlx::FromRow)]
-struct AudioFileRow {
-    file_path: String,
+enum PreindexedFile {
+    /// Not in the index yet
+    New(LoadedAudioFile),
+    /// Already indexed under a path that no longer exists, which has been updated
+    Moved,
+    /// Already indexed under another path that still exists, so this one was added
+    Copied,
+    /// Already indexed, but every location it had was gone, so this one was added
+    Restored,
+    Unchanged,
+    /// Failed to index recently, and isn't due to be retried yet
+    WaitingToRetry,
 }
 
-/// Assesses whether a file with the same hash has already been indexed,
-/// and updates the path if the file has moved.
-/// Returns None if the file has already been indexed.
-/// Returns Some(LoadedAudioFile) if the file has not been indexed.
+/// Hashes a file found by the scan, then assesses whether a file with the same hash
+/// has already been indexed, and updates its locations if the file has moved or been copied.
 async fn preindex_files(
     pool: SqlitePool,
     path: PathBuf,
+    roots: &[IndexRoot],
     indexing_status: &IndexingStatus,
-) -> Result<Option<LoadedAudioFile>> {
+) -> result::Result<PreindexedFile, FileFailure> {
     let file_name = match log_enabled!(log::Level::Debug) {
-        true => get_file_name(&path.to_string_lossy().into_owned()),
+        true => get_file_name(&path),
         false => "file".into(),
     };
     debug!("Handling {} ", file_name);
-    let file = File::open(&path)?;
-    debug!("Opened {}", file_name);
-    let audio_file = LoadedAudioFile {
-        file_hash: compute_hash(&file).context("Failed to compute hash")?,
-        file_path: path.to_string_lossy().into_owned(),
+    let file_hash = match hash_file(&path) {
+        Ok(file_hash) => file_hash,
+        Err(error) => {
+            return Err(FileFailure {
+                file_path: path,
+                file_hash: String::new(),
+                stage: PipelineStage::Hash,
+                error,
+            })
+        }
     };
-    // Save some memory :)
-    drop(file);
     debug!("Hashed {}", file_name);
+    let audio_file = LoadedAudioFile {
+        file_hash,
+        file_path: path,
+    };
+    let (file_path, file_hash) = (audio_file.file_path.clone(), audio_file.file_hash.clone());
+    locate_hashed_file(&pool, audio_file, &file_name, roots, indexing_status)
+        .await
+        .map_err(|error| FileFailure {
+            file_path,
+            file_hash,
+            stage: PipelineStage::Preindex,
+            error,
+        })
+}
+
+fn hash_file(path: &Path) -> Result<String> {
+    let file = File::open(path).context("Failed to open file")?;
+    compute_hash(&file).context("Failed to compute hash")
+}
+
+async fn locate_hashed_file(
+    pool: &SqlitePool,
+    audio_file: LoadedAudioFile,
+    file_name: &str,
+    roots: &[IndexRoot],
+    indexing_status: &IndexingStatus,
+) -> Result<PreindexedFile> {
     // Anecdotally, the most useful loading checkpoint is after hashing but before checking the database
     indexing_status
-        .increment_preindexed()
+        .increment_preindexed(&audio_file.file_path)
         .await
         .map_err(|err| {
             anyhow!(
@@ -298,80 +474,95 @@ async fn preindex_files(
             )
         })?;
 
-    let existing_row = sqlx::query_as!(
-        AudioFileRow,
-        r#"SELECT file_path FROM audio_file WHERE file_hash = ?"#,
-        audio_file.file_hash
-    )
-    .fetch_optional(&pool)
-    .await
-    .context(format!(
-        "Failed while finding existing row for {}",
-        get_file_name(&audio_file.file_path)
-    ))?;
-    debug!("Fetched {}", file_name);
-
-    if existing_row.is_none() {
-        debug!("{} is new, indexing...", file_name);
-        return Ok(Some(audio_file));
-    } else if existing_row
-        .as_ref()
-        .is_some_and(|row| row.file_path != audio_file.file_path)
-    {
-        debug!(
-            "{} has moved from {}, updating path...",
-            file_name,
-            existing_row
-                .as_ref()
-                .context("Could not get existing row")?
-                .file_path
-        );
-        update_path(pool, &audio_file).await?;
-    } else {
+    let known_hash = file_location::get_hash_at(pool, &audio_file.file_path).await?;
+    if known_hash.as_ref() == Some(&audio_file.file_hash) {
         debug!(
             "{} already indexed and in the correct path. Doing nothing.",
             file_name
         );
+        return Ok(PreindexedFile::Unchanged);
     }
+    let locations = file_location::get_locations(pool, &audio_file.file_hash)
+        .await
+        .context(format!(
+            "Failed while finding existing locations for {}",
+            get_file_name(&audio_file.file_path)
+        ))?;
+    debug!("Fetched {}", file_name);
 
-    Ok(None)
-}
-
-async fn update_path(pool: SqlitePool, audio_file: &LoadedAudioFile) -> Result<()> {
-    trace!("Updating path for {}...", audio_file.file_path);
-    sqlx::query(r#"UPDATE audio_file SET file_path = ? WHERE file_hash = ?"#)
-        .bind(&audio_file.file_path)
-        .bind(&audio_file.file_hash)
-        .execute(&pool)
-        .await?;
+    if locations.is_empty() {
+        if file_location::add_indexed_location(pool, &audio_file.file_path, &audio_file.file_hash)
+            .await?
+        {
+            debug!("{} turned up again, added location", file_name);
+            return Ok(PreindexedFile::Restored);
+        }
+        if !index_error::is_due_for_retry(
+            pool,
+            &audio_file.file_path,
+            &audio_file.file_hash,
+            Utc::now(),
+        )
+        .await?
+        {
+            debug!("{} failed to index recently, skipping", file_name);
+            return Ok(PreindexedFile::WaitingToRetry);
+        }
+        debug!("{} is new, indexing...", file_name);
+        return Ok(PreindexedFile::New(audio_file));
+    }
 
-    Ok(())
+    // A location that's gone from an online root is where the file moved from.
+    // If this path held other content before, it's a copy that replaced it.
+    if known_hash.is_none() {
+        let vacated_locations = locations
+            .iter()
+            .filter(|location| is_on_online_root(location, roots) && !location.exists());
+        for location in vacated_locations {
+            if file_location::move_location(
+                pool,
+                &audio_file.file_hash,
+                location,
+                &audio_file.file_path,
+            )
+            .await?
+            {
+                debug!("{} has moved from {:?}, updated path", file_name, location);
+                return Ok(PreindexedFile::Moved);
+            }
+        }
+    }
+    debug!(
+        "{} is a copy of {:?}, adding location...",
+        file_name, locations[0]
+    );
+    file_location::add_location(pool, &audio_file.file_path, &audio_file.file_hash).await?;
+    Ok(PreindexedFile::Copied)
 }
 
 #[derive(Debug)]
 struct FileSegment<'a> {
     starting_timestamp: f64,
+    ending_timestamp: f64,
     pcm_audio: &'a [f32],
 }
 
 #[derive(Debug)]
 struct FileSegmentWithEmbedding {
     starting_timestamp: f64,
+    ending_timestamp: f64,
     embedding: Vec<f32>,
 }
 
-async fn index_new_file(
-    pool: SqlitePool,
-    audio_embedder: &AudioEmbedder,
+/// Insert an embedded file and all of its segments in one transaction
+async fn insert_indexed_file(
+    pool: &SqlitePool,
     audio_file: &LoadedAudioFile,
+    duration_seconds: f64,
+    overview: &WaveformOverview,
+    segments_with_embeddings: Vec<FileSegmentWithEmbedding>,
 ) -> Result<()> {
-    // Split file into segments and compute embeddings for each segment
-    // Once all are computed, insert into database
     let file_name = get_file_name(&audio_file.file_path);
-    debug!("Splitting {} into segments", file_name);
-    let segments_with_embeddings = segment_and_embed_file(audio_file, audio_embedder).await?;
-
-    // Insert all segments and audio file into database
     debug!(
         "Inserting {} segments of {} into database...",
         segments_with_embeddings.len(),
@@ -382,9 +573,9 @@ async fn index_new_file(
         get_file_name(&audio_file.file_path)
     ))?;
     sqlx::query!(
-        r#"INSERT INTO audio_file (file_hash, file_path) VALUES (?, ?)"#,
+        r#"INSERT INTO audio_file (file_hash, duration_seconds) VALUES (?, ?)"#,
         audio_file.file_hash,
-        audio_file.file_path
+        duration_seconds
     )
     .execute(&mut *sql_transaction)
     .await
@@ -392,18 +583,42 @@ async fn index_new_file(
         "Failed while inserting embedding for file {}",
         get_file_name(&audio_file.file_path)
     ))?;
+    let encoded_path = encode_path(&audio_file.file_path);
+    sqlx::query!(
+        r#"INSERT OR REPLACE INTO file_location (file_path, file_hash) VALUES (?, ?)"#,
+        encoded_path,
+        audio_file.file_hash
+    )
+    .execute(&mut *sql_transaction)
+    .await
+    .context(format!(
+        "Failed while inserting location of file {}",
+        get_file_name(&audio_file.file_path)
+    ))?;
+    save_overview_in_transaction(&mut sql_transaction, &audio_file.file_hash, overview)
+        .await
+        .context(format!(
+            "Failed while inserting waveform overview of file {}",
+            get_file_name(&audio_file.file_path)
+        ))?;
     for segment in segments_with_embeddings {
         let encoded_embedding: Vec<u8> = encode_embedding(&segment.embedding);
         // Might not be necessary
         let encoded_embedding_slice = encoded_embedding.as_slice();
         sqlx::query!(
-            r#"INSERT INTO audio_file_segment (file_hash, starting_timestamp, embedding) VALUES (?, ?, ?)"#,
+            r#"INSERT INTO audio_file_segment (file_hash, starting_timestamp, ending_timestamp, embedding) VALUES (?, ?, ?, ?)"#,
             audio_file.file_hash,
             segment.starting_timestamp,
+            segment.ending_timestamp,
             encoded_embedding_slice
         )
             .execute(&mut *sql_transaction)
-            .await.expect("Segment insertion should succeed");
+            .await
+            .context(format!(
+                "Failed while inserting segment at {}s of file {}",
+                segment.starting_timestamp,
+                get_file_name(&audio_file.file_path)
+            ))?;
     }
     sql_transaction.commit().await?;
     debug!(
@@ -414,16 +629,24 @@ async fn index_new_file(
     Ok(())
 }
 
+/// A segment's timestamps and model input, ready to be embedded
+struct SegmentFeatures {
+    starting_timestamp: f64,
+    ending_timestamp: f64,
+    features: AudioFeatures,
+}
+
 async fn segment_and_embed_file(
     audio_file: &LoadedAudioFile,
     audio_embedder: &AudioEmbedder,
+    segmentation: SegmentationStrategy,
 ) -> Result<Vec<FileSegmentWithEmbedding>> {
     // Process audio file into embedded segments
     debug!("Preprocessing {}...", get_file_name(&audio_file.file_path));
     let pcm_audio = preprocess_audio_file_to_pcm(audio_file)
         .await
         .context(format!(
-            "Failed to preprocess audio file {}",
+            "Failed to preprocess audio file {:?}",
             audio_file.file_path
         ))?;
     debug!(
@@ -431,68 +654,188 @@ async fn segment_and_embed_file(
         get_file_name(&audio_file.file_path),
         pcm_audio.len()
     );
+    let segment_features =
+        segment_and_extract_features(audio_file, &pcm_audio, audio_embedder.model, segmentation)?;
+    embed_segment_features(audio_file, segment_features, audio_embedder).await
+}
+
+/// Embed a file that doesn't need to be in the index, to search for sounds like it.
+/// Its segments are averaged into one embedding.
+pub(crate) async fn embed_query_file(
+    path: &Path,
+    audio_embedder: &AudioEmbedder,
+    segmentation: SegmentationStrategy,
+) -> Result<Vec<f32>> {
+    let audio_file = LoadedAudioFile {
+        file_hash: String::new(),
+        file_path: path.to_owned(),
+    };
+    let pcm_audio = preprocess_audio_file_to_pcm(&audio_file)
+        .await
+        .context(format!("Failed to preprocess audio file {:?}", path))?;
+    let segment_features =
+        segment_and_extract_features(&audio_file, &pcm_audio, audio_embedder.model, segmentation)?
+            .into_iter()
+            .map(|segment| segment.features)
+            .collect::<Vec<_>>();
+    let embeddings = audio_embedder
+        .embed_now(&segment_features)
+        .await
+        .context(format!("Failed to embed {:?}", path))?
+        .into_iter()
+        .map(|embedding| embedding.to_vec())
+        .collect::<Vec<_>>();
+    mean_embedding(&embeddings).context(format!("{:?} has no audio to search with", path))
+}
+
+/// Split a file's audio into segments and compute the model input for each
+fn segment_and_extract_features(
+    audio_file: &LoadedAudioFile,
+    pcm_audio: &[f32],
+    model: ClapModel,
+    segmentation: SegmentationStrategy,
+) -> Result<Vec<SegmentFeatures>> {
     debug!(
         "Splitting {} into segments...",
         get_file_name(&audio_file.file_path)
     );
-    let audio_segments = split_audio_into_segments(&pcm_audio);
-    let num_audio_segments = audio_segments.len();
+    let (segment_length, segment_step) = segment_window(model);
+    let audio_segments = match segmentation {
+        SegmentationStrategy::FixedWindow => {
+            split_audio_into_segments(pcm_audio, segment_length, segment_step)
+        }
+        SegmentationStrategy::EventAligned => {
+            split_audio_into_event_segments(pcm_audio, segment_length, segment_step)
+        }
+    };
     debug!(
         "Split {} into {} segments with lengths {:?}",
         get_file_name(&audio_file.file_path),
-        num_audio_segments,
+        audio_segments.len(),
         audio_segments
             .iter()
             .map(|segment| segment.pcm_audio.len())
             .collect::<Vec<usize>>()
     );
-    let segments_with_embeddings: Result<Vec<_>> = Result::from_iter(
-        join_all(
-            audio_segments
-                .into_iter()
-                .enumerate()
-                .map(|(i, segment)| async move {
-                    debug!(
-                        "Computing embedding for segment {}/{} of {}...",
+    // Compute all mel specs for the file up front, reusing the FFT buffers
+    let mel_specs = clap_feature_extractor(model)
+        .extract_batch(
+            &audio_segments
+                .iter()
+                .map(|segment| segment.pcm_audio)
+                .collect::<Vec<_>>(),
+        )
+        .context(format!(
+            "Failed to compute mel spectrograms for {}",
+            get_file_name(&audio_file.file_path)
+        ))?;
+
+    Ok(audio_segments
+        .into_iter()
+        .zip(mel_specs)
+        .map(|(segment, features)| SegmentFeatures {
+            starting_timestamp: segment.starting_timestamp,
+            ending_timestamp: segment.ending_timestamp,
+            features,
+        })
+        .collect())
+}
+
+/// Embed all of a file's segments concurrently, so they can share batches
+async fn embed_segment_features(
+    audio_file: &LoadedAudioFile,
+    segment_features: Vec<SegmentFeatures>,
+    audio_embedder: &AudioEmbedder,
+) -> Result<Vec<FileSegmentWithEmbedding>> {
+    let num_audio_segments = segment_features.len();
+    let segment_results = join_all(segment_features.into_iter().enumerate().map(
+        |(i, segment)| async move {
+            debug!(
+                "Computing embedding for segment {}/{} of {}...",
+                i,
+                num_audio_segments,
+                get_file_name(&audio_file.file_path)
+            );
+            let segment_embedding =
+                compute_embedding_from_mel_spec(segment.features, audio_embedder)
+                    .await
+                    .context(format!(
+                        "Failed to compute embedding for segment {} of {}",
                         i,
-                        num_audio_segments,
                         get_file_name(&audio_file.file_path)
-                    );
-                    let segment_embedding =
-                        compute_embedding_from_pcm(segment.pcm_audio, audio_embedder)
-                            .await
-                            .context("Failed to compute embedding for segment {} of {}")?;
-                    Ok(FileSegmentWithEmbedding {
-                        starting_timestamp: segment.starting_timestamp,
-                        embedding: segment_embedding,
-                    })
-                }),
-        )
-        .await,
-    );
+                    ))?;
+            Ok(FileSegmentWithEmbedding {
+                starting_timestamp: segment.starting_timestamp,
+                ending_timestamp: segment.ending_timestamp,
+                embedding: segment_embedding,
+            })
+        },
+    ))
+    .await;
+
+    // Every segment has been attempted, so report all failures rather than just the first
+    let (segments_with_embeddings, segment_errors): (Vec<_>, Vec<_>) =
+        segment_results.into_iter().partition(Result::is_ok);
+    if !segment_errors.is_empty() {
+        let segment_errors = segment_errors
+            .into_iter()
+            .filter_map(Result::err)
+            .collect::<Vec<anyhow::Error>>();
+        for err in &segment_errors {
+            warn!("{:?}", err);
+        }
+        return Err(anyhow!(
+            "{} of {} segments of {} failed to embed. First error: {:?}",
+            segment_errors.len(),
+            num_audio_segments,
+            get_file_name(&audio_file.file_path),
+            segment_errors[0]
+        ));
+    }
 
-    segments_with_embeddings
+    segments_with_embeddings.into_iter().collect()
 }
 
 const TARGET_SAMPLE_RATE: u32 = 48000;
 const SEGMENT_LENGTH: f32 = 10.0; // seconds
 const SEGMENT_STEP: f32 = 5.0; // seconds
+/// Fused models see the whole segment, so long sounds can be embedded in one piece
+const FUSED_SEGMENT_LENGTH: f32 = 30.0; // seconds
+const FUSED_SEGMENT_STEP: f32 = 15.0; // seconds
+
+/// Segment length and step for the given model, in seconds
+fn segment_window(model: ClapModel) -> (f32, f32) {
+    match model {
+        ClapModel::HtsatUnfused => (SEGMENT_LENGTH, SEGMENT_STEP),
+        ClapModel::HtsatFused => (FUSED_SEGMENT_LENGTH, FUSED_SEGMENT_STEP),
+    }
+}
 
-fn get_file_name(path: &String) -> String {
-    let path = Path::new(path);
+/// For logs and the UI only, since invalid UTF-8 is replaced
+fn get_file_name(path: &Path) -> String {
     path.file_name()
-        .and_then(|file_name| file_name.to_str())
-        .map(|file_name| file_name.to_string())
-        .expect("Should get file name")
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| display_path(path))
 }
 
 /// Process an audio file into an f32 PCM vector with a sample rate of 48kHz
 async fn preprocess_audio_file_to_pcm(audio_file: &LoadedAudioFile) -> Result<Vec<f32>> {
+    let decoded_audio = decode_audio_file(audio_file)?;
+    resample_to_target_rate(audio_file, decoded_audio)
+}
+
+/// A decoded file, mixed down to mono but at its original sample rate
+struct DecodedAudio {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+fn decode_audio_file(audio_file: &LoadedAudioFile) -> Result<DecodedAudio> {
     let file_ext = audio_file
         .file_path
-        .split('.')
-        .last()
+        .extension()
         .context("Failed to get file extension")?
+        .to_string_lossy()
         .to_lowercase();
     let file_ext = file_ext.as_str();
 
@@ -504,28 +847,17 @@ async fn preprocess_audio_file_to_pcm(audio_file: &LoadedAudioFile) -> Result<Ve
                 // and using that here for I/O gains.
                 WavReader::open(&audio_file.file_path).context("Failed to read .wav file")?;
             let wav_spec = wav_reader.spec();
-            let initial_seconds = wav_reader.duration() as f32 / wav_spec.sample_rate as f32;
             debug!(
                 "Before preprocessing, {} has a sample rate of {} and a length of {} samples, for a duration of {} seconds",
                 get_file_name(&audio_file.file_path),
                 wav_spec.sample_rate,
                 wav_reader.duration(),
-                initial_seconds
+                wav_reader.duration() as f32 / wav_spec.sample_rate as f32
             );
-            let mut wav_samples: Vec<f32> = match wav_spec.sample_format {
-                SampleFormat::Float => wav_reader
-                    .into_samples::<f32>()
-                    .map(|sample| sample.context("Failed to read .wav sample"))
-                    .collect::<Result<Vec<_>>>()?,
-                SampleFormat::Int => wav_reader
-                    .into_samples::<i32>()
-                    .map(|sample| {
-                        let sample = sample.context("Failed to read .wav sample")?;
-                        // Normalize to f32
-                        Ok(sample as f32 / i32::MAX as f32)
-                    })
-                    .collect::<Result<Vec<_>>>()?,
-            };
+            let mut wav_samples = read_wav_samples(wav_reader).context(format!(
+                "Failed to read samples from {}",
+                get_file_name(&audio_file.file_path)
+            ))?;
 
             if wav_spec.channels != 1 {
                 // Sum to mono
@@ -534,31 +866,71 @@ async fn preprocess_audio_file_to_pcm(audio_file: &LoadedAudioFile) -> Result<Ve
                     .map(|chunk| chunk.iter().sum::<f32>() / wav_spec.channels as f32)
                     .collect();
             }
-            if wav_spec.sample_rate != TARGET_SAMPLE_RATE {
-                wav_samples = resample(wav_samples.as_ref(), wav_spec.sample_rate)?;
-            }
-            let final_seconds = wav_samples.len() as f32 / TARGET_SAMPLE_RATE as f32;
-            debug!(
-                "Resampled {} to {} samples, for a duration of {} seconds",
-                get_file_name(&audio_file.file_path),
-                wav_samples.len(),
-                final_seconds
-            );
-            if (final_seconds - initial_seconds).abs() > 0.1 {
-                return Err(anyhow::anyhow!(
-                    "Resampled audio file {} has a duration of {} seconds, but should have a duration of {} seconds",
-                    get_file_name(&audio_file.file_path),
-                    final_seconds,
-                    initial_seconds
-                ));
-            }
-            Ok(wav_samples)
+            Ok(DecodedAudio {
+                samples: wav_samples,
+                sample_rate: wav_spec.sample_rate,
+            })
         }
-        _ => Err(anyhow::anyhow!(
-            "Unsupported file extension: {} for file {}",
-            file_ext,
-            audio_file.file_path
-        )),
+        _ => Err(UnsupportedFormatError(format!(
+            "Unsupported file extension: {} for file {:?}",
+            file_ext, audio_file.file_path
+        ))
+        .into()),
+    }
+}
+
+fn resample_to_target_rate(
+    audio_file: &LoadedAudioFile,
+    decoded_audio: DecodedAudio,
+) -> Result<Vec<f32>> {
+    let initial_seconds = decoded_audio.samples.len() as f32 / decoded_audio.sample_rate as f32;
+    let mut samples = decoded_audio.samples;
+    if decoded_audio.sample_rate != TARGET_SAMPLE_RATE {
+        samples = resample(samples.as_ref(), decoded_audio.sample_rate)?;
+    }
+    let final_seconds = samples.len() as f32 / TARGET_SAMPLE_RATE as f32;
+    debug!(
+        "Resampled {} to {} samples, for a duration of {} seconds",
+        get_file_name(&audio_file.file_path),
+        samples.len(),
+        final_seconds
+    );
+    if (final_seconds - initial_seconds).abs() > 0.1 {
+        return Err(anyhow::anyhow!(
+            "Resampled audio file {} has a duration of {} seconds, but should have a duration of {} seconds",
+            get_file_name(&audio_file.file_path),
+            final_seconds,
+            initial_seconds
+        ));
+    }
+    Ok(samples)
+}
+
+/// Read all samples from a .wav file, normalized to [-1.0, 1.0).
+/// hound returns integer samples at their native magnitude (e.g. ±32768 for 16-bit),
+/// so they are scaled by the full-scale value of the file's bit depth.
+fn read_wav_samples<R: Read>(wav_reader: WavReader<R>) -> Result<Vec<f32>> {
+    let wav_spec = wav_reader.spec();
+    match (wav_spec.sample_format, wav_spec.bits_per_sample) {
+        (SampleFormat::Float, 32) => wav_reader
+            .into_samples::<f32>()
+            .map(|sample| sample.context("Failed to read .wav sample"))
+            .collect::<Result<Vec<_>>>(),
+        (SampleFormat::Int, bits_per_sample @ (8 | 16 | 24 | 32)) => {
+            let full_scale = (1_i64 << (bits_per_sample - 1)) as f32;
+            wav_reader
+                .into_samples::<i32>()
+                .map(|sample| {
+                    let sample = sample.context("Failed to read .wav sample")?;
+                    Ok(sample as f32 / full_scale)
+                })
+                .collect::<Result<Vec<_>>>()
+        }
+        (sample_format, bits_per_sample) => Err(UnsupportedFormatError(format!(
+            "Unsupported .wav format: {:?} samples with {} bits per sample",
+            sample_format, bits_per_sample
+        ))
+        .into()),
     }
 }
 
@@ -628,9 +1000,13 @@ fn resample(samples: &[f32], source_sample_rate: u32) -> Result<Vec<f32>> {
     Ok(resampled_samples)
 }
 
-fn split_audio_into_segments(pcm_audio: &[f32]) -> Vec<FileSegment> {
-    let segment_length_samples = (TARGET_SAMPLE_RATE as f32 * SEGMENT_LENGTH) as usize;
-    let segment_step_samples = (TARGET_SAMPLE_RATE as f32 * SEGMENT_STEP) as usize;
+fn split_audio_into_segments(
+    pcm_audio: &[f32],
+    segment_length: f32,
+    segment_step: f32,
+) -> Vec<FileSegment> {
+    let segment_length_samples = (TARGET_SAMPLE_RATE as f32 * segment_length) as usize;
+    let segment_step_samples = (TARGET_SAMPLE_RATE as f32 * segment_step) as usize;
 
     let mut segments = vec![];
     let mut current_sample = 0;
@@ -638,6 +1014,7 @@ fn split_audio_into_segments(pcm_audio: &[f32]) -> Vec<FileSegment> {
         let final_sample = cmp::min(current_sample + segment_length_samples, pcm_audio.len() - 1);
         let segment = FileSegment {
             starting_timestamp: current_sample as f64 / TARGET_SAMPLE_RATE as f64,
+            ending_timestamp: final_sample as f64 / TARGET_SAMPLE_RATE as f64,
             pcm_audio: &pcm_audio[current_sample..final_sample],
         };
         segments.push(segment);
@@ -647,6 +1024,7 @@ fn split_audio_into_segments(pcm_audio: &[f32]) -> Vec<FileSegment> {
     let segments = match segments.len() {
         0 => vec![FileSegment {
             starting_timestamp: 0.0,
+            ending_timestamp: pcm_audio.len() as f64 / TARGET_SAMPLE_RATE as f64,
             pcm_audio,
         }],
         _ => segments,
@@ -655,113 +1033,21 @@ fn split_audio_into_segments(pcm_audio: &[f32]) -> Vec<FileSegment> {
     segments
 }
 
-struct MelConfigSettings {
-    fft_size: usize,
-    sample_rate: f64,
-    hop_size: usize,
-    n_mels: usize,
-    _power: f64,
-}
-
-/// Config based on `clap_export.ipynb` experiments
-const MEL_CONFIG: MelConfigSettings = MelConfigSettings {
-    fft_size: 1024,
-    sample_rate: TARGET_SAMPLE_RATE as f64,
-    hop_size: 480,
-    n_mels: 64,
-    _power: 2.0,
-};
-
-const TARGET_LENGTH: usize = 1001;
-/// Repeat-pad mel spectrogram to have a length of TARGET_LENGTH (1001)
-fn reshape_mel_spec(mel_spec: Array2<f64>) -> Result<Array3<f64>> {
-    debug!("Reshaping mel_spec of shape {:?}", mel_spec.shape());
-    // [n_mels, n_frames] -> [n_frames, n_mels]
-    let transposed_mel_spec = mel_spec.t().to_owned();
-    if transposed_mel_spec.len_of(Axis(0)) == TARGET_LENGTH {
-        return Ok(transposed_mel_spec.insert_axis(Axis(0)));
-    } else if transposed_mel_spec.len_of(Axis(0)) == 0 {
-        return Err(anyhow::anyhow!("Mel spectrogram is empty"));
-    }
-
-    let mut result: Array2<f64> = transposed_mel_spec.clone();
-    while result.len_of(Axis(0)) < TARGET_LENGTH {
-        let result_len = result.len_of(Axis(0));
-        let transposed_mel_spec_len = transposed_mel_spec.len_of(Axis(0));
-        let frames_remaining = TARGET_LENGTH - result_len;
-        let slice_end = match frames_remaining > transposed_mel_spec_len {
-            true => transposed_mel_spec_len,
-            false => frames_remaining,
-        };
-
-        let view_to_add = transposed_mel_spec.slice_axis(
-            Axis(0),
-            ndarray::Slice {
-                start: (0),
-                end: (Some(slice_end.try_into().context(format!(
-                    "slice_end should always be positive, was {}",
-                    slice_end
-                ))?)),
-                step: (1),
-            },
-        );
-        debug!(
-            "Adding view of shape {:?} to result of shape {:?}",
-            view_to_add.shape(),
-            result.shape()
-        );
-        result = concatenate![Axis(0), result, view_to_add];
-    }
-
-    Ok(result.insert_axis(Axis(0)))
-}
-
-fn compute_mel_spec_from_pcm(segment_pcm: &[f32]) -> Result<Array3<f64>> {
+fn compute_mel_spec_from_pcm(segment_pcm: &[f32], model: ClapModel) -> Result<AudioFeatures> {
     debug!(
         "Computing mel spectrogram for pcm of length {}",
         segment_pcm.len()
     );
-    // Compute mel spectrogram
-    let mel_config = MelConfig::new(
-        MEL_CONFIG.fft_size,
-        MEL_CONFIG.hop_size,
-        MEL_CONFIG.n_mels,
-        MEL_CONFIG.sample_rate,
-    );
-    // TODO: make sure this doesn't have weird Voice Activity Detection side effects
-    let pipeline_config = PipelineConfig::new(mel_config, None);
-    let mut pipeline = Pipeline::new(pipeline_config);
-
-    let pipeline_join_handles = pipeline.start();
-    let rx_clone = pipeline.rx();
-    pipeline.send_pcm(segment_pcm)?;
-    pipeline.close_ingress();
-
-    let mut mel_spec: Array2<f64> = Array2::zeros((MEL_CONFIG.n_mels, 0));
-    while let Ok((mel_idx, mel_spec_chunk)) = rx_clone.recv() {
-        trace!("\rReceived mel spectrogram chunk {:?}", mel_idx);
-        mel_spec
-            .append(Axis(1), mel_spec_chunk.view())
-            .context(format!(
-                "Failed to append mel spectrogram chunk of shape {:?} to mel_spec of shape {:?}",
-                mel_spec_chunk.shape(),
-                mel_spec.shape()
-            ))?;
-    }
-
-    for handle in pipeline_join_handles {
-        handle.join().expect("Pipeline should join");
-    }
-
-    let reshaped_mel_spec = reshape_mel_spec(mel_spec).context("Failed to reshape mel spec")?;
-    Ok(reshaped_mel_spec)
+    clap_feature_extractor(model)
+        .extract(segment_pcm)
+        .context("Failed to compute mel spectrogram")
 }
 
 async fn compute_embedding_from_pcm(
     segment_pcm: &[f32],
     audio_embedder: &AudioEmbedder,
 ) -> Result<Vec<f32>> {
-    let mel_spec = compute_mel_spec_from_pcm(segment_pcm)?;
+    let mel_spec = compute_mel_spec_from_pcm(segment_pcm, audio_embedder.model)?;
     // Compute embedding
     let embedding = compute_embedding_from_mel_spec(mel_spec, audio_embedder).await?;
 
@@ -769,16 +1055,14 @@ async fn compute_embedding_from_pcm(
 }
 
 async fn compute_embedding_from_mel_spec(
-    mel_spec: Array3<f64>,
+    mel_spec: AudioFeatures,
     audio_embedder: &AudioEmbedder,
 ) -> Result<Vec<f32>> {
     debug!(
         "Computing embedding for mel_spec of shape {:?}",
-        mel_spec.shape()
+        mel_spec.input_features.shape()
     );
-    let embedding = audio_embedder
-        .queue_for_batch_processing(mel_spec.to_owned())
-        .await?;
+    let embedding = audio_embedder.queue_for_batch_processing(mel_spec).await?;
 
     Ok(embedding.to_vec())
 }
@@ -787,6 +1071,7 @@ async fn compute_embedding_from_mel_spec(
 mod tests {
     use super::*;
 
+    use ndarray::Array3;
     use ort::{Environment, GraphOptimizationLevel, SessionBuilder};
     use std::sync::Arc;
 
@@ -824,7 +1109,7 @@ mod tests {
     fn test_compute_mel_spec_from_pcm_with_zeros() {
         // 10 seconds of 48kHz silence
         let test_segment_pcm = vec![0.0; 48000 * 10];
-        let result = compute_mel_spec_from_pcm(&test_segment_pcm);
+        let result = compute_mel_spec_from_pcm(&test_segment_pcm, ClapModel::HtsatUnfused);
         assert!(result.is_ok());
     }
 
@@ -832,12 +1117,58 @@ mod tests {
     fn test_compute_mel_spec_from_pcm_with_no_length() {
         // 0 seconds of 48kHz silence
         let test_segment_pcm = vec![0.0; 0];
-        let result = compute_mel_spec_from_pcm(&test_segment_pcm);
+        let result = compute_mel_spec_from_pcm(&test_segment_pcm, ClapModel::HtsatUnfused);
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_wav_bit_depths_produce_equivalent_mel_specs() {
+        // The same signal (440Hz + 3kHz sines) stored at each supported bit depth.
+        // Only bins within `dynamic_range` of the peak (in dB) are compared, since
+        // quantization distortion differs between bit depths. It is significant at 8-bit.
+        let reference_mel_spec = compute_mel_spec_for_fixture("sine_32bit_float.wav").await;
+        let reference_max = reference_mel_spec.fold(f64::NEG_INFINITY, |acc, x| acc.max(*x));
+        for (filename, dynamic_range, tolerance) in [
+            ("sine_8bit_int.wav", 10.0, 0.5),
+            ("sine_16bit_int.wav", 40.0, 0.1),
+            ("sine_24bit_int.wav", 40.0, 0.1),
+            ("sine_32bit_int.wav", 40.0, 0.1),
+        ] {
+            let mel_spec = compute_mel_spec_for_fixture(filename).await;
+            assert_eq!(mel_spec.shape(), reference_mel_spec.shape());
+            let max_difference = mel_spec
+                .iter()
+                .zip(reference_mel_spec.iter())
+                .filter(|(_, reference)| **reference > reference_max - dynamic_range)
+                .map(|(value, reference)| (value - reference).abs())
+                .fold(0.0, f64::max);
+            assert!(
+                max_difference < tolerance,
+                "{} differs from the float reference by {}dB",
+                filename,
+                max_difference
+            );
+        }
+    }
+
+    async fn compute_mel_spec_for_fixture(filename: &str) -> Array3<f64> {
+        let audio_file = LoadedAudioFile {
+            file_hash: "fake_hash".to_string(),
+            file_path: PathBuf::from(
+                get_local_path(("test_resources/audio/bit_depths/".to_owned() + filename).as_str())
+                    .expect("Should get local path"),
+            ),
+        };
+        let pcm_audio = preprocess_audio_file_to_pcm(&audio_file)
+            .await
+            .expect("Fixture should be decoded");
+        compute_mel_spec_from_pcm(&pcm_audio, ClapModel::HtsatUnfused)
+            .expect("Mel spectrogram should be computed")
+            .input_features
+    }
+
     /// Get local path for testing, based on CARGO_MANIFEST_DIR env var
-    fn get_local_path(path: &str) -> Result<String> {
+    pub(super) fn get_local_path(path: &str) -> Result<String> {
         let mut local_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         local_path.push(path);
         let local_path_string = local_path
@@ -847,10 +1178,13 @@ mod tests {
         Ok(local_path_string)
     }
 
-    fn create_local_audio_embedder() -> AudioEmbedder {
-        let audio_embedder_model_path =
-            get_local_path("onnx_models/clap-htsat-unfused_audio_with_projection.onnx")
-                .expect("Should get local path");
+    pub(super) fn create_local_audio_embedder() -> AudioEmbedder {
+        let model = ClapModel::HtsatUnfused;
+        let audio_embedder_model_path = get_local_path(&format!(
+            "onnx_models/{}_audio_with_projection.onnx",
+            model.model_name()
+        ))
+        .expect("Should get local path");
         let environment = Environment::builder()
             .with_name("CLAP")
             .build()
@@ -867,7 +1201,72 @@ mod tests {
                     audio_embedder_model_path
                 )
             });
-        AudioEmbedder::new(audio_embedder_session)
+        AudioEmbedder::new(audio_embedder_session, model)
+    }
+
+    #[tokio::test]
+    async fn test_missing_locations_are_removed_until_found_again() {
+        use crate::index_paths::RootOptions;
+
+        let pool = crate::state::database::create_test_pool().await;
+        let root = |path: &str, enabled: bool, online: bool| IndexRoot {
+            path: PathBuf::from(path),
+            options: RootOptions {
+                enabled,
+                ..Default::default()
+            },
+            parent: None,
+            online,
+            last_seen_at: None,
+        };
+        let roots = [
+            root("/Scanned", true, true),
+            root("/Offline", true, false),
+            root("/Disabled", false, true),
+        ];
+        let indexed_paths = [
+            "/Scanned/kept.wav",
+            "/Scanned/gone.wav",
+            "/Offline/steps.wav",
+            "/Disabled/door.wav",
+            "/Elsewhere/gone.wav",
+        ]
+        .map(PathBuf::from);
+        sqlx::query!("INSERT INTO audio_file (file_hash) VALUES ('hash')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        for path in &indexed_paths {
+            file_location::add_location(&pool, path, "hash")
+                .await
+                .unwrap();
+        }
+
+        let scanned_paths = HashSet::from([&indexed_paths[0]]);
+        let removed = remove_missing_locations(&pool, &scanned_paths, &roots)
+            .await
+            .unwrap();
+        assert_eq!(removed, 2);
+        assert_eq!(
+            file_location::get_locations(&pool, "hash").await.unwrap(),
+            vec![
+                PathBuf::from("/Disabled/door.wav"),
+                PathBuf::from("/Offline/steps.wav"),
+                PathBuf::from("/Scanned/kept.wav"),
+            ]
+        );
+
+        // The content stays indexed, so a file that turns up again only needs its location back
+        let restored = file_location::add_indexed_location(&pool, &indexed_paths[1], "hash")
+            .await
+            .unwrap();
+        assert!(restored);
+        assert_eq!(
+            file_location::get_hash_at(&pool, &indexed_paths[1])
+                .await
+                .unwrap(),
+            Some("hash".to_string())
+        );
     }
 
     /// Generic testing process. Takes a list of filenames expected to be present in the test_resources/audio directory.
@@ -877,10 +1276,10 @@ mod tests {
             .map(|filename| {
                 Arc::new(LoadedAudioFile {
                     file_hash: "fake_hash".to_string(),
-                    file_path: get_local_path(
-                        ("test_resources/audio/".to_owned() + filename).as_str(),
-                    )
-                    .expect("Should get local path"),
+                    file_path: PathBuf::from(
+                        get_local_path(("test_resources/audio/".to_owned() + filename).as_str())
+                            .expect("Should get local path"),
+                    ),
                 })
             })
             .collect();
@@ -902,7 +1301,12 @@ mod tests {
                 let cloned_audio_file = test_audio_file.clone();
                 tokio::spawn({
                     async move {
-                        segment_and_embed_file(&cloned_audio_file, &cloned_audio_embedder).await
+                        segment_and_embed_file(
+                            &cloned_audio_file,
+                            &cloned_audio_embedder,
+                            SegmentationStrategy::default(),
+                        )
+                        .await
                     }
                 })
             })
@@ -0,0 +1,621 @@
+//! A synchronous log-mel frontend that reproduces the HF `ClapFeatureExtractor`.
+//! See `transformers.audio_utils` for the reference implementation of the
+//! filterbank, window and spectrogram.
+
+use std::borrow::Cow;
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{anyhow, Result};
+use ndarray::{stack, Array2, Array3, Axis};
+use rand::Rng;
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+
+use super::TARGET_SAMPLE_RATE;
+use crate::clap::ClapModel;
+
+#[derive(Clone, Copy, Debug)]
+pub enum MelScale {
+    Htk,
+    Slaney,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum FilterbankNorm {
+    None,
+    /// Scale each filter by 2 / (its bandwidth in Hz), so they have equal area
+    Slaney,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum WindowFunction {
+    /// Periodic Hann window, as in `window_function(n, "hann")`
+    Hann,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LogScaling {
+    /// Power in dB relative to `reference`, floored at `min_value`,
+    /// and optionally limited to `db_range` below the maximum
+    Decibels {
+        reference: f64,
+        min_value: f64,
+        db_range: Option<f64>,
+    },
+}
+
+/// How clips shorter than `max_length` are extended
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum Padding {
+    /// Tile the clip as many whole times as fit, then zero-pad the rest
+    RepeatPad,
+    /// Tile the clip and cut it off at `max_length`
+    Repeat,
+    /// Zero-pad
+    Pad,
+}
+
+/// How clips longer than `max_length` are shortened
+#[derive(Clone, Copy, Debug)]
+pub enum Truncation {
+    /// Crop a random `max_length` window
+    RandTrunc,
+    /// Stack a downsampled view of the whole clip with three random `max_length`
+    /// crops from its front, middle and back thirds. Shorter clips are padded
+    /// and repeated across all four channels.
+    Fusion,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FeatureExtractorConfig {
+    pub sample_rate: u32,
+    pub fft_size: usize,
+    pub hop_size: usize,
+    pub n_mels: usize,
+    pub f_min: f64,
+    pub f_max: f64,
+    pub mel_scale: MelScale,
+    pub filterbank_norm: FilterbankNorm,
+    pub window: WindowFunction,
+    /// Lower bound applied to the mel power before log scaling
+    pub mel_floor: f64,
+    pub log_scaling: LogScaling,
+    /// Clip length in samples. Clips are padded or truncated to this.
+    pub max_length: usize,
+    pub padding: Padding,
+    pub truncation: Truncation,
+}
+
+/// The preprocessor config of `laion/clap-htsat-unfused`
+pub const CLAP_HTSAT_UNFUSED_CONFIG: FeatureExtractorConfig = FeatureExtractorConfig {
+    sample_rate: TARGET_SAMPLE_RATE,
+    fft_size: 1024,
+    hop_size: 480,
+    n_mels: 64,
+    f_min: 0.0,
+    f_max: 14_000.0,
+    mel_scale: MelScale::Slaney,
+    filterbank_norm: FilterbankNorm::Slaney,
+    window: WindowFunction::Hann,
+    mel_floor: 1e-10,
+    log_scaling: LogScaling::Decibels {
+        reference: 1.0,
+        min_value: 1e-10,
+        db_range: None,
+    },
+    max_length: TARGET_SAMPLE_RATE as usize * 10,
+    padding: Padding::RepeatPad,
+    truncation: Truncation::RandTrunc,
+};
+
+/// The preprocessor config of `laion/clap-htsat-fused`
+pub const CLAP_HTSAT_FUSED_CONFIG: FeatureExtractorConfig = FeatureExtractorConfig {
+    mel_scale: MelScale::Htk,
+    filterbank_norm: FilterbankNorm::None,
+    truncation: Truncation::Fusion,
+    ..CLAP_HTSAT_UNFUSED_CONFIG
+};
+
+/// Feature extractor for the given CLAP audio model, shared between all indexing tasks
+pub fn clap_feature_extractor(model: ClapModel) -> &'static FeatureExtractor {
+    static UNFUSED_FEATURE_EXTRACTOR: OnceLock<FeatureExtractor> = OnceLock::new();
+    static FUSED_FEATURE_EXTRACTOR: OnceLock<FeatureExtractor> = OnceLock::new();
+    match model {
+        ClapModel::HtsatUnfused => UNFUSED_FEATURE_EXTRACTOR
+            .get_or_init(|| FeatureExtractor::new(CLAP_HTSAT_UNFUSED_CONFIG)),
+        ClapModel::HtsatFused => {
+            FUSED_FEATURE_EXTRACTOR.get_or_init(|| FeatureExtractor::new(CLAP_HTSAT_FUSED_CONFIG))
+        }
+    }
+}
+
+/// The audio model input for one clip
+#[derive(Debug)]
+pub struct AudioFeatures {
+    /// [n_channels, n_frames, n_mels]. There are four channels for fusion, otherwise one.
+    pub input_features: Array3<f64>,
+    /// Whether the clip was long enough to be fused from several views.
+    /// Only fused models take this as an input.
+    pub is_longer: bool,
+}
+
+impl AudioFeatures {
+    pub fn size_in_bytes(&self) -> usize {
+        self.input_features.len() * std::mem::size_of::<f64>()
+    }
+}
+
+/// Computes log-mel spectrograms. The window, filterbank and FFT plan are
+/// computed once and reused for every clip.
+pub struct FeatureExtractor {
+    config: FeatureExtractorConfig,
+    window: Vec<f64>,
+    /// [n_mels, n_frequency_bins]
+    mel_filters: Array2<f64>,
+    fft: Arc<dyn RealToComplex<f64>>,
+}
+
+/// Buffers reused between frames and clips
+struct FftBuffers {
+    input: Vec<f64>,
+    output: Vec<Complex<f64>>,
+    scratch: Vec<Complex<f64>>,
+    power: Vec<f64>,
+}
+
+impl FeatureExtractor {
+    pub fn new(config: FeatureExtractorConfig) -> Self {
+        let fft = RealFftPlanner::<f64>::new().plan_fft_forward(config.fft_size);
+        Self {
+            window: make_window(config.window, config.fft_size),
+            mel_filters: mel_filter_bank(
+                config.fft_size / 2 + 1,
+                config.n_mels,
+                config.f_min,
+                config.f_max,
+                config.sample_rate,
+                config.filterbank_norm,
+                config.mel_scale,
+            ),
+            fft,
+            config,
+        }
+    }
+
+    /// Compute the model input for a single clip
+    pub fn extract(&self, pcm_audio: &[f32]) -> Result<AudioFeatures> {
+        let mut buffers = self.make_buffers();
+        self.extract_with_buffers(pcm_audio, &mut buffers, &mut rand::thread_rng())
+    }
+
+    /// Compute the model inputs for several clips (e.g. all segments of a file),
+    /// reusing the same buffers throughout.
+    pub fn extract_batch(&self, pcm_audio_clips: &[&[f32]]) -> Result<Vec<AudioFeatures>> {
+        let mut buffers = self.make_buffers();
+        let mut rng = rand::thread_rng();
+        pcm_audio_clips
+            .iter()
+            .map(|pcm_audio| self.extract_with_buffers(pcm_audio, &mut buffers, &mut rng))
+            .collect()
+    }
+
+    fn make_buffers(&self) -> FftBuffers {
+        FftBuffers {
+            input: self.fft.make_input_vec(),
+            output: self.fft.make_output_vec(),
+            scratch: self.fft.make_scratch_vec(),
+            power: vec![0.0; self.fft.complex_len()],
+        }
+    }
+
+    fn extract_with_buffers(
+        &self,
+        pcm_audio: &[f32],
+        buffers: &mut FftBuffers,
+        rng: &mut impl Rng,
+    ) -> Result<AudioFeatures> {
+        if pcm_audio.is_empty() {
+            return Err(anyhow!("Cannot compute features for empty audio"));
+        }
+
+        let log_mel_spec = match self.config.truncation {
+            // HF's fusion doesn't truncate long clips, it computes the whole spectrogram
+            Truncation::Fusion if pcm_audio.len() > self.config.max_length => {
+                let log_mel_spec = self.log_mel_spectrogram(pcm_audio, buffers)?;
+                let chunk_frames = self.config.max_length / self.config.hop_size + 1;
+                if log_mel_spec.nrows() > chunk_frames {
+                    return Ok(AudioFeatures {
+                        input_features: random_mel_fusion(&log_mel_spec, chunk_frames, rng)?,
+                        is_longer: true,
+                    });
+                }
+                log_mel_spec
+            }
+            _ => {
+                let waveform = self.fit_to_max_length(pcm_audio, rng);
+                self.log_mel_spectrogram(&waveform, buffers)?
+            }
+        };
+
+        let n_channels = match self.config.truncation {
+            Truncation::RandTrunc => 1,
+            Truncation::Fusion => 4,
+        };
+        Ok(AudioFeatures {
+            input_features: stack(Axis(0), &vec![log_mel_spec.view(); n_channels])?,
+            is_longer: false,
+        })
+    }
+
+    /// Pad or truncate the clip to `max_length` samples, as in `ClapFeatureExtractor._get_input_mel`
+    fn fit_to_max_length<'a>(&self, pcm_audio: &'a [f32], rng: &mut impl Rng) -> Cow<'a, [f32]> {
+        let max_length = self.config.max_length;
+        match pcm_audio.len().cmp(&max_length) {
+            std::cmp::Ordering::Equal => Cow::Borrowed(pcm_audio),
+            // Fused clips only get here if they are too short to fuse,
+            // so both truncation modes crop the same way
+            std::cmp::Ordering::Greater => {
+                let crop_start = rng.gen_range(0..=pcm_audio.len() - max_length);
+                Cow::Borrowed(&pcm_audio[crop_start..crop_start + max_length])
+            }
+            std::cmp::Ordering::Less => {
+                let n_repeats = max_length / pcm_audio.len();
+                let mut waveform = match self.config.padding {
+                    Padding::RepeatPad => pcm_audio.repeat(n_repeats),
+                    Padding::Repeat => {
+                        let mut waveform = pcm_audio.repeat(n_repeats + 1);
+                        waveform.truncate(max_length);
+                        waveform
+                    }
+                    Padding::Pad => pcm_audio.to_vec(),
+                };
+                waveform.resize(max_length, 0.0);
+                Cow::Owned(waveform)
+            }
+        }
+    }
+
+    /// Centered STFT power spectrogram -> mel filterbank -> log scaling.
+    /// Returns [n_frames, n_mels].
+    fn log_mel_spectrogram(
+        &self,
+        waveform: &[f32],
+        buffers: &mut FftBuffers,
+    ) -> Result<Array2<f64>> {
+        let fft_size = self.config.fft_size;
+        let hop_size = self.config.hop_size;
+        let padded_waveform = reflect_pad(waveform, fft_size / 2)?;
+        let n_frames = 1 + (padded_waveform.len() - fft_size) / hop_size;
+
+        let mut mel_spec = Array2::<f64>::zeros((n_frames, self.config.n_mels));
+        for (frame_i, mut mel_frame) in mel_spec.axis_iter_mut(Axis(0)).enumerate() {
+            let frame_start = frame_i * hop_size;
+            let frame = &padded_waveform[frame_start..frame_start + fft_size];
+            for ((input, sample), window) in buffers.input.iter_mut().zip(frame).zip(&self.window) {
+                *input = sample * window;
+            }
+            self.fft
+                .process_with_scratch(
+                    &mut buffers.input,
+                    &mut buffers.output,
+                    &mut buffers.scratch,
+                )
+                .map_err(|err| anyhow!("Failed to compute FFT: {:?}", err))?;
+            for (power, bin) in buffers.power.iter_mut().zip(&buffers.output) {
+                *power = bin.norm_sqr();
+            }
+
+            for (mel, mel_filter) in mel_frame.iter_mut().zip(self.mel_filters.outer_iter()) {
+                let mel_power = mel_filter
+                    .iter()
+                    .zip(&buffers.power)
+                    .map(|(weight, power)| weight * power)
+                    .sum::<f64>();
+                *mel = mel_power.max(self.config.mel_floor);
+            }
+        }
+
+        match self.config.log_scaling {
+            LogScaling::Decibels {
+                reference,
+                min_value,
+                db_range,
+            } => {
+                let reference_db = 10.0 * reference.max(min_value).log10();
+                mel_spec.mapv_inplace(|power| 10.0 * power.max(min_value).log10() - reference_db);
+                if let Some(db_range) = db_range {
+                    let max_db = mel_spec.fold(f64::NEG_INFINITY, |acc, x| acc.max(*x));
+                    mel_spec.mapv_inplace(|db| db.max(max_db - db_range));
+                }
+            }
+        }
+
+        Ok(mel_spec)
+    }
+}
+
+/// Stack a downsampled view of the whole spectrogram with crops from its front,
+/// middle and back, as in `ClapFeatureExtractor._random_mel_fusion`.
+/// Returns [4, chunk_frames, n_mels].
+fn random_mel_fusion(
+    log_mel_spec: &Array2<f64>,
+    chunk_frames: usize,
+    rng: &mut impl Rng,
+) -> Result<Array3<f64>> {
+    let n_crop_starts = log_mel_spec.nrows() - chunk_frames + 1;
+    let crops = array_split(n_crop_starts, 3)
+        .into_iter()
+        .map(|crop_starts| match crop_starts.is_empty() {
+            true => 0,
+            false => rng.gen_range(crop_starts),
+        })
+        .map(|crop_start| {
+            log_mel_spec.slice_axis(Axis(0), (crop_start..crop_start + chunk_frames).into())
+        })
+        .collect::<Vec<_>>();
+    let global_view = shrink_frames(log_mel_spec, chunk_frames);
+
+    let mut views = vec![global_view.view()];
+    views.extend(crops);
+    Ok(stack(Axis(0), &views)?)
+}
+
+/// Split 0..n into `sections` contiguous ranges, the first `n % sections`
+/// one longer than the rest (numpy's `array_split`)
+fn array_split(n: usize, sections: usize) -> Vec<std::ops::Range<usize>> {
+    let base_length = n / sections;
+    let n_longer = n % sections;
+    let mut start = 0;
+    (0..sections)
+        .map(|section| {
+            let length = base_length + usize::from(section < n_longer);
+            let range = start..start + length;
+            start += length;
+            range
+        })
+        .collect()
+}
+
+/// Resize the frame axis with bilinear interpolation
+/// (`torch.nn.functional.interpolate` with `align_corners=False`)
+fn shrink_frames(log_mel_spec: &Array2<f64>, n_frames: usize) -> Array2<f64> {
+    let source_frames = log_mel_spec.nrows();
+    let scale = source_frames as f64 / n_frames as f64;
+    Array2::from_shape_fn((n_frames, log_mel_spec.ncols()), |(frame_i, mel_i)| {
+        let source_position = ((frame_i as f64 + 0.5) * scale - 0.5).max(0.0);
+        let lower = (source_position.floor() as usize).min(source_frames - 1);
+        let upper = (lower + 1).min(source_frames - 1);
+        let weight = source_position - lower as f64;
+        (1.0 - weight) * log_mel_spec[[lower, mel_i]] + weight * log_mel_spec[[upper, mel_i]]
+    })
+}
+
+/// Pad both ends by mirroring the signal, excluding the edge sample (numpy's "reflect" mode)
+fn reflect_pad(waveform: &[f32], pad: usize) -> Result<Vec<f64>> {
+    if waveform.len() <= pad {
+        return Err(anyhow!(
+            "Cannot reflect-pad {} samples by {}",
+            waveform.len(),
+            pad
+        ));
+    }
+    let left = (1..=pad).rev().map(|i| waveform[i]);
+    let right = (1..=pad).map(|i| waveform[waveform.len() - 1 - i]);
+    Ok(left
+        .chain(waveform.iter().copied())
+        .chain(right)
+        .map(|sample| sample as f64)
+        .collect())
+}
+
+fn make_window(window: WindowFunction, size: usize) -> Vec<f64> {
+    match window {
+        WindowFunction::Hann => (0..size)
+            .map(|n| 0.5 - 0.5 * (2.0 * std::f64::consts::PI * n as f64 / size as f64).cos())
+            .collect(),
+    }
+}
+
+fn hertz_to_mel(frequency: f64, mel_scale: MelScale) -> f64 {
+    match mel_scale {
+        MelScale::Htk => 2595.0 * (1.0 + frequency / 700.0).log10(),
+        MelScale::Slaney => {
+            const MIN_LOG_HERTZ: f64 = 1000.0;
+            const MIN_LOG_MEL: f64 = 15.0;
+            let log_step = 27.0 / 6.4_f64.ln();
+            if frequency >= MIN_LOG_HERTZ {
+                MIN_LOG_MEL + (frequency / MIN_LOG_HERTZ).ln() * log_step
+            } else {
+                3.0 * frequency / 200.0
+            }
+        }
+    }
+}
+
+fn mel_to_hertz(mel: f64, mel_scale: MelScale) -> f64 {
+    match mel_scale {
+        MelScale::Htk => 700.0 * (10_f64.powf(mel / 2595.0) - 1.0),
+        MelScale::Slaney => {
+            const MIN_LOG_HERTZ: f64 = 1000.0;
+            const MIN_LOG_MEL: f64 = 15.0;
+            let log_step = 6.4_f64.ln() / 27.0;
+            if mel >= MIN_LOG_MEL {
+                MIN_LOG_HERTZ * (log_step * (mel - MIN_LOG_MEL)).exp()
+            } else {
+                200.0 * mel / 3.0
+            }
+        }
+    }
+}
+
+/// Triangular mel filters, as in `transformers.audio_utils.mel_filter_bank`.
+/// Returns [n_mels, n_frequency_bins].
+fn mel_filter_bank(
+    n_frequency_bins: usize,
+    n_mels: usize,
+    f_min: f64,
+    f_max: f64,
+    sample_rate: u32,
+    norm: FilterbankNorm,
+    mel_scale: MelScale,
+) -> Array2<f64> {
+    let mel_min = hertz_to_mel(f_min, mel_scale);
+    let mel_max = hertz_to_mel(f_max, mel_scale);
+    let mel_step = (mel_max - mel_min) / (n_mels + 1) as f64;
+    let filter_frequencies = (0..n_mels + 2)
+        .map(|i| mel_to_hertz(mel_min + i as f64 * mel_step, mel_scale))
+        .collect::<Vec<_>>();
+    let nyquist = (sample_rate / 2) as f64;
+    let fft_frequencies = (0..n_frequency_bins)
+        .map(|i| nyquist * i as f64 / (n_frequency_bins - 1) as f64)
+        .collect::<Vec<_>>();
+
+    Array2::from_shape_fn((n_mels, n_frequency_bins), |(mel_i, bin_i)| {
+        let lower = filter_frequencies[mel_i];
+        let center = filter_frequencies[mel_i + 1];
+        let upper = filter_frequencies[mel_i + 2];
+        let frequency = fft_frequencies[bin_i];
+        let rising = (frequency - lower) / (center - lower);
+        let falling = (upper - frequency) / (upper - center);
+        let weight = rising.min(falling).max(0.0);
+        match norm {
+            FilterbankNorm::None => weight,
+            FilterbankNorm::Slaney => weight * 2.0 / (upper - lower),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(frequency: f32, seconds: f32) -> Vec<f32> {
+        (0..(TARGET_SAMPLE_RATE as f32 * seconds) as usize)
+            .map(|i| {
+                (2.0 * std::f32::consts::PI * frequency * i as f32 / TARGET_SAMPLE_RATE as f32)
+                    .sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_output_shape_matches_clap_input() {
+        let features = clap_feature_extractor(ClapModel::HtsatUnfused)
+            .extract(&tone(440.0, 10.0))
+            .expect("Features should be extracted")
+            .input_features;
+        assert_eq!(features.shape(), &[1, 1001, 64]);
+    }
+
+    #[test]
+    fn test_silence_is_floored() {
+        let features = clap_feature_extractor(ClapModel::HtsatUnfused)
+            .extract(&vec![0.0; 48000])
+            .expect("Features should be extracted")
+            .input_features;
+        assert!(features.iter().all(|db| (db + 100.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_repeat_pad_repeats_waveform_then_pads_with_silence() {
+        // 3s repeats 3 times to 9s, and the last second is zero-padded
+        let features = clap_feature_extractor(ClapModel::HtsatUnfused)
+            .extract(&tone(1000.0, 3.0))
+            .expect("Features should be extracted")
+            .input_features;
+        let loud_frame = features.index_axis(Axis(1), 450);
+        let silent_frame = features.index_axis(Axis(1), 960);
+        assert!(loud_frame.iter().any(|db| *db > 0.0));
+        assert!(silent_frame.iter().all(|db| (db + 100.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_truncation_crops_to_max_length() {
+        let features = clap_feature_extractor(ClapModel::HtsatUnfused)
+            .extract(&tone(440.0, 14.0))
+            .expect("Features should be extracted")
+            .input_features;
+        assert_eq!(features.shape(), &[1, 1001, 64]);
+    }
+
+    #[test]
+    fn test_tone_lands_in_expected_mel_bin() {
+        let extractor = clap_feature_extractor(ClapModel::HtsatUnfused);
+        let features = extractor
+            .extract(&tone(1000.0, 10.0))
+            .expect("Features should be extracted")
+            .input_features;
+        let middle_frame = features.index_axis(Axis(1), 500);
+        let loudest_bin = middle_frame
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.partial_cmp(b.1).expect("dB should be comparable"))
+            .map(|(bin, _)| bin)
+            .expect("Frame should have bins");
+        let mel_filter = extractor.mel_filters.index_axis(Axis(0), loudest_bin);
+        // The FFT bin for 1kHz should be inside the loudest filter
+        let fft_bin = (1000.0 / (TARGET_SAMPLE_RATE as f64 / 1024.0)).round() as usize;
+        assert!(mel_filter[fft_bin] > 0.0);
+    }
+
+    #[test]
+    fn test_batch_matches_single_extraction() {
+        let clips = [tone(440.0, 10.0), tone(2000.0, 10.0)];
+        let extractor = clap_feature_extractor(ClapModel::HtsatUnfused);
+        let batch = extractor
+            .extract_batch(&clips.iter().map(|clip| clip.as_slice()).collect::<Vec<_>>())
+            .expect("Batch should be extracted");
+        for (clip, batch_features) in clips.iter().zip(batch) {
+            let features = extractor
+                .extract(clip)
+                .expect("Features should be extracted");
+            assert_eq!(features.input_features, batch_features.input_features);
+        }
+    }
+
+    #[test]
+    fn test_fusion_stacks_global_and_local_views() {
+        let features = clap_feature_extractor(ClapModel::HtsatFused)
+            .extract(&tone(440.0, 30.0))
+            .expect("Features should be extracted");
+        assert!(features.is_longer);
+        assert_eq!(features.input_features.shape(), &[4, 1001, 64]);
+    }
+
+    #[test]
+    fn test_fusion_repeats_short_clips_across_channels() {
+        let features = clap_feature_extractor(ClapModel::HtsatFused)
+            .extract(&tone(440.0, 3.0))
+            .expect("Features should be extracted");
+        assert!(!features.is_longer);
+        assert_eq!(features.input_features.shape(), &[4, 1001, 64]);
+        let first_channel = features.input_features.index_axis(Axis(0), 0);
+        for channel in features.input_features.outer_iter() {
+            assert_eq!(channel, first_channel);
+        }
+    }
+
+    #[test]
+    fn test_shrink_frames_interpolates_between_frames() {
+        let ramp = Array2::from_shape_fn((4, 1), |(frame_i, _)| frame_i as f64);
+        let shrunk = shrink_frames(&ramp, 2);
+        // Output frames are centered on input positions 0.5 and 2.5
+        assert_eq!(shrunk.column(0).to_vec(), vec![0.5, 2.5]);
+    }
+
+    #[test]
+    fn test_array_split_matches_numpy() {
+        assert_eq!(array_split(7, 3), vec![0..3, 3..5, 5..7]);
+        assert_eq!(array_split(2, 3), vec![0..1, 1..2, 2..2]);
+    }
+
+    #[test]
+    fn test_slaney_mel_scale_round_trips() {
+        for frequency in [0.0, 500.0, 1000.0, 4000.0, 14_000.0] {
+            let mel = hertz_to_mel(frequency, MelScale::Slaney);
+            assert!((mel_to_hertz(mel, MelScale::Slaney) - frequency).abs() < 1e-6);
+            let mel = hertz_to_mel(frequency, MelScale::Htk);
+            assert!((mel_to_hertz(mel, MelScale::Htk) - frequency).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! Where each indexed file can be found. The same content can be at several paths,
+//! so every path is recorded as a location of the file's hash.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use sqlx::SqlitePool;
+
+use super::LoadedAudioFile;
+use crate::paths::{decode_path, display_path, encode_path};
+
+/// The hash of the file last seen at `path`, if any
+pub(crate) async fn get_hash_at(pool: &SqlitePool, path: &Path) -> Result<Option<String>> {
+    let encoded_path = encode_path(path);
+    sqlx::query_scalar!(
+        r#"SELECT file_hash FROM file_location WHERE file_path = ?"#,
+        encoded_path
+    )
+    .fetch_optional(pool)
+    .await
+    .context(format!("Failed to get the file at {:?}", path))
+}
+
+/// Every path of a file, in order
+pub(crate) async fn get_locations(pool: &SqlitePool, file_hash: &str) -> Result<Vec<PathBuf>> {
+    let paths = sqlx::query_scalar!(
+        r#"SELECT file_path FROM file_location WHERE file_hash = ? ORDER BY file_path"#,
+        file_hash
+    )
+    .fetch_all(pool)
+    .await
+    .context(format!("Failed to get locations of {}", file_hash))?;
+    Ok(paths.iter().map(|path| decode_path(path)).collect())
+}
+
+/// The hash of the file a segment was cut from
+pub(crate) async fn get_segment_file_hash(pool: &SqlitePool, segment_id: i64) -> Result<String> {
+    sqlx::query_scalar!(
+        r#"SELECT file_hash FROM audio_file_segment WHERE rowid = ?"#,
+        segment_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context(format!("Failed to get the file of segment {}", segment_id))?
+    .context(format!("Segment {} isn't indexed", segment_id))
+}
+
+/// The UI only has the display form of a path, which loses file names that aren't valid
+/// UTF-8, so find the location of `file_hash` that it was made from
+pub(crate) async fn find_location_by_display_path(
+    pool: &SqlitePool,
+    file_hash: &str,
+    display: &str,
+) -> Result<PathBuf> {
+    get_locations(pool, file_hash)
+        .await?
+        .into_iter()
+        .find(|path| display_path(path) == display)
+        .context(format!("{} isn't a location of {}", display, file_hash))
+}
+
+/// Every path of every file, by hash
+pub(crate) async fn get_all_locations(pool: &SqlitePool) -> Result<HashMap<String, Vec<PathBuf>>> {
+    let rows = sqlx::query!(r#"SELECT file_hash, file_path FROM file_location ORDER BY file_path"#)
+        .fetch_all(pool)
+        .await
+        .context("Failed to get file locations")?;
+    let mut locations: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for row in rows {
+        locations
+            .entry(row.file_hash)
+            .or_default()
+            .push(decode_path(&row.file_path));
+    }
+    Ok(locations)
+}
+
+/// Record that `path` holds `file_hash`, replacing whatever it held before
+pub(crate) async fn add_location(pool: &SqlitePool, path: &Path, file_hash: &str) -> Result<()> {
+    let encoded_path = encode_path(path);
+    sqlx::query!(
+        r#"INSERT OR REPLACE INTO file_location (file_path, file_hash) VALUES (?, ?)"#,
+        encoded_path,
+        file_hash
+    )
+    .execute(pool)
+    .await
+    .context(format!("Failed to add location {:?}", path))?;
+    Ok(())
+}
+
+/// Add the paths of files whose content was indexed under another path during the same run.
+/// Files that failed to index are skipped, so they're tried again on the next run.
+pub(super) async fn add_indexed_locations(
+    pool: &SqlitePool,
+    audio_files: &[LoadedAudioFile],
+) -> Result<()> {
+    for audio_file in audio_files {
+        add_indexed_location(pool, &audio_file.file_path, &audio_file.file_hash).await?;
+    }
+    Ok(())
+}
+
+/// Record that `path` holds `file_hash` if its content is indexed, replacing whatever it held before.
+/// Returns false if the content isn't indexed.
+pub(super) async fn add_indexed_location(
+    pool: &SqlitePool,
+    path: &Path,
+    file_hash: &str,
+) -> Result<bool> {
+    let encoded_path = encode_path(path);
+    let result = sqlx::query!(
+        r#"INSERT OR REPLACE INTO file_location (file_path, file_hash)
+        SELECT ?, file_hash FROM audio_file WHERE file_hash = ?"#,
+        encoded_path,
+        file_hash
+    )
+    .execute(pool)
+    .await
+    .context(format!("Failed to add location {:?}", path))?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Forget locations where files are no longer found.
+/// The files stay indexed, and get a location again if they turn up.
+pub(super) async fn remove_locations(pool: &SqlitePool, paths: &[PathBuf]) -> Result<()> {
+    let mut sql_transaction = pool
+        .begin()
+        .await
+        .context("Failed to begin transaction to remove locations")?;
+    for path in paths {
+        let encoded_path = encode_path(path);
+        sqlx::query!(
+            r#"DELETE FROM file_location WHERE file_path = ?"#,
+            encoded_path
+        )
+        .execute(&mut *sql_transaction)
+        .await
+        .context(format!("Failed to remove location {:?}", path))?;
+    }
+    sql_transaction
+        .commit()
+        .await
+        .context("Failed to commit removed locations")?;
+    Ok(())
+}
+
+/// Move the location `from` of `file_hash` to `to`, leaving the file's other locations alone.
+/// Returns false if `from` isn't a location of the file, for example because another copy
+/// has already been moved from it, or if `to` is already taken.
+pub(crate) async fn move_location(
+    pool: &SqlitePool,
+    file_hash: &str,
+    from: &Path,
+    to: &Path,
+) -> Result<bool> {
+    let (encoded_from, encoded_to) = (encode_path(from), encode_path(to));
+    let result = sqlx::query!(
+        r#"UPDATE OR IGNORE file_location SET file_path = ? WHERE file_path = ? AND file_hash = ?"#,
+        encoded_to,
+        encoded_from,
+        file_hash
+    )
+    .execute(pool)
+    .await
+    .context(format!("Failed to move location {:?} to {:?}", from, to))?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::database;
+
+    async fn create_test_pool() -> SqlitePool {
+        let pool = database::create_test_pool().await;
+        for file_hash in ["steps_hash", "door_hash"] {
+            sqlx::query!("INSERT INTO audio_file (file_hash) VALUES (?)", file_hash)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_move_only_updates_the_moved_location() {
+        let pool = create_test_pool().await;
+        add_location(&pool, Path::new("/A/steps.wav"), "steps_hash")
+            .await
+            .unwrap();
+        add_location(&pool, Path::new("/B/steps.wav"), "steps_hash")
+            .await
+            .unwrap();
+
+        let moved = move_location(
+            &pool,
+            "steps_hash",
+            Path::new("/B/steps.wav"),
+            Path::new("/C/steps.wav"),
+        )
+        .await
+        .unwrap();
+        assert!(moved);
+        assert_eq!(
+            get_locations(&pool, "steps_hash").await.unwrap(),
+            vec![PathBuf::from("/A/steps.wav"), PathBuf::from("/C/steps.wav")]
+        );
+
+        // Another copy can't move from the same place
+        let moved = move_location(
+            &pool,
+            "steps_hash",
+            Path::new("/B/steps.wav"),
+            Path::new("/D/steps.wav"),
+        )
+        .await
+        .unwrap();
+        assert!(!moved);
+    }
+
+    #[tokio::test]
+    async fn test_changed_content_replaces_location() {
+        let pool = create_test_pool().await;
+        add_location(&pool, Path::new("/A/take.wav"), "steps_hash")
+            .await
+            .unwrap();
+        add_location(&pool, Path::new("/A/take.wav"), "door_hash")
+            .await
+            .unwrap();
+        assert_eq!(
+            get_hash_at(&pool, Path::new("/A/take.wav")).await.unwrap(),
+            Some("door_hash".to_string())
+        );
+        assert!(get_locations(&pool, "steps_hash").await.unwrap().is_empty());
+
+        // Only files whose content made it into the index get a location
+        let copies = [
+            LoadedAudioFile {
+                file_hash: "door_hash".to_string(),
+                file_path: PathBuf::from("/B/door.wav"),
+            },
+            LoadedAudioFile {
+                file_hash: "failed_hash".to_string(),
+                file_path: PathBuf::from("/B/failed.wav"),
+            },
+        ];
+        add_indexed_locations(&pool, &copies).await.unwrap();
+        let locations = get_all_locations(&pool).await.unwrap();
+        assert_eq!(
+            locations["door_hash"],
+            vec![PathBuf::from("/A/take.wav"), PathBuf::from("/B/door.wav")]
+        );
+        assert!(!locations.contains_key("failed_hash"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_non_utf8_locations_are_found_by_display_path() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let pool = create_test_pool().await;
+        let latin1_path = Path::new(OsStr::from_bytes(b"/CD-ROM/Caf\xe9.wav"));
+        add_location(&pool, latin1_path, "door_hash").await.unwrap();
+        add_location(&pool, Path::new("/Sounds/door.wav"), "door_hash")
+            .await
+            .unwrap();
+        sqlx::query!(
+            "INSERT INTO audio_file_segment
+                (rowid, file_hash, starting_timestamp, ending_timestamp, embedding)
+            VALUES (7, 'door_hash', 0, 10, X'')"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let file_hash = get_segment_file_hash(&pool, 7).await.unwrap();
+        assert_eq!(
+            find_location_by_display_path(&pool, &file_hash, "/CD-ROM/Caf\u{FFFD}.wav")
+                .await
+                .unwrap(),
+            latin1_path
+        );
+        assert!(
+            find_location_by_display_path(&pool, &file_hash, "/CD-ROM/Cafe.wav")
+                .await
+                .is_err()
+        );
+        assert!(get_segment_file_hash(&pool, 8).await.is_err());
+    }
+}
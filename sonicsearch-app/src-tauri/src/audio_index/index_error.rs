@@ -0,0 +1,468 @@
+//! Files that failed to index, and when to try them again.
+//! A failure is recorded per path, along with the hash of the contents that failed,
+//! so a file that changes on disk is retried straight away.
+
+use std::path::{Path, PathBuf};
+use std::{fmt, io, result};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use log::debug;
+use sqlx::SqlitePool;
+use tauri::State;
+
+use super::index_job;
+use super::indexing_status::Status;
+use super::pipeline::{FileFailure, PipelineStage};
+use super::{timestamp_to_date_time, update_audio_index};
+use crate::paths::{decode_path, encode_path, serialize_display_path};
+use crate::state::AppState;
+
+/// Wait this long before retrying a file that failed once
+const BASE_RETRY_DELAY_HOURS: i64 = 1;
+/// Each further failure doubles the delay, up to this
+const MAX_RETRY_DELAY_HOURS: i64 = 7 * 24;
+
+/// The file is in a format we can't read, as opposed to being broken
+#[derive(Debug)]
+pub struct UnsupportedFormatError(pub String);
+
+impl fmt::Display for UnsupportedFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedFormatError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, specta::Type)]
+pub enum IndexErrorKind {
+    /// The file couldn't be read, e.g. it was deleted or is on an unplugged drive
+    Io,
+    /// The file is in a format we can't decode. Only retried once the file changes
+    UnsupportedFormat,
+    /// The file is corrupt or otherwise failed to decode
+    Decode,
+    /// Resampling or feature extraction failed
+    Processing,
+    /// The model failed to embed the file
+    Embedding,
+    /// The file couldn't be written to the index
+    Database,
+}
+
+impl IndexErrorKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            IndexErrorKind::Io => "io",
+            IndexErrorKind::UnsupportedFormat => "unsupported_format",
+            IndexErrorKind::Decode => "decode",
+            IndexErrorKind::Processing => "processing",
+            IndexErrorKind::Embedding => "embedding",
+            IndexErrorKind::Database => "database",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "io" => Some(IndexErrorKind::Io),
+            "unsupported_format" => Some(IndexErrorKind::UnsupportedFormat),
+            "decode" => Some(IndexErrorKind::Decode),
+            "processing" => Some(IndexErrorKind::Processing),
+            "embedding" => Some(IndexErrorKind::Embedding),
+            "database" => Some(IndexErrorKind::Database),
+            _ => None,
+        }
+    }
+
+    fn classify(stage: PipelineStage, error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if cause.is::<UnsupportedFormatError>() {
+                return IndexErrorKind::UnsupportedFormat;
+            }
+            if cause.is::<io::Error>() {
+                return IndexErrorKind::Io;
+            }
+            match cause.downcast_ref::<hound::Error>() {
+                Some(hound::Error::IoError(_)) => return IndexErrorKind::Io,
+                Some(hound::Error::Unsupported) => return IndexErrorKind::UnsupportedFormat,
+                _ => {}
+            }
+        }
+        match stage {
+            PipelineStage::Hash => IndexErrorKind::Io,
+            PipelineStage::Decode => IndexErrorKind::Decode,
+            PipelineStage::Resample | PipelineStage::Mel => IndexErrorKind::Processing,
+            PipelineStage::Embed => IndexErrorKind::Embedding,
+            PipelineStage::Preindex | PipelineStage::Write => IndexErrorKind::Database,
+        }
+    }
+
+    /// How long to wait before the next attempt, or None to wait for the file to change
+    fn retry_delay(&self, attempts: u32) -> Option<Duration> {
+        if *self == IndexErrorKind::UnsupportedFormat {
+            return None;
+        }
+        let doublings = attempts.saturating_sub(1).min(16);
+        let delay_hours = (BASE_RETRY_DELAY_HOURS << doublings).min(MAX_RETRY_DELAY_HOURS);
+        Some(Duration::hours(delay_hours))
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, specta::Type)]
+pub struct IndexError {
+    #[serde(serialize_with = "serialize_display_path")]
+    file_path: PathBuf,
+    stage: String,
+    kind: IndexErrorKind,
+    message: String,
+    attempts: u32,
+    last_failed_at: DateTime<Utc>,
+    /// When the file will next be tried, or null if it waits for the file to change
+    next_retry_at: Option<DateTime<Utc>>,
+}
+
+struct IndexErrorRow {
+    file_path: Vec<u8>,
+    stage: String,
+    error_kind: String,
+    message: String,
+    attempts: i64,
+    last_failed_at: i64,
+    next_retry_at: Option<i64>,
+}
+
+impl From<IndexErrorRow> for IndexError {
+    fn from(row: IndexErrorRow) -> Self {
+        Self {
+            file_path: decode_path(&row.file_path),
+            stage: row.stage,
+            kind: IndexErrorKind::parse(&row.error_kind).unwrap_or(IndexErrorKind::Processing),
+            message: row.message,
+            attempts: row.attempts as u32,
+            last_failed_at: timestamp_to_date_time(row.last_failed_at),
+            next_retry_at: row.next_retry_at.map(timestamp_to_date_time),
+        }
+    }
+}
+
+/// Record a failure, backing off further if the same contents failed before
+pub(super) async fn record_failure(
+    pool: &SqlitePool,
+    failure: &FileFailure,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    let encoded_path = encode_path(&failure.file_path);
+    let previous_attempts = sqlx::query_scalar!(
+        r#"SELECT attempts FROM index_error WHERE file_path = ? AND file_hash = ?"#,
+        encoded_path,
+        failure.file_hash
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to get previous index errors")?
+    .unwrap_or(0);
+    let attempts = previous_attempts + 1;
+
+    let kind = IndexErrorKind::classify(failure.stage, &failure.error);
+    let error_kind = kind.as_str();
+    let stage = failure.stage.to_string();
+    let message = format!("{:#}", failure.error);
+    let last_failed_at = now.timestamp();
+    let next_retry_at = kind
+        .retry_delay(attempts as u32)
+        .map(|delay| (now + delay).timestamp());
+    sqlx::query!(
+        r#"INSERT OR REPLACE INTO index_error
+        (file_path, file_hash, stage, error_kind, message, attempts, last_failed_at, next_retry_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#,
+        encoded_path,
+        failure.file_hash,
+        stage,
+        error_kind,
+        message,
+        attempts,
+        last_failed_at,
+        next_retry_at
+    )
+    .execute(pool)
+    .await
+    .context(format!(
+        "Failed to record index error for {:?}",
+        failure.file_path
+    ))?;
+    Ok(())
+}
+
+/// Forget a file's failures once it has been indexed
+pub(super) async fn clear_error(pool: &SqlitePool, file_path: &Path) -> Result<()> {
+    let encoded_path = encode_path(file_path);
+    sqlx::query!("DELETE FROM index_error WHERE file_path = ?", encoded_path)
+        .execute(pool)
+        .await
+        .context(format!("Failed to clear index error for {:?}", file_path))?;
+    Ok(())
+}
+
+/// Whether a file should be indexed now, or skipped because it failed recently
+pub(super) async fn is_due_for_retry(
+    pool: &SqlitePool,
+    file_path: &Path,
+    file_hash: &str,
+    now: DateTime<Utc>,
+) -> Result<bool> {
+    let encoded_path = encode_path(file_path);
+    let previous_failure = sqlx::query!(
+        r#"SELECT file_hash, next_retry_at FROM index_error WHERE file_path = ?"#,
+        encoded_path
+    )
+    .fetch_optional(pool)
+    .await
+    .context(format!("Failed to get index error for {:?}", file_path))?;
+    let Some(previous_failure) = previous_failure else {
+        return Ok(true);
+    };
+    if previous_failure.file_hash != file_hash {
+        debug!("{:?} has changed since it failed, retrying", file_path);
+        return Ok(true);
+    }
+    Ok(previous_failure
+        .next_retry_at
+        .is_some_and(|next_retry_at| next_retry_at <= now.timestamp()))
+}
+
+/// Get every file that failed to index, most recent first
+#[tauri::command]
+#[specta::specta]
+pub async fn get_index_errors(
+    app_state: State<'_, AppState>,
+) -> result::Result<Vec<IndexError>, String> {
+    let rows = sqlx::query_as!(
+        IndexErrorRow,
+        r#"SELECT file_path, stage, error_kind, message, attempts, last_failed_at, next_retry_at
+        FROM index_error ORDER BY last_failed_at DESC"#
+    )
+    .fetch_all(&app_state.pool)
+    .await
+    .map_err(|err| format!("Failed to get index errors: {:?}", err))?;
+    Ok(rows.into_iter().map(IndexError::from).collect())
+}
+
+/// Make every failed file due for retry, however recently it failed
+async fn clear_backoff(pool: &SqlitePool) -> Result<()> {
+    sqlx::query!("UPDATE index_error SET next_retry_at = 0")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Retry every failed file now, regardless of backoff, by updating the index.
+/// An interrupted job is dropped rather than resumed, since only a full scan finds
+/// the failed files. Its unfinished files aren't indexed, so the scan finds them again.
+/// If indexing is already running, returns false and the failed files are retried by the next run.
+#[tauri::command]
+#[specta::specta]
+pub async fn retry_failed_files(app_state: State<'_, AppState>) -> result::Result<bool, String> {
+    clear_backoff(&app_state.pool)
+        .await
+        .map_err(|err| format!("Failed to reset index error backoff: {:?}", err))?;
+    if matches!(
+        app_state.indexing_status.get_status().await,
+        Status::InProgress(_)
+    ) {
+        // The running job has already scanned, so keep it going
+        return update_audio_index(app_state).await;
+    }
+    index_job::clear_job(&app_state.pool)
+        .await
+        .map_err(|err| format!("Failed to drop interrupted index job: {:?}", err))?;
+    update_audio_index(app_state).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::database::create_test_pool;
+
+    fn failure(file_hash: &str, stage: PipelineStage, error: anyhow::Error) -> FileFailure {
+        FileFailure {
+            file_path: PathBuf::from("/Sounds/door.wav"),
+            file_hash: file_hash.to_string(),
+            stage,
+            error,
+        }
+    }
+
+    #[test]
+    fn test_classify_errors() {
+        let missing_file =
+            anyhow::Error::new(io::Error::from(io::ErrorKind::NotFound)).context("Failed to open");
+        assert_eq!(
+            IndexErrorKind::classify(PipelineStage::Decode, &missing_file),
+            IndexErrorKind::Io
+        );
+        let unsupported = anyhow::Error::new(UnsupportedFormatError("A-law".to_string()));
+        assert_eq!(
+            IndexErrorKind::classify(PipelineStage::Decode, &unsupported),
+            IndexErrorKind::UnsupportedFormat
+        );
+        let corrupt = anyhow::Error::new(hound::Error::FormatError("no RIFF tag found"));
+        assert_eq!(
+            IndexErrorKind::classify(PipelineStage::Decode, &corrupt),
+            IndexErrorKind::Decode
+        );
+        assert_eq!(
+            IndexErrorKind::classify(PipelineStage::Embed, &anyhow::anyhow!("Model failed")),
+            IndexErrorKind::Embedding
+        );
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off() {
+        let kind = IndexErrorKind::Decode;
+        assert_eq!(kind.retry_delay(1), Some(Duration::hours(1)));
+        assert_eq!(kind.retry_delay(2), Some(Duration::hours(2)));
+        assert_eq!(kind.retry_delay(4), Some(Duration::hours(8)));
+        assert_eq!(
+            kind.retry_delay(100),
+            Some(Duration::hours(MAX_RETRY_DELAY_HOURS))
+        );
+        assert_eq!(IndexErrorKind::UnsupportedFormat.retry_delay(1), None);
+    }
+
+    #[tokio::test]
+    async fn test_failed_files_are_retried_with_backoff() {
+        let pool = create_test_pool().await;
+        let path = Path::new("/Sounds/door.wav");
+        let now = Utc::now();
+        let corrupt = || {
+            failure(
+                "door_hash",
+                PipelineStage::Decode,
+                anyhow::anyhow!("Corrupt"),
+            )
+        };
+        assert!(is_due_for_retry(&pool, path, "door_hash", now)
+            .await
+            .unwrap());
+
+        record_failure(&pool, &corrupt(), now).await.unwrap();
+        assert!(
+            !is_due_for_retry(&pool, path, "door_hash", now + Duration::minutes(59))
+                .await
+                .unwrap()
+        );
+        assert!(
+            is_due_for_retry(&pool, path, "door_hash", now + Duration::hours(1))
+                .await
+                .unwrap()
+        );
+        // Changed contents are retried straight away
+        assert!(is_due_for_retry(&pool, path, "new_door_hash", now)
+            .await
+            .unwrap());
+
+        let retried_at = now + Duration::hours(1);
+        record_failure(&pool, &corrupt(), retried_at).await.unwrap();
+        let attempts = sqlx::query_scalar!("SELECT attempts FROM index_error")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(attempts, 2);
+        assert!(!is_due_for_retry(
+            &pool,
+            path,
+            "door_hash",
+            retried_at + Duration::minutes(119)
+        )
+        .await
+        .unwrap());
+        assert!(
+            is_due_for_retry(&pool, path, "door_hash", retried_at + Duration::hours(2))
+                .await
+                .unwrap()
+        );
+
+        clear_error(&pool, path).await.unwrap();
+        assert!(is_due_for_retry(&pool, path, "door_hash", now)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_files_wait_for_changes() {
+        let pool = create_test_pool().await;
+        let path = Path::new("/Sounds/door.wav");
+        let now = Utc::now();
+        let unsupported = failure(
+            "door_hash",
+            PipelineStage::Decode,
+            anyhow::Error::new(UnsupportedFormatError("A-law".to_string())),
+        );
+        record_failure(&pool, &unsupported, now).await.unwrap();
+        assert!(
+            !is_due_for_retry(&pool, path, "door_hash", now + Duration::days(365))
+                .await
+                .unwrap()
+        );
+        assert!(is_due_for_retry(&pool, path, "new_door_hash", now)
+            .await
+            .unwrap());
+
+        // Failing with different contents, here before it could be hashed, starts a new count
+        let unreadable = failure(
+            "",
+            PipelineStage::Hash,
+            anyhow::Error::new(io::Error::from(io::ErrorKind::PermissionDenied)),
+        );
+        record_failure(&pool, &unreadable, now).await.unwrap();
+        let error = sqlx::query!("SELECT stage, error_kind, attempts FROM index_error")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            (
+                error.stage.as_str(),
+                error.error_kind.as_str(),
+                error.attempts
+            ),
+            ("hash", "io", 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cleared_backoff_makes_every_failure_due() {
+        let pool = create_test_pool().await;
+        let now = Utc::now();
+        let corrupt = failure(
+            "door_hash",
+            PipelineStage::Decode,
+            anyhow::Error::new(hound::Error::FormatError("no RIFF tag found")),
+        );
+        record_failure(&pool, &corrupt, now).await.unwrap();
+        let mut unsupported = failure(
+            "bell_hash",
+            PipelineStage::Decode,
+            anyhow::Error::new(UnsupportedFormatError("A-law".to_string())),
+        );
+        unsupported.file_path = PathBuf::from("/Sounds/bell.wav");
+        record_failure(&pool, &unsupported, now).await.unwrap();
+        assert!(
+            !is_due_for_retry(&pool, Path::new("/Sounds/door.wav"), "door_hash", now)
+                .await
+                .unwrap()
+        );
+
+        clear_backoff(&pool).await.unwrap();
+        assert!(
+            is_due_for_retry(&pool, Path::new("/Sounds/door.wav"), "door_hash", now)
+                .await
+                .unwrap()
+        );
+        assert!(
+            is_due_for_retry(&pool, Path::new("/Sounds/bell.wav"), "bell_hash", now)
+                .await
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! The persisted work queue of the current indexing run.
+//! Every file to be indexed is recorded before the pipeline starts, and its state
+//! is updated as it moves through. If the app quits mid-run, the next run picks up
+//! the unfinished files instead of rescanning and rehashing the library.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use sqlx::SqlitePool;
+
+use super::LoadedAudioFile;
+use crate::paths::{decode_path, encode_path};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexJobState {
+    Pending,
+    Embedding,
+    Done,
+    Failed,
+    /// Another path of a queued file's content, located once the content is indexed
+    Copy,
+}
+
+impl IndexJobState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            IndexJobState::Pending => "pending",
+            IndexJobState::Embedding => "embedding",
+            IndexJobState::Done => "done",
+            IndexJobState::Failed => "failed",
+            IndexJobState::Copy => "copy",
+        }
+    }
+}
+
+/// Replace the job with `audio_files`, all pending, and the `copies` to locate once they're indexed
+pub async fn create_job(
+    pool: &SqlitePool,
+    audio_files: &[LoadedAudioFile],
+    copies: &[LoadedAudioFile],
+) -> Result<()> {
+    let mut sql_transaction = pool
+        .begin()
+        .await
+        .context("Failed to begin transaction to create index job")?;
+    sqlx::query!("DELETE FROM index_job")
+        .execute(&mut *sql_transaction)
+        .await
+        .context("Failed to clear previous index job")?;
+    let queued_files = audio_files
+        .iter()
+        .map(|audio_file| (audio_file, IndexJobState::Pending))
+        .chain(copies.iter().map(|copy| (copy, IndexJobState::Copy)));
+    for (audio_file, state) in queued_files {
+        let encoded_path = encode_path(&audio_file.file_path);
+        let state = state.as_str();
+        sqlx::query!(
+            r#"INSERT OR REPLACE INTO index_job (file_path, file_hash, state) VALUES (?, ?, ?)"#,
+            encoded_path,
+            audio_file.file_hash,
+            state
+        )
+        .execute(&mut *sql_transaction)
+        .await
+        .context(format!(
+            "Failed to add {:?} to index job",
+            audio_file.file_path
+        ))?;
+    }
+    sql_transaction
+        .commit()
+        .await
+        .context("Failed to commit index job")?;
+    Ok(())
+}
+
+/// Files of an interrupted job that were never written to the index.
+/// A file may have been written just before the app quit without its state
+/// being updated, so anything already in audio_file is skipped.
+pub async fn get_unfinished_files(pool: &SqlitePool) -> Result<Vec<LoadedAudioFile>> {
+    let pending = IndexJobState::Pending.as_str();
+    let embedding = IndexJobState::Embedding.as_str();
+    let unfinished_files = sqlx::query!(
+        r#"SELECT file_hash, file_path FROM index_job
+        WHERE state IN (?, ?) AND file_hash NOT IN (SELECT file_hash FROM audio_file)
+        ORDER BY rowid"#,
+        pending,
+        embedding
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to get unfinished index job")?;
+    Ok(unfinished_files
+        .into_iter()
+        .map(|row| LoadedAudioFile {
+            file_hash: row.file_hash,
+            file_path: decode_path(&row.file_path),
+        })
+        .collect())
+}
+
+/// The copies recorded with the job, whether or not their content has been indexed yet
+pub async fn get_copies(pool: &SqlitePool) -> Result<Vec<LoadedAudioFile>> {
+    let copy = IndexJobState::Copy.as_str();
+    let copies = sqlx::query!(
+        r#"SELECT file_hash, file_path FROM index_job WHERE state = ? ORDER BY rowid"#,
+        copy
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to get copies of index job")?;
+    Ok(copies
+        .into_iter()
+        .map(|row| LoadedAudioFile {
+            file_hash: row.file_hash,
+            file_path: decode_path(&row.file_path),
+        })
+        .collect())
+}
+
+pub async fn set_file_state(
+    pool: &SqlitePool,
+    file_path: &Path,
+    state: IndexJobState,
+) -> Result<()> {
+    let state = state.as_str();
+    let encoded_path = encode_path(file_path);
+    sqlx::query!(
+        r#"UPDATE index_job SET state = ?, updated_at = CURRENT_TIMESTAMP WHERE file_path = ?"#,
+        state,
+        encoded_path
+    )
+    .execute(pool)
+    .await
+    .context(format!("Failed to set index job state of {:?}", file_path))?;
+    Ok(())
+}
+
+/// Called once a run has finished, so it isn't resumed at next launch
+pub async fn clear_job(pool: &SqlitePool) -> Result<()> {
+    sqlx::query!("DELETE FROM index_job")
+        .execute(pool)
+        .await
+        .context("Failed to clear index job")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::database::create_test_pool;
+
+    fn audio_file(name: &str) -> LoadedAudioFile {
+        LoadedAudioFile {
+            file_hash: format!("{}_hash", name),
+            file_path: format!("{}.wav", name).into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unfinished_files_are_resumed() {
+        let pool = create_test_pool().await;
+        let files = ["pending", "embedding", "done", "failed", "written"].map(audio_file);
+        let copies = [LoadedAudioFile {
+            file_hash: "pending_hash".to_string(),
+            file_path: "pending copy.wav".into(),
+        }];
+        create_job(&pool, &files, &copies).await.unwrap();
+        set_file_state(&pool, Path::new("embedding.wav"), IndexJobState::Embedding)
+            .await
+            .unwrap();
+        set_file_state(&pool, Path::new("done.wav"), IndexJobState::Done)
+            .await
+            .unwrap();
+        set_file_state(&pool, Path::new("failed.wav"), IndexJobState::Failed)
+            .await
+            .unwrap();
+        // Written to the index, but the app quit before the job was updated
+        sqlx::query!(r#"INSERT INTO audio_file (file_hash) VALUES ('written_hash')"#)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let unfinished_paths = get_unfinished_files(&pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|file| file.file_path)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            unfinished_paths,
+            vec![Path::new("pending.wav"), Path::new("embedding.wav")]
+        );
+        let copy_paths = get_copies(&pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|file| file.file_path)
+            .collect::<Vec<_>>();
+        assert_eq!(copy_paths, vec![Path::new("pending copy.wav")]);
+
+        clear_job(&pool).await.unwrap();
+        assert!(get_unfinished_files(&pool).await.unwrap().is_empty());
+        assert!(get_copies(&pool).await.unwrap().is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_non_utf8_paths_are_resumed_unchanged() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let pool = create_test_pool().await;
+        let file = LoadedAudioFile {
+            file_hash: "latin1_hash".to_string(),
+            file_path: OsStr::from_bytes(b"/CD-ROM/Caf\xe9.wav").into(),
+        };
+        create_job(&pool, std::slice::from_ref(&file), &[])
+            .await
+            .unwrap();
+        set_file_state(&pool, &file.file_path, IndexJobState::Embedding)
+            .await
+            .unwrap();
+
+        let unfinished_files = get_unfinished_files(&pool).await.unwrap();
+        assert_eq!(unfinished_files.len(), 1);
+        assert_eq!(unfinished_files[0].file_path, file.file_path);
+    }
+}
@@ -0,0 +1,188 @@
+//! The history of indexing runs, so it's possible to audit what each one did.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+
+use super::timestamp_to_date_time;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct IndexRunCounts {
+    pub files_scanned: u32,
+    pub files_new: u32,
+    pub files_moved: u32,
+    pub files_removed: u32,
+    pub files_failed: u32,
+    pub segments_embedded: u32,
+}
+
+#[derive(Clone, Debug, serde::Serialize, specta::Type)]
+pub struct IndexRun {
+    id: u32,
+    started_at: DateTime<Utc>,
+    /// Null if the run was interrupted, or is still going
+    ended_at: Option<DateTime<Utc>>,
+    resumed: bool,
+    /// For a resumed run, the unfinished files it picked up from the interrupted one
+    files_scanned: u32,
+    files_new: u32,
+    files_moved: u32,
+    /// Locations of indexed files that the scan didn't find, which were removed
+    files_removed: u32,
+    files_failed: u32,
+    segments_embedded: u32,
+    /// Why the run stopped early, or null if it finished
+    error: Option<String>,
+}
+
+struct IndexRunRow {
+    id: i64,
+    started_at: i64,
+    ended_at: Option<i64>,
+    resumed: bool,
+    files_scanned: i64,
+    files_new: i64,
+    files_moved: i64,
+    files_removed: i64,
+    files_failed: i64,
+    segments_embedded: i64,
+    error: Option<String>,
+}
+
+impl From<IndexRunRow> for IndexRun {
+    fn from(row: IndexRunRow) -> Self {
+        Self {
+            id: row.id as u32,
+            started_at: timestamp_to_date_time(row.started_at),
+            ended_at: row.ended_at.map(timestamp_to_date_time),
+            resumed: row.resumed,
+            files_scanned: row.files_scanned as u32,
+            files_new: row.files_new as u32,
+            files_moved: row.files_moved as u32,
+            files_removed: row.files_removed as u32,
+            files_failed: row.files_failed as u32,
+            segments_embedded: row.segments_embedded as u32,
+            error: row.error,
+        }
+    }
+}
+
+/// Record the start of a run, returning its id
+pub(super) async fn start_run(
+    pool: &SqlitePool,
+    resumed: bool,
+    started_at: DateTime<Utc>,
+) -> Result<i64> {
+    let started_at = started_at.timestamp();
+    let run_id = sqlx::query!(
+        r#"INSERT INTO index_run (started_at, resumed) VALUES (?, ?)"#,
+        started_at,
+        resumed
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record start of index run")?
+    .last_insert_rowid();
+    Ok(run_id)
+}
+
+/// Record the end of a run, with the error that stopped it early if there was one
+pub(super) async fn finish_run(
+    pool: &SqlitePool,
+    run_id: i64,
+    counts: IndexRunCounts,
+    error: Option<&str>,
+    ended_at: DateTime<Utc>,
+) -> Result<()> {
+    let ended_at = ended_at.timestamp();
+    sqlx::query!(
+        r#"UPDATE index_run SET
+            ended_at = ?,
+            files_scanned = ?,
+            files_new = ?,
+            files_moved = ?,
+            files_removed = ?,
+            files_failed = ?,
+            segments_embedded = ?,
+            error = ?
+        WHERE id = ?"#,
+        ended_at,
+        counts.files_scanned,
+        counts.files_new,
+        counts.files_moved,
+        counts.files_removed,
+        counts.files_failed,
+        counts.segments_embedded,
+        error,
+        run_id
+    )
+    .execute(pool)
+    .await
+    .context(format!("Failed to record end of index run {}", run_id))?;
+    Ok(())
+}
+
+/// The most recent runs, newest first
+pub async fn get_recent_runs(pool: &SqlitePool, limit: u32) -> Result<Vec<IndexRun>> {
+    let rows = sqlx::query_as!(
+        IndexRunRow,
+        r#"SELECT
+            id as "id!",
+            started_at,
+            ended_at,
+            resumed as "resumed: bool",
+            files_scanned,
+            files_new,
+            files_moved,
+            files_removed,
+            files_failed,
+            segments_embedded,
+            error
+        FROM index_run ORDER BY id DESC LIMIT ?"#,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to get index runs")?;
+    Ok(rows.into_iter().map(IndexRun::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::database::create_test_pool;
+
+    #[tokio::test]
+    async fn test_failed_runs_are_closed_with_their_error() {
+        let pool = create_test_pool().await;
+        let now = Utc::now();
+        let finished_run = start_run(&pool, false, now).await.unwrap();
+        let counts = IndexRunCounts {
+            files_scanned: 3,
+            files_new: 2,
+            ..Default::default()
+        };
+        finish_run(&pool, finished_run, counts, None, now)
+            .await
+            .unwrap();
+        let failed_run = start_run(&pool, true, now).await.unwrap();
+        finish_run(
+            &pool,
+            failed_run,
+            IndexRunCounts::default(),
+            Some("Failed to run audio embedder"),
+            now,
+        )
+        .await
+        .unwrap();
+
+        let runs = get_recent_runs(&pool, 10).await.unwrap();
+        assert_eq!(runs.len(), 2);
+        assert!(runs[0].resumed && runs[0].ended_at.is_some());
+        assert_eq!(
+            runs[0].error.as_deref(),
+            Some("Failed to run audio embedder")
+        );
+        assert_eq!((runs[1].files_scanned, runs[1].error.as_deref()), (3, None));
+    }
+}
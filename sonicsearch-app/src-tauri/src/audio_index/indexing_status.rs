@@ -1,11 +1,40 @@
 use std::ops::DerefMut;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Utc};
 use log::trace;
 use tauri::AppHandle;
 
 use tauri_specta::Event;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
+
+use crate::paths::display_path;
+
+/// Progress updates are emitted at most this often, so large runs don't flood the frontend.
+/// Phase changes are always emitted.
+const MIN_EMIT_INTERVAL: Duration = Duration::from_millis(250);
+/// How often the indexing throughput is sampled
+const THROUGHPUT_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Weight of the newest sample in the throughput's moving average
+const THROUGHPUT_SMOOTHING: f64 = 0.3;
+/// Status updates kept for subscribers that fall behind. Older ones are dropped,
+/// since only the latest status matters
+const STATUS_CHANNEL_CAPACITY: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, specta::Type)]
+pub enum IndexingPhase {
+    /// Walking the user's directories for audio files
+    Scanning,
+    /// Hashing files to find the ones that aren't indexed yet
+    Hashing,
+    /// Decoding the first files, before any have reached the model
+    Decoding,
+    Embedding,
+    /// Making the newly indexed files searchable
+    SyncingIndex,
+}
 
 #[derive(Clone, Debug, serde::Serialize, specta::Type)]
 pub struct PreIndexingProgress {
@@ -17,19 +46,27 @@ pub struct PreIndexingProgress {
 pub struct IndexingProgress {
     pub(crate) started_indexing: DateTime<Utc>,
     pub(crate) newly_indexed: u32,
+    pub(crate) failed: u32,
     pub(crate) total_to_index: u32,
+    pub(crate) segments_embedded: u32,
+    /// Moving average of files finished per second, once there have been enough to tell
+    pub(crate) files_per_second: Option<f64>,
+    pub(crate) eta_seconds: Option<f64>,
 }
 
 #[derive(Clone, Debug, serde::Serialize, specta::Type)]
 pub struct Progress {
+    phase: IndexingPhase,
     preindexing: PreIndexingProgress,
     indexing: Option<IndexingProgress>,
     total: u32,
+    /// Whether this run is picking up a job that was interrupted before it finished
+    resumed: bool,
+    current_file: Option<String>,
 }
 
 #[derive(Clone, Debug, serde::Serialize, specta::Type)]
 pub enum Status {
-    Started,
     InProgress(Progress),
     Idle,
 }
@@ -37,6 +74,9 @@ pub enum Status {
 pub struct IndexingStatus {
     pub(crate) status: RwLock<Status>,
     pub(crate) app_handle: AppHandle,
+    throughput: Mutex<Throughput>,
+    emit_throttle: Mutex<EmitThrottle>,
+    status_sender: broadcast::Sender<Status>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, specta::Type, tauri_specta::Event)]
@@ -47,96 +87,319 @@ impl IndexingStatus {
         Self {
             status: RwLock::new(Status::Idle),
             app_handle,
+            throughput: Mutex::new(Throughput::default()),
+            emit_throttle: Mutex::new(EmitThrottle::default()),
+            status_sender: broadcast::channel(STATUS_CHANNEL_CAPACITY).0,
         }
     }
 
-    pub async fn set_started(&self) -> tauri::Result<()> {
-        let new_status = Status::Started;
-        *self.status.write().await.deref_mut() = new_status.clone();
-        IndexingStatusChanged(new_status.clone()).emit_all(&self.app_handle)
+    pub async fn set_scanning(&self) -> tauri::Result<()> {
+        let now = Utc::now();
+        self.replace_status(Status::InProgress(Progress {
+            phase: IndexingPhase::Scanning,
+            total: 0,
+            indexing: None,
+            preindexing: PreIndexingProgress {
+                started_preindexing: now,
+                preindexed: 0,
+            },
+            resumed: false,
+            current_file: None,
+        }))
+        .await
     }
 
     pub async fn set_preindexing_started(&self, total: u32) -> tauri::Result<()> {
-        let new_status = Status::InProgress(Progress {
+        self.replace_status(Status::InProgress(Progress {
+            phase: IndexingPhase::Hashing,
             total,
             indexing: None,
             preindexing: PreIndexingProgress {
                 started_preindexing: Utc::now(),
                 preindexed: 0,
             },
-        });
-        *self.status.write().await.deref_mut() = new_status.clone();
-        IndexingStatusChanged(new_status.clone()).emit_all(&self.app_handle)
+            resumed: false,
+            current_file: None,
+        }))
+        .await
     }
 
-    pub async fn increment_preindexed(&self) -> Result<(), String> {
-        let mut status = self.status.write().await;
-        if let Status::InProgress(progress) = status.deref_mut() {
+    /// Skip straight to indexing the unfinished files of an interrupted job.
+    /// They were already preindexed by the run that was interrupted.
+    pub async fn set_resumed_indexing_started(&self, total_to_index: u32) -> tauri::Result<()> {
+        let now = Utc::now();
+        self.reset_throughput();
+        self.replace_status(Status::InProgress(Progress {
+            phase: IndexingPhase::Decoding,
+            total: total_to_index,
+            indexing: Some(IndexingProgress::new(now, total_to_index)),
+            preindexing: PreIndexingProgress {
+                started_preindexing: now,
+                preindexed: total_to_index,
+            },
+            resumed: true,
+            current_file: None,
+        }))
+        .await
+    }
+
+    pub async fn increment_preindexed(&self, file_path: &Path) -> Result<(), String> {
+        // TODO: could put this guard rail at the type level
+        self.update_progress(false, "Cannot increment preindex", |progress| {
             progress.preindexing.preindexed += 1;
+            progress.current_file = Some(display_path(file_path));
             trace!(
-                "indexed: {}, total: {}",
+                "preindexed: {}, total: {}",
                 progress.preindexing.preindexed,
                 progress.total,
             );
-            IndexingStatusChanged(status.clone())
-                .emit_all(&self.app_handle)
-                .map_err(|err| err.to_string())
-        } else {
-            // TODO: could put this guard rail at the type level
-            Err("Cannot increment preindex if not in progress".to_string())
-        }
+            Ok(())
+        })
+        .await
     }
 
     pub async fn set_indexing_started(&self, total_to_index: u32) -> Result<(), String> {
-        let mut status = self.status.write().await;
-        if let Status::InProgress(progress) = status.deref_mut() {
-            progress.indexing = Some(IndexingProgress {
-                started_indexing: Utc::now(),
-                newly_indexed: 0,
-                total_to_index,
-            });
-            IndexingStatusChanged(status.clone())
-                .emit_all(&self.app_handle)
-                .map_err(|err| err.to_string())
-        } else {
-            Err("Cannot set indexing started if not indexing".to_string())
-        }
+        self.reset_throughput();
+        self.update_progress(true, "Cannot set indexing started", |progress| {
+            progress.phase = IndexingPhase::Decoding;
+            progress.current_file = None;
+            progress.indexing = Some(IndexingProgress::new(Utc::now(), total_to_index));
+            Ok(())
+        })
+        .await
     }
 
-    pub async fn increment_n_indexed(&self, n: u32) -> Result<(), String> {
-        let mut status = self.status.write().await;
-        // If we're indexing, increment the number of indexed files
-        if let Status::InProgress(progress) = status.deref_mut() {
-            if let Some(indexing_progress) = &mut progress.indexing {
-                indexing_progress.newly_indexed += n;
-                trace!(
-                    "indexed: {}, total: {}, percent: {}",
-                    indexing_progress.newly_indexed,
-                    indexing_progress.total_to_index,
-                    indexing_progress.newly_indexed % (indexing_progress.total_to_index / 100)
-                );
-                IndexingStatusChanged(status.clone())
-                    .emit_all(&self.app_handle)
-                    .map_err(|err| err.to_string())
-            } else {
-                Err("Cannot increment indexed if not indexing".to_string())
-            }
-        } else {
-            Err("Cannot increment indexed if not in progress".to_string())
-        }
+    pub async fn set_phase(&self, phase: IndexingPhase) -> Result<(), String> {
+        self.update_progress(true, "Cannot set indexing phase", |progress| {
+            progress.phase = phase;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Note the file that was most recently handed to the model
+    pub async fn set_embedding_file(&self, file_path: &Path) -> Result<(), String> {
+        let is_new_phase = matches!(
+            &*self.status.read().await,
+            Status::InProgress(progress) if progress.phase != IndexingPhase::Embedding
+        );
+        self.update_progress(is_new_phase, "Cannot set embedding file", |progress| {
+            progress.phase = IndexingPhase::Embedding;
+            progress.current_file = Some(display_path(file_path));
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn record_file_indexed(&self, n_segments: u32) -> Result<(), String> {
+        self.record_file_finished(|indexing_progress| {
+            indexing_progress.newly_indexed += 1;
+            indexing_progress.segments_embedded += n_segments;
+        })
+        .await
+    }
+
+    pub async fn record_file_failed(&self) -> Result<(), String> {
+        self.record_file_finished(|indexing_progress| indexing_progress.failed += 1)
+            .await
+    }
+
+    async fn record_file_finished(
+        &self,
+        update: impl FnOnce(&mut IndexingProgress),
+    ) -> Result<(), String> {
+        self.update_progress(false, "Cannot record indexed file", |progress| {
+            let Some(indexing_progress) = &mut progress.indexing else {
+                return Err("Cannot record indexed file if not indexing".to_string());
+            };
+            update(indexing_progress);
+
+            let finished = indexing_progress.finished();
+            let mut throughput = self
+                .throughput
+                .lock()
+                .expect("Throughput lock should not be poisoned");
+            throughput.record(finished, Instant::now());
+            indexing_progress.files_per_second = throughput.per_second;
+            indexing_progress.eta_seconds =
+                throughput.eta_seconds(indexing_progress.total_to_index.saturating_sub(finished));
+            trace!(
+                "indexed: {}, failed: {}, total: {}, percent: {}",
+                indexing_progress.newly_indexed,
+                indexing_progress.failed,
+                indexing_progress.total_to_index,
+                finished * 100 / indexing_progress.total_to_index.max(1)
+            );
+            Ok(())
+        })
+        .await
     }
 
     pub async fn set_idle(&self) -> tauri::Result<()> {
-        let new_status = Status::Idle;
-        *self.status.write().await.deref_mut() = new_status.clone();
-        IndexingStatusChanged(new_status.clone()).emit_all(&self.app_handle)
+        self.replace_status(Status::Idle).await
     }
 
     pub async fn get_status(&self) -> Status {
         self.status.read().await.clone()
     }
 
+    /// Receive every status that's emitted to the frontend, for listeners outside of it
+    pub fn subscribe(&self) -> broadcast::Receiver<Status> {
+        self.status_sender.subscribe()
+    }
+
     pub async fn emit_status(&self) -> tauri::Result<()> {
         IndexingStatusChanged(self.get_status().await).emit_all(&self.app_handle)
     }
+
+    fn reset_throughput(&self) {
+        *self
+            .throughput
+            .lock()
+            .expect("Throughput lock should not be poisoned") = Throughput::default();
+    }
+
+    async fn replace_status(&self, new_status: Status) -> tauri::Result<()> {
+        *self.status.write().await.deref_mut() = new_status.clone();
+        self.emit_throttled(new_status, true)
+    }
+
+    async fn update_progress(
+        &self,
+        force_emit: bool,
+        error_context: &str,
+        update: impl FnOnce(&mut Progress) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut status = self.status.write().await;
+        let Status::InProgress(progress) = status.deref_mut() else {
+            return Err(format!("{} if not in progress", error_context));
+        };
+        update(progress)?;
+        self.emit_throttled(status.clone(), force_emit)
+            .map_err(|err| err.to_string())
+    }
+
+    fn emit_throttled(&self, status: Status, force: bool) -> tauri::Result<()> {
+        let should_emit = self
+            .emit_throttle
+            .lock()
+            .expect("Emit throttle lock should not be poisoned")
+            .should_emit(Instant::now(), force);
+        if should_emit {
+            // Sending only fails when nobody is subscribed
+            let _ = self.status_sender.send(status.clone());
+            IndexingStatusChanged(status).emit_all(&self.app_handle)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl IndexingProgress {
+    fn new(started_indexing: DateTime<Utc>, total_to_index: u32) -> Self {
+        Self {
+            started_indexing,
+            newly_indexed: 0,
+            failed: 0,
+            total_to_index,
+            segments_embedded: 0,
+            files_per_second: None,
+            eta_seconds: None,
+        }
+    }
+
+    fn finished(&self) -> u32 {
+        self.newly_indexed + self.failed
+    }
+}
+
+/// Exponential moving average of files finished per second
+#[derive(Debug, Default)]
+struct Throughput {
+    last_sample: Option<(Instant, u32)>,
+    per_second: Option<f64>,
+}
+
+impl Throughput {
+    fn record(&mut self, finished: u32, now: Instant) {
+        let Some((sampled_at, sampled_finished)) = self.last_sample else {
+            self.last_sample = Some((now, finished));
+            return;
+        };
+        let elapsed = now.duration_since(sampled_at);
+        if elapsed < THROUGHPUT_SAMPLE_INTERVAL {
+            return;
+        }
+        let sample = finished.saturating_sub(sampled_finished) as f64 / elapsed.as_secs_f64();
+        self.per_second = Some(match self.per_second {
+            Some(average) => average + THROUGHPUT_SMOOTHING * (sample - average),
+            None => sample,
+        });
+        self.last_sample = Some((now, finished));
+    }
+
+    fn eta_seconds(&self, remaining: u32) -> Option<f64> {
+        self.per_second
+            .filter(|per_second| *per_second > 0.0)
+            .map(|per_second| remaining as f64 / per_second)
+    }
+}
+
+#[derive(Debug, Default)]
+struct EmitThrottle {
+    last_emitted: Option<Instant>,
+}
+
+impl EmitThrottle {
+    fn should_emit(&mut self, now: Instant, force: bool) -> bool {
+        let too_soon = self
+            .last_emitted
+            .is_some_and(|last_emitted| now.duration_since(last_emitted) < MIN_EMIT_INTERVAL);
+        if too_soon && !force {
+            return false;
+        }
+        self.last_emitted = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throughput_waits_for_a_full_sample() {
+        let start = Instant::now();
+        let mut throughput = Throughput::default();
+        throughput.record(0, start);
+        throughput.record(5, start + Duration::from_millis(500));
+        assert_eq!(throughput.per_second, None);
+        assert_eq!(throughput.eta_seconds(10), None);
+
+        throughput.record(10, start + Duration::from_secs(2));
+        assert_eq!(throughput.per_second, Some(5.0));
+        assert_eq!(throughput.eta_seconds(10), Some(2.0));
+    }
+
+    #[test]
+    fn test_throughput_is_smoothed() {
+        let start = Instant::now();
+        let mut throughput = Throughput::default();
+        throughput.record(0, start);
+        throughput.record(10, start + Duration::from_secs(1));
+        throughput.record(10, start + Duration::from_secs(2));
+        let per_second = throughput.per_second.unwrap();
+        assert!((per_second - 10.0 * (1.0 - THROUGHPUT_SMOOTHING)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_emit_throttle() {
+        let start = Instant::now();
+        let mut throttle = EmitThrottle::default();
+        assert!(throttle.should_emit(start, false));
+        assert!(!throttle.should_emit(start + Duration::from_millis(10), false));
+        assert!(throttle.should_emit(start + Duration::from_millis(20), true));
+        assert!(!throttle.should_emit(start + MIN_EMIT_INTERVAL, false));
+        assert!(throttle.should_emit(start + Duration::from_millis(20) + MIN_EMIT_INTERVAL, false));
+    }
 }
@@ -0,0 +1,191 @@
+//! Parity tests against the reference Python CLAP pipeline.
+//! The fixtures in test_resources/parity are produced by the HF `ClapFeatureExtractor`
+//! and the exported ONNX audio model. Regenerate them with
+//! `clap_export/generate_parity_fixtures.py`.
+
+use std::sync::Arc;
+
+use super::tests::{create_local_audio_embedder, get_local_path};
+use super::*;
+use crate::embeddings::cosine_similarity;
+
+const PARITY_FIXTURES_DIR: &str = "test_resources/parity";
+/// Maximum difference between any two mel bins, in dB
+const MEL_SPEC_TOLERANCE_DB: f64 = 0.05;
+const EMBEDDING_MIN_COSINE_SIMILARITY: f32 = 0.999;
+
+#[derive(serde::Deserialize)]
+struct ParityManifest {
+    cases: Vec<ParityCase>,
+}
+
+#[derive(serde::Deserialize)]
+struct ParityCase {
+    name: String,
+    description: String,
+    /// Where the reference pipeline cropped clips longer than 10 seconds
+    crop_start: Option<usize>,
+}
+
+fn load_parity_case(name: &str) -> ParityCase {
+    let manifest_path = get_local_path(&format!("{}/manifest.json", PARITY_FIXTURES_DIR))
+        .expect("Should get local path");
+    let manifest_file = File::open(&manifest_path).unwrap_or_else(|_| {
+        panic!(
+            "Missing parity fixtures at {}. Generate them with clap_export/generate_parity_fixtures.py",
+            manifest_path
+        )
+    });
+    let manifest: ParityManifest =
+        serde_json::from_reader(BufReader::new(manifest_file)).expect("Manifest should parse");
+    manifest
+        .cases
+        .into_iter()
+        .find(|case| case.name == name)
+        .unwrap_or_else(|| panic!("Parity case {} should be in the manifest", name))
+}
+
+/// Read a raw little-endian f32 fixture
+fn read_f32_fixture(filename: &str) -> Vec<f32> {
+    let path = get_local_path(&format!("{}/{}", PARITY_FIXTURES_DIR, filename))
+        .expect("Should get local path");
+    let bytes = std::fs::read(&path).unwrap_or_else(|_| panic!("Should read {}", path));
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+/// The case's input audio, cropped the same way as the reference pipeline
+fn load_parity_pcm(case: &ParityCase) -> Vec<f32> {
+    let path = get_local_path(&format!("{}/{}.wav", PARITY_FIXTURES_DIR, case.name))
+        .expect("Should get local path");
+    let wav_reader = WavReader::open(&path).expect("Should open parity .wav");
+    assert_eq!(wav_reader.spec().sample_rate, TARGET_SAMPLE_RATE);
+    let pcm_audio = read_wav_samples(wav_reader).expect("Should read parity .wav");
+    match case.crop_start {
+        Some(crop_start) => {
+            let segment_length_samples = (TARGET_SAMPLE_RATE as f32 * SEGMENT_LENGTH) as usize;
+            pcm_audio[crop_start..crop_start + segment_length_samples].to_vec()
+        }
+        None => pcm_audio,
+    }
+}
+
+fn assert_mel_spec_parity(name: &str) {
+    let case = load_parity_case(name);
+    let pcm_audio = load_parity_pcm(&case);
+    let reference = read_f32_fixture(&format!("{}.input_features.f32", case.name));
+
+    let mel_spec = compute_mel_spec_from_pcm(&pcm_audio, ClapModel::HtsatUnfused)
+        .expect("Mel spec should be computed")
+        .input_features;
+    assert_eq!(
+        mel_spec.len(),
+        reference.len(),
+        "{}: mel spec of shape {:?} should have as many values as the reference",
+        case.description,
+        mel_spec.shape()
+    );
+    let max_difference = mel_spec
+        .iter()
+        .zip(reference.iter())
+        .map(|(value, reference)| (value - *reference as f64).abs())
+        .fold(0.0, f64::max);
+    assert!(
+        max_difference < MEL_SPEC_TOLERANCE_DB,
+        "{}: mel spec differs from the reference by up to {}dB",
+        case.description,
+        max_difference
+    );
+}
+
+/// The ONNX models are gitignored, so the embedding tests are ignored by default.
+/// Run them with `cargo test -- --ignored` once the model has been exported.
+fn assert_audio_model_is_exported() {
+    let model_path = get_local_path(&format!(
+        "onnx_models/{}_audio_with_projection.onnx",
+        ClapModel::HtsatUnfused.model_name()
+    ))
+    .expect("Should get local path");
+    assert!(
+        Path::new(&model_path).exists(),
+        "No model at {}. Export it with clap_export first",
+        model_path
+    );
+}
+
+async fn assert_embedding_parity(name: &str) {
+    assert_audio_model_is_exported();
+    let case = load_parity_case(name);
+    let pcm_audio = load_parity_pcm(&case);
+    let reference = read_f32_fixture(&format!("{}.embedding.f32", case.name));
+
+    let audio_embedder = Arc::new(create_local_audio_embedder());
+    let processing_handle = tokio::spawn({
+        let audio_embedder = audio_embedder.clone();
+        async move { audio_embedder.begin_processing_queue().await }
+    });
+    let embedding = compute_embedding_from_pcm(&pcm_audio, &audio_embedder)
+        .await
+        .expect("Embedding should be computed");
+    audio_embedder.stop_processing_queue();
+    processing_handle
+        .await
+        .expect("Processing queue should join")
+        .expect("Processing queue should succeed");
+
+    assert_eq!(embedding.len(), reference.len());
+    let similarity = cosine_similarity(&embedding, &reference);
+    assert!(
+        similarity > EMBEDDING_MIN_COSINE_SIMILARITY,
+        "{}: embedding has cosine similarity {} with the reference",
+        case.description,
+        similarity
+    );
+}
+
+#[test]
+fn test_mel_spec_parity_repeat_padding() {
+    assert_mel_spec_parity("repeatpad_3s");
+}
+
+#[test]
+fn test_mel_spec_parity_exact_length() {
+    assert_mel_spec_parity("exact_10s");
+}
+
+#[test]
+fn test_mel_spec_parity_truncation() {
+    assert_mel_spec_parity("truncation_14s");
+}
+
+#[test]
+fn test_mel_spec_parity_silence() {
+    assert_mel_spec_parity("silence_10s");
+    assert_mel_spec_parity("silence_1s");
+}
+
+#[tokio::test]
+#[ignore = "needs the exported ONNX audio model; run clap_export first"]
+async fn test_embedding_parity_repeat_padding() {
+    assert_embedding_parity("repeatpad_3s").await;
+}
+
+#[tokio::test]
+#[ignore = "needs the exported ONNX audio model; run clap_export first"]
+async fn test_embedding_parity_exact_length() {
+    assert_embedding_parity("exact_10s").await;
+}
+
+#[tokio::test]
+#[ignore = "needs the exported ONNX audio model; run clap_export first"]
+async fn test_embedding_parity_truncation() {
+    assert_embedding_parity("truncation_14s").await;
+}
+
+#[tokio::test]
+#[ignore = "needs the exported ONNX audio model; run clap_export first"]
+async fn test_embedding_parity_silence() {
+    assert_embedding_parity("silence_10s").await;
+}
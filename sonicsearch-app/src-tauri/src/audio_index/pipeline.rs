@@ -0,0 +1,512 @@
+//! Staged indexing pipeline: decode → resample → mel → embed → write.
+//!
+//! The CPU-heavy stages run on their own threads rather than the async runtime,
+//! and every stage is connected to the next by a bounded channel. Files stream
+//! through, so the embedder always has segments from several files queued.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use futures::stream::{FuturesUnordered, StreamExt};
+use log::{debug, info, warn};
+use sqlx::SqlitePool;
+use tauri::async_runtime::RwLock;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+use super::index_error;
+use super::index_job::{self, IndexJobState};
+use super::indexing_status::{IndexingPhase, IndexingStatus};
+use super::segmentation::SegmentationStrategy;
+use super::waveform::{compute_overview, WaveformOverview};
+use super::{
+    decode_audio_file, embed_segment_features, get_file_name, insert_indexed_file,
+    resample_to_target_rate, segment_and_extract_features, DecodedAudio, FileSegmentWithEmbedding,
+    LoadedAudioFile, SegmentFeatures, TARGET_SAMPLE_RATE,
+};
+use crate::state::audio_embedder::AudioEmbedder;
+use crate::state::database::vector_index::{self, VectorIndex};
+
+/// Synchronize the vector index after this many files have been written,
+/// so new files become searchable while indexing continues
+const SYNCHRONIZE_INTERVAL_FILES: usize = 10;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PipelineConfig {
+    pub decode_workers: usize,
+    pub resample_workers: usize,
+    pub mel_workers: usize,
+    /// How many files may be waiting on the embedder at once
+    pub files_embedding: usize,
+    /// Capacity of the channel in front of each stage, in files
+    pub channel_capacity: usize,
+    /// How the mel stage splits files into segments
+    pub segmentation: SegmentationStrategy,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        let n_cpus = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4);
+        Self {
+            // Decoding is mostly I/O
+            decode_workers: 2,
+            resample_workers: (n_cpus / 4).max(1),
+            mel_workers: (n_cpus / 2).max(1),
+            files_embedding: 8,
+            channel_capacity: 4,
+            segmentation: SegmentationStrategy::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PipelineStage {
+    /// Hashing a file found by the scan, before it enters the pipeline
+    Hash,
+    /// Checking a hashed file against the index, before it enters the pipeline
+    Preindex,
+    Decode,
+    Resample,
+    Mel,
+    Embed,
+    Write,
+}
+
+impl fmt::Display for PipelineStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PipelineStage::Hash => "hash",
+            PipelineStage::Preindex => "preindex",
+            PipelineStage::Decode => "decode",
+            PipelineStage::Resample => "resample",
+            PipelineStage::Mel => "mel",
+            PipelineStage::Embed => "embed",
+            PipelineStage::Write => "write",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A file that dropped out of the pipeline. Failures are passed down the
+/// remaining stages untouched so the write stage can account for every file.
+#[derive(Debug)]
+pub struct FileFailure {
+    pub file_path: PathBuf,
+    /// Empty if the file couldn't be hashed
+    pub file_hash: String,
+    pub stage: PipelineStage,
+    pub error: anyhow::Error,
+}
+
+impl FileFailure {
+    fn new(audio_file: &LoadedAudioFile, stage: PipelineStage, error: anyhow::Error) -> Self {
+        Self {
+            file_path: audio_file.file_path.clone(),
+            file_hash: audio_file.file_hash.clone(),
+            stage,
+            error,
+        }
+    }
+}
+
+type StageResult<T> = std::result::Result<T, FileFailure>;
+
+struct Decoded {
+    audio_file: LoadedAudioFile,
+    decoded_audio: DecodedAudio,
+}
+
+struct Resampled {
+    audio_file: LoadedAudioFile,
+    overview: WaveformOverview,
+    pcm_audio: Vec<f32>,
+}
+
+struct Featurized {
+    audio_file: LoadedAudioFile,
+    duration_seconds: f64,
+    overview: WaveformOverview,
+    segment_features: Vec<SegmentFeatures>,
+}
+
+struct Embedded {
+    audio_file: LoadedAudioFile,
+    duration_seconds: f64,
+    overview: WaveformOverview,
+    segments_with_embeddings: Vec<FileSegmentWithEmbedding>,
+}
+
+#[derive(Debug, Default)]
+pub struct PipelineSummary {
+    pub n_indexed: usize,
+    pub n_segments: usize,
+    pub failures: Vec<FileFailure>,
+}
+
+/// Run `audio_files` through every stage and write them to the database.
+/// The embedder's processing queue must be running for this to make progress.
+pub async fn run_indexing_pipeline(
+    audio_files: Vec<LoadedAudioFile>,
+    pool: &SqlitePool,
+    audio_embedder: &AudioEmbedder,
+    indexing_status: &IndexingStatus,
+    vector_index: &RwLock<VectorIndex>,
+    config: PipelineConfig,
+) -> Result<PipelineSummary> {
+    info!("Indexing {} files with {:?}", audio_files.len(), config);
+    let (files_sender, files_receiver) = mpsc::channel(config.channel_capacity);
+    let (decoded_sender, decoded_receiver) = mpsc::channel(config.channel_capacity);
+    let (resampled_sender, resampled_receiver) = mpsc::channel(config.channel_capacity);
+    let (featurized_sender, featurized_receiver) = mpsc::channel(config.channel_capacity);
+    let (embedded_sender, embedded_receiver) = mpsc::channel(config.channel_capacity);
+
+    let model = audio_embedder.model;
+    let segmentation = config.segmentation;
+    let mut worker_handles = vec![];
+    worker_handles.extend(spawn_blocking_stage(
+        PipelineStage::Decode,
+        config.decode_workers,
+        files_receiver,
+        decoded_sender,
+        |audio_file: LoadedAudioFile| match decode_audio_file(&audio_file) {
+            Ok(decoded_audio) => Ok(Decoded {
+                audio_file,
+                decoded_audio,
+            }),
+            Err(err) => Err(FileFailure::new(&audio_file, PipelineStage::Decode, err)),
+        },
+    )?);
+    worker_handles.extend(spawn_blocking_stage(
+        PipelineStage::Resample,
+        config.resample_workers,
+        decoded_receiver,
+        resampled_sender,
+        |decoded: Decoded| {
+            // From the samples at their original rate, since resampling doesn't change the shape
+            let overview = compute_overview(&decoded.decoded_audio.samples);
+            match resample_to_target_rate(&decoded.audio_file, decoded.decoded_audio) {
+                Ok(pcm_audio) => Ok(Resampled {
+                    audio_file: decoded.audio_file,
+                    overview,
+                    pcm_audio,
+                }),
+                Err(err) => Err(FileFailure::new(
+                    &decoded.audio_file,
+                    PipelineStage::Resample,
+                    err,
+                )),
+            }
+        },
+    )?);
+    worker_handles.extend(spawn_blocking_stage(
+        PipelineStage::Mel,
+        config.mel_workers,
+        resampled_receiver,
+        featurized_sender,
+        move |resampled: Resampled| match segment_and_extract_features(
+            &resampled.audio_file,
+            &resampled.pcm_audio,
+            model,
+            segmentation,
+        ) {
+            Ok(segment_features) => Ok(Featurized {
+                audio_file: resampled.audio_file,
+                duration_seconds: resampled.pcm_audio.len() as f64 / TARGET_SAMPLE_RATE as f64,
+                overview: resampled.overview,
+                segment_features,
+            }),
+            Err(err) => Err(FileFailure::new(
+                &resampled.audio_file,
+                PipelineStage::Mel,
+                err,
+            )),
+        },
+    )?);
+
+    let feed_stage = async move {
+        for audio_file in audio_files {
+            if files_sender.send(Ok(audio_file)).await.is_err() {
+                warn!("Decode stage stopped before all files were sent");
+                break;
+            }
+        }
+    };
+    let embed_stage = embed_stage(
+        featurized_receiver,
+        embedded_sender,
+        pool,
+        audio_embedder,
+        indexing_status,
+        config.files_embedding,
+    );
+    let write_stage = write_stage(embedded_receiver, pool, indexing_status, vector_index);
+    let ((), (), summary) = tokio::join!(feed_stage, embed_stage, write_stage);
+
+    // Every channel has closed by the time the write stage finishes, so the workers are exiting
+    for handle in worker_handles {
+        if handle.join().is_err() {
+            warn!("An indexing worker panicked");
+        }
+    }
+    Ok(summary)
+}
+
+/// Spawn `n_workers` threads that take items from `input`, apply `process`,
+/// and send the results to `output`. Failures from earlier stages pass straight through.
+/// The threads exit once `input` is closed and drained.
+fn spawn_blocking_stage<I, O, F>(
+    stage: PipelineStage,
+    n_workers: usize,
+    input: Receiver<StageResult<I>>,
+    output: Sender<StageResult<O>>,
+    process: F,
+) -> Result<Vec<JoinHandle<()>>>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+    F: Fn(I) -> StageResult<O> + Send + Sync + 'static,
+{
+    let input = Arc::new(Mutex::new(input));
+    let process = Arc::new(process);
+    (0..n_workers.max(1))
+        .map(|worker_i| {
+            let input = input.clone();
+            let output = output.clone();
+            let process = process.clone();
+            thread::Builder::new()
+                .name(format!("index-{}-{}", stage, worker_i))
+                .spawn(move || loop {
+                    // Only hold the lock while waiting for the next item, not while processing it
+                    let item = input
+                        .lock()
+                        .expect("Stage input lock should not be poisoned")
+                        .blocking_recv();
+                    let Some(item) = item else {
+                        break;
+                    };
+                    let result = item.and_then(|item| process(item));
+                    if output.blocking_send(result).is_err() {
+                        warn!("{} stage output closed early", stage);
+                        break;
+                    }
+                })
+                .context(format!("Failed to spawn {} worker", stage))
+        })
+        .collect()
+}
+
+/// Keep up to `files_embedding` files queued on the embedder, so its batches stay full
+async fn embed_stage(
+    mut input: Receiver<StageResult<Featurized>>,
+    output: Sender<StageResult<Embedded>>,
+    pool: &SqlitePool,
+    audio_embedder: &AudioEmbedder,
+    indexing_status: &IndexingStatus,
+    files_embedding: usize,
+) {
+    let mut in_flight = FuturesUnordered::new();
+    let mut input_open = true;
+    loop {
+        tokio::select! {
+            item = input.recv(), if input_open && in_flight.len() < files_embedding => {
+                match item {
+                    Some(item) => in_flight.push(embed_file(item, pool, audio_embedder, indexing_status)),
+                    None => input_open = false,
+                }
+            }
+            Some(result) = in_flight.next(), if !in_flight.is_empty() => {
+                if output.send(result).await.is_err() {
+                    warn!("Write stage stopped before all files were embedded");
+                    break;
+                }
+            }
+            else => break,
+        }
+    }
+}
+
+async fn embed_file(
+    item: StageResult<Featurized>,
+    pool: &SqlitePool,
+    audio_embedder: &AudioEmbedder,
+    indexing_status: &IndexingStatus,
+) -> StageResult<Embedded> {
+    let featurized = item?;
+    set_job_state(
+        pool,
+        &featurized.audio_file.file_path,
+        IndexJobState::Embedding,
+    )
+    .await;
+    if let Err(err) = indexing_status
+        .set_embedding_file(&featurized.audio_file.file_path)
+        .await
+    {
+        warn!("Failed to set embedding file in indexing status: {:?}", err);
+    }
+    match embed_segment_features(
+        &featurized.audio_file,
+        featurized.segment_features,
+        audio_embedder,
+    )
+    .await
+    {
+        Ok(segments_with_embeddings) => Ok(Embedded {
+            audio_file: featurized.audio_file,
+            duration_seconds: featurized.duration_seconds,
+            overview: featurized.overview,
+            segments_with_embeddings,
+        }),
+        Err(err) => Err(FileFailure::new(
+            &featurized.audio_file,
+            PipelineStage::Embed,
+            err,
+        )),
+    }
+}
+
+async fn write_stage(
+    mut input: Receiver<StageResult<Embedded>>,
+    pool: &SqlitePool,
+    indexing_status: &IndexingStatus,
+    vector_index: &RwLock<VectorIndex>,
+) -> PipelineSummary {
+    let mut summary = PipelineSummary::default();
+    let mut written_since_synchronize = 0;
+    while let Some(result) = input.recv().await {
+        let write_result = match result {
+            Ok(embedded) => {
+                let n_segments = embedded.segments_with_embeddings.len() as u32;
+                match insert_indexed_file(
+                    pool,
+                    &embedded.audio_file,
+                    embedded.duration_seconds,
+                    &embedded.overview,
+                    embedded.segments_with_embeddings,
+                )
+                .await
+                {
+                    Ok(()) => Ok((embedded.audio_file, n_segments)),
+                    Err(err) => Err(FileFailure::new(
+                        &embedded.audio_file,
+                        PipelineStage::Write,
+                        err,
+                    )),
+                }
+            }
+            Err(failure) => Err(failure),
+        };
+        let status_result = match write_result {
+            Ok((audio_file, n_segments)) => {
+                debug!("Indexed {}", get_file_name(&audio_file.file_path));
+                set_job_state(pool, &audio_file.file_path, IndexJobState::Done).await;
+                if let Err(err) = index_error::clear_error(pool, &audio_file.file_path).await {
+                    warn!("{:?}", err);
+                }
+                summary.n_indexed += 1;
+                summary.n_segments += n_segments as usize;
+                written_since_synchronize += 1;
+                indexing_status.record_file_indexed(n_segments).await
+            }
+            Err(failure) => {
+                warn!(
+                    "Failed to index {:?} at the {} stage: {:?}",
+                    failure.file_path, failure.stage, failure.error
+                );
+                set_job_state(pool, &failure.file_path, IndexJobState::Failed).await;
+                if let Err(err) = index_error::record_failure(pool, &failure, Utc::now()).await {
+                    warn!("{:?}", err);
+                }
+                summary.failures.push(failure);
+                indexing_status.record_file_failed().await
+            }
+        };
+        if let Err(err) = status_result {
+            warn!("Failed to update indexing status: {:?}", err);
+        }
+        if written_since_synchronize >= SYNCHRONIZE_INTERVAL_FILES {
+            synchronize_vector_index(pool, vector_index).await;
+            written_since_synchronize = 0;
+        }
+    }
+    if let Err(err) = indexing_status.set_phase(IndexingPhase::SyncingIndex).await {
+        warn!("Failed to set indexing status to syncing index: {:?}", err);
+    }
+    synchronize_vector_index(pool, vector_index).await;
+    summary
+}
+
+async fn set_job_state(pool: &SqlitePool, file_path: &Path, state: IndexJobState) {
+    if let Err(err) = index_job::set_file_state(pool, file_path, state).await {
+        warn!("Failed to update index job: {:?}", err);
+    }
+}
+
+async fn synchronize_vector_index(pool: &SqlitePool, vector_index: &RwLock<VectorIndex>) {
+    let mut vector_index_lock = vector_index.write().await;
+    if let Err(err) = vector_index::synchronize_index(pool, &mut vector_index_lock).await {
+        warn!("Failed to synchronize index during indexing: {:?}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocking_stage_processes_items_and_passes_failures_through() {
+        let (input_sender, input_receiver) = mpsc::channel(2);
+        let (output_sender, mut output_receiver) = mpsc::channel(2);
+        let handles = spawn_blocking_stage(
+            PipelineStage::Mel,
+            3,
+            input_receiver,
+            output_sender,
+            |n: u32| Ok(n * 2),
+        )
+        .expect("Workers should spawn");
+
+        let failed_file = LoadedAudioFile {
+            file_hash: "fake_hash".to_string(),
+            file_path: PathBuf::from("failed.wav"),
+        };
+        let feeder = thread::spawn(move || {
+            for n in 0..10 {
+                input_sender
+                    .blocking_send(Ok(n))
+                    .expect("Stage should accept input");
+            }
+            input_sender
+                .blocking_send(Err(FileFailure::new(
+                    &failed_file,
+                    PipelineStage::Decode,
+                    anyhow::anyhow!("Failed to decode"),
+                )))
+                .expect("Stage should accept input");
+        });
+
+        let mut outputs = vec![];
+        let mut failures = vec![];
+        while let Some(result) = output_receiver.blocking_recv() {
+            match result {
+                Ok(n) => outputs.push(n),
+                Err(failure) => failures.push(failure),
+            }
+        }
+        feeder.join().expect("Feeder should finish");
+        for handle in handles {
+            handle.join().expect("Worker should finish");
+        }
+
+        outputs.sort();
+        assert_eq!(outputs, (0..10).map(|n| n * 2).collect::<Vec<_>>());
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].stage, PipelineStage::Decode);
+        assert_eq!(failures[0].file_path, Path::new("failed.wav"));
+    }
+}
@@ -0,0 +1,285 @@
+//! Finds the audio files under each root, honouring its RootOptions.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use hound::WavReader;
+use log::{debug, warn};
+use walkdir::WalkDir;
+
+use super::is_audio_file;
+use crate::index_paths::{IndexRoot, RootOptions};
+
+/// Walk every enabled, online root, returning each audio file that its options let through.
+/// Roots nested inside another root override its options for their subtree,
+/// so every file is visited once, by its innermost root.
+pub(super) fn scan_roots(roots: &[IndexRoot]) -> Result<Vec<PathBuf>> {
+    let mut seen_files = HashSet::new();
+    let mut audio_files = vec![];
+    for root in roots
+        .iter()
+        .filter(|root| root.options.enabled && root.online)
+    {
+        let nested_roots = roots
+            .iter()
+            .map(|other| other.path.as_path())
+            .filter(|other| *other != root.path && other.starts_with(&root.path))
+            .collect::<Vec<_>>();
+        for path in scan_root(root, &nested_roots)? {
+            // Followed symlinks can lead to the same file twice, under different paths.
+            // Roots are canonical, so any other path already is
+            let canonical_path = match root.options.follow_symlinks {
+                true => path.canonicalize().unwrap_or_else(|_| path.clone()),
+                false => path.clone(),
+            };
+            if seen_files.insert(canonical_path) {
+                audio_files.push(path);
+            }
+        }
+    }
+    Ok(audio_files)
+}
+
+/// Walk `root`, skipping the subtrees of `nested_roots`
+fn scan_root(root: &IndexRoot, nested_roots: &[&Path]) -> Result<Vec<PathBuf>> {
+    let filter = RootFilter::new(&root.path, &root.options)?;
+    let mut walk_dir = WalkDir::new(&root.path).follow_links(root.options.follow_symlinks);
+    if let Some(max_depth) = root.options.max_depth {
+        walk_dir = walk_dir.max_depth(max_depth as usize);
+    }
+    let walk_dir = walk_dir
+        .into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !nested_roots.contains(&entry.path()));
+
+    let mut audio_files = vec![];
+    for entry in walk_dir {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                warn!("Skipping unreadable entry in {:?}: {}", root.path, err);
+                continue;
+            }
+        };
+        let path = entry.path();
+        // Only a followed link has its target's file type, unlike `Path::is_file`
+        if !entry.file_type().is_file() || !is_audio_file(path) {
+            continue;
+        }
+        if !filter.matches_path(path) {
+            debug!("{:?} is excluded by the options of {:?}", path, root.path);
+            continue;
+        }
+        if !filter.matches_duration(path) {
+            debug!(
+                "{:?} is outside the duration limits of {:?}",
+                path, root.path
+            );
+            continue;
+        }
+        audio_files.push(path.to_owned());
+    }
+    Ok(audio_files)
+}
+
+struct RootFilter<'a> {
+    root: &'a Path,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    min_duration_seconds: Option<f64>,
+    max_duration_seconds: Option<f64>,
+}
+
+impl<'a> RootFilter<'a> {
+    fn new(root: &'a Path, options: &RootOptions) -> Result<Self> {
+        let include = match options.include_globs.is_empty() {
+            true => None,
+            false => Some(build_glob_set(&options.include_globs)?),
+        };
+        Ok(Self {
+            root,
+            include,
+            exclude: build_glob_set(&options.exclude_globs)?,
+            min_duration_seconds: options.min_duration_seconds,
+            max_duration_seconds: options.max_duration_seconds,
+        })
+    }
+
+    fn matches_path(&self, path: &Path) -> bool {
+        let relative_path = path.strip_prefix(self.root).unwrap_or(path);
+        let included = self
+            .include
+            .as_ref()
+            .map(|include| include.is_match(relative_path))
+            .unwrap_or(true);
+        included && !self.exclude.is_match(relative_path)
+    }
+
+    /// Files whose duration can't be read are let through, so the indexer can report why
+    fn matches_duration(&self, path: &Path) -> bool {
+        if self.min_duration_seconds.is_none() && self.max_duration_seconds.is_none() {
+            return true;
+        }
+        let Some(duration_seconds) = read_duration_seconds(path) else {
+            return true;
+        };
+        let long_enough = self
+            .min_duration_seconds
+            .map(|min| duration_seconds >= min)
+            .unwrap_or(true);
+        let short_enough = self
+            .max_duration_seconds
+            .map(|max| duration_seconds <= max)
+            .unwrap_or(true);
+        long_enough && short_enough
+    }
+}
+
+fn build_glob_set(globs: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).context(format!("Invalid glob {}", glob))?);
+    }
+    builder.build().context("Failed to build glob set")
+}
+
+/// Read the duration from the file's header, without decoding it
+fn read_duration_seconds(path: &Path) -> Option<f64> {
+    let wav_reader = WavReader::open(path).ok()?;
+    let sample_rate = wav_reader.spec().sample_rate;
+    (sample_rate > 0).then(|| wav_reader.duration() as f64 / sample_rate as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(include_globs: &[&str], exclude_globs: &[&str]) -> RootOptions {
+        RootOptions {
+            include_globs: include_globs.iter().map(|glob| glob.to_string()).collect(),
+            exclude_globs: exclude_globs.iter().map(|glob| glob.to_string()).collect(),
+            ..RootOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_exclude_globs() {
+        let root = Path::new("/Sounds");
+        let options = options(&[], &["**/Bounces/**", "*_preview.wav"]);
+        let filter = RootFilter::new(root, &options).unwrap();
+        assert!(filter.matches_path(Path::new("/Sounds/Foley/steps.wav")));
+        assert!(!filter.matches_path(Path::new("/Sounds/Bounces/mix.wav")));
+        assert!(!filter.matches_path(Path::new("/Sounds/Music/Bounces/mix.wav")));
+        assert!(!filter.matches_path(Path::new("/Sounds/Foley/steps_preview.wav")));
+    }
+
+    #[test]
+    fn test_include_globs() {
+        let root = Path::new("/Sounds");
+        let options = options(&["Foley/**"], &["**/*_alt.wav"]);
+        let filter = RootFilter::new(root, &options).unwrap();
+        assert!(filter.matches_path(Path::new("/Sounds/Foley/steps.wav")));
+        assert!(!filter.matches_path(Path::new("/Sounds/Music/song.wav")));
+        assert!(!filter.matches_path(Path::new("/Sounds/Foley/steps_alt.wav")));
+    }
+
+    #[test]
+    fn test_duration_limits() {
+        let root = Path::new("test_resources");
+        let wav_path = Path::new("test_resources/audio/audio_00.wav");
+        let duration_seconds = read_duration_seconds(wav_path).expect("Duration should be read");
+        let filter_for = |min_duration_seconds, max_duration_seconds| RootOptions {
+            min_duration_seconds,
+            max_duration_seconds,
+            ..RootOptions::default()
+        };
+
+        let options = filter_for(Some(duration_seconds + 1.0), None);
+        assert!(!RootFilter::new(root, &options)
+            .unwrap()
+            .matches_duration(wav_path));
+        let options = filter_for(None, Some(duration_seconds - 0.5));
+        assert!(!RootFilter::new(root, &options)
+            .unwrap()
+            .matches_duration(wav_path));
+        let options = filter_for(Some(0.0), Some(duration_seconds + 1.0));
+        assert!(RootFilter::new(root, &options)
+            .unwrap()
+            .matches_duration(wav_path));
+    }
+
+    #[test]
+    fn test_nested_roots_are_scanned_once() {
+        let root = |path: &str, options: RootOptions| IndexRoot {
+            path: PathBuf::from(path),
+            options,
+            parent: None,
+            online: true,
+            last_seen_at: None,
+        };
+        let parent = root("test_resources/audio", RootOptions::default());
+        let nested = root("test_resources/audio/bit_depths", RootOptions::default());
+        let parent_files = scan_roots(std::slice::from_ref(&parent)).unwrap();
+        let files = scan_roots(&[nested.clone(), parent.clone()]).unwrap();
+        assert!(files.iter().any(|path| path.starts_with(&nested.path)));
+        assert_eq!(files.len(), parent_files.len());
+        assert_eq!(files.len(), files.iter().collect::<HashSet<_>>().len());
+
+        // The nested root's options win for its subtree
+        let excluding_nested = root("test_resources/audio/bit_depths", options(&[], &["*"]));
+        let files = scan_roots(&[parent, excluding_nested]).unwrap();
+        assert!(!files.iter().any(|path| path.starts_with(&nested.path)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_files_are_only_scanned_when_followed() {
+        let dir = std::env::temp_dir().join(format!("scanner_symlinks_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = Path::new("test_resources/audio/audio_00.wav")
+            .canonicalize()
+            .unwrap();
+        let link = dir.join("linked.wav");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(target, &link).unwrap();
+
+        let mut root = IndexRoot {
+            path: dir.clone(),
+            options: RootOptions::default(),
+            parent: None,
+            online: true,
+            last_seen_at: None,
+        };
+        assert!(scan_roots(std::slice::from_ref(&root)).unwrap().is_empty());
+        root.options.follow_symlinks = true;
+        assert_eq!(scan_roots(std::slice::from_ref(&root)).unwrap(), vec![link]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_files_reached_through_symlinks_are_scanned_once() {
+        let dir = std::env::temp_dir().join(format!("scanner_duplicates_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("steps.wav");
+        std::fs::copy("test_resources/audio/audio_00.wav", &file).unwrap();
+        std::os::unix::fs::symlink(&file, dir.join("steps_link.wav")).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("loop")).unwrap();
+
+        let root = IndexRoot {
+            path: dir.canonicalize().unwrap(),
+            options: RootOptions {
+                follow_symlinks: true,
+                max_depth: Some(2),
+                ..RootOptions::default()
+            },
+            parent: None,
+            online: true,
+            last_seen_at: None,
+        };
+        assert_eq!(scan_roots(&[root]).unwrap().len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,326 @@
+use std::{cmp, result};
+
+use anyhow::{Context, Result};
+use log::debug;
+use sqlx::SqlitePool;
+use tauri::State;
+
+use super::{FileSegment, TARGET_SAMPLE_RATE};
+use crate::state::AppState;
+
+/// Size of the frames used to compute the energy envelope (~21ms at 48kHz)
+const FRAME_SIZE: usize = 1024;
+/// Hop between energy frames (10ms at 48kHz)
+const HOP_SIZE: usize = 480;
+/// Frames quieter than this (in dBFS) are treated as silence
+const SILENCE_THRESHOLD_DB: f32 = -60.0;
+/// Silent gaps shorter than this do not split a region of sound
+const MIN_SILENCE_DURATION: f32 = 0.5; // seconds
+/// How far a frame must rise above the recent average to count as an onset
+const ONSET_THRESHOLD_DB: f32 = 9.0;
+/// Number of preceding frames averaged when looking for onsets
+const ONSET_HISTORY_FRAMES: usize = 10;
+/// Onsets closer together than this are treated as a single event
+const MIN_ONSET_INTERVAL: f32 = 0.1; // seconds
+/// Windows never start closer together than this, even with dense onsets
+const MIN_SEGMENT_STEP: f32 = 2.5; // seconds
+/// Start windows slightly before an onset so the attack isn't clipped
+const ONSET_PRE_ROLL: f32 = 0.02; // seconds
+
+/// How files are split into segments before they're embedded
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, specta::Type,
+)]
+pub enum SegmentationStrategy {
+    /// Fixed-length windows at a fixed step, regardless of content
+    #[default]
+    FixedWindow,
+    /// Skip silence and start windows on onsets
+    EventAligned,
+}
+
+impl SegmentationStrategy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SegmentationStrategy::FixedWindow => "fixed_window",
+            SegmentationStrategy::EventAligned => "event_aligned",
+        }
+    }
+
+    fn parse(strategy: &str) -> Option<Self> {
+        match strategy {
+            "fixed_window" => Some(SegmentationStrategy::FixedWindow),
+            "event_aligned" => Some(SegmentationStrategy::EventAligned),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) async fn get_segmentation_strategy_from_db(
+    pool: &SqlitePool,
+) -> Result<SegmentationStrategy> {
+    let strategy = sqlx::query_scalar!("SELECT segmentation FROM index_settings WHERE id = 1")
+        .fetch_one(pool)
+        .await
+        .context("Failed to get segmentation strategy")?;
+    Ok(SegmentationStrategy::parse(&strategy).unwrap_or_default())
+}
+
+/// Get how files are split into segments when they're indexed
+#[tauri::command]
+#[specta::specta]
+pub async fn get_segmentation_strategy(
+    app_state: State<'_, AppState>,
+) -> result::Result<SegmentationStrategy, String> {
+    get_segmentation_strategy_from_db(&app_state.pool)
+        .await
+        .map_err(|err| format!("Failed to get segmentation strategy: {:?}", err))
+}
+
+/// Choose how files are split into segments.
+/// Only files indexed from now on are affected; files already in the index keep their segments.
+#[tauri::command]
+#[specta::specta]
+pub async fn set_segmentation_strategy(
+    app_state: State<'_, AppState>,
+    strategy: SegmentationStrategy,
+) -> result::Result<(), String> {
+    let strategy = strategy.as_str();
+    sqlx::query!(
+        "UPDATE index_settings SET segmentation = ? WHERE id = 1",
+        strategy
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(|err| format!("Failed to save segmentation strategy: {:?}", err))?;
+    Ok(())
+}
+
+/// A contiguous, non-silent stretch of audio, in samples (end exclusive)
+#[derive(Debug, PartialEq)]
+struct ActiveRegion {
+    start: usize,
+    end: usize,
+}
+
+/// Alternative to `split_audio_into_segments` that skips silence
+/// and aligns window starts to onsets.
+///
+/// Regions of sound are found with an RMS gate. Each region is covered by windows
+/// of at most `segment_length`, the first starting at the region's onset and each
+/// subsequent one starting on the latest onset within `segment_step` of the previous
+/// start (or exactly `segment_step` later if there is none). Windows are trimmed
+/// to the end of the region, so segments carry their true start and end times.
+pub(super) fn split_audio_into_event_segments(
+    pcm_audio: &[f32],
+    segment_length: f32,
+    segment_step: f32,
+) -> Vec<FileSegment<'_>> {
+    let frame_energies = compute_frame_energies_db(pcm_audio);
+    let regions = find_active_regions(&frame_energies, pcm_audio.len());
+    let onsets = find_onsets(&frame_energies);
+
+    let segment_length_samples = seconds_to_samples(segment_length);
+    let segment_step_samples = seconds_to_samples(segment_step);
+    let min_segment_step_samples = seconds_to_samples(MIN_SEGMENT_STEP);
+    let pre_roll_samples = seconds_to_samples(ONSET_PRE_ROLL);
+
+    let mut segments = vec![];
+    for region in &regions {
+        let region_onsets = onsets
+            .iter()
+            .map(|onset_frame| onset_frame * HOP_SIZE)
+            .filter(|onset| *onset > region.start && *onset < region.end)
+            .map(|onset| cmp::max(onset.saturating_sub(pre_roll_samples), region.start))
+            .collect::<Vec<_>>();
+
+        // Energy frames overlap the start of the sound, so the region already has some pre-roll
+        let mut current_sample = region.start;
+        loop {
+            let final_sample = cmp::min(current_sample + segment_length_samples, region.end);
+            segments.push(FileSegment {
+                starting_timestamp: current_sample as f64 / TARGET_SAMPLE_RATE as f64,
+                ending_timestamp: final_sample as f64 / TARGET_SAMPLE_RATE as f64,
+                pcm_audio: &pcm_audio[current_sample..final_sample],
+            });
+            if final_sample >= region.end {
+                break;
+            }
+            let earliest_next = current_sample + min_segment_step_samples;
+            let latest_next = current_sample + segment_step_samples;
+            current_sample = region_onsets
+                .iter()
+                .rev()
+                .find(|onset| (earliest_next..=latest_next).contains(*onset))
+                .copied()
+                .unwrap_or(latest_next);
+        }
+    }
+
+    debug!(
+        "Found {} regions and {} onsets, split into {} segments",
+        regions.len(),
+        onsets.len(),
+        segments.len()
+    );
+    match segments.len() {
+        // Entirely silent. Keep the whole file so it is still marked as indexed.
+        0 => vec![FileSegment {
+            starting_timestamp: 0.0,
+            ending_timestamp: pcm_audio.len() as f64 / TARGET_SAMPLE_RATE as f64,
+            pcm_audio,
+        }],
+        _ => segments,
+    }
+}
+
+fn seconds_to_samples(seconds: f32) -> usize {
+    (TARGET_SAMPLE_RATE as f32 * seconds) as usize
+}
+
+/// RMS energy of each frame in dBFS
+fn compute_frame_energies_db(pcm_audio: &[f32]) -> Vec<f32> {
+    (0..pcm_audio.len().div_ceil(HOP_SIZE))
+        .map(|frame_i| {
+            let frame_start = frame_i * HOP_SIZE;
+            let frame_end = cmp::min(frame_start + FRAME_SIZE, pcm_audio.len());
+            let frame = &pcm_audio[frame_start..frame_end];
+            let mean_square =
+                frame.iter().map(|sample| sample * sample).sum::<f32>() / frame.len() as f32;
+            10.0 * (mean_square + 1e-12).log10()
+        })
+        .collect()
+}
+
+/// Groups non-silent frames into regions, bridging gaps shorter than MIN_SILENCE_DURATION
+fn find_active_regions(frame_energies: &[f32], n_samples: usize) -> Vec<ActiveRegion> {
+    let min_silence_frames = seconds_to_samples(MIN_SILENCE_DURATION) / HOP_SIZE;
+
+    let mut frame_regions: Vec<(usize, usize)> = vec![];
+    for (frame_i, energy) in frame_energies.iter().enumerate() {
+        if *energy < SILENCE_THRESHOLD_DB {
+            continue;
+        }
+        match frame_regions.last_mut() {
+            Some((_, end)) if frame_i - *end < min_silence_frames => *end = frame_i + 1,
+            _ => frame_regions.push((frame_i, frame_i + 1)),
+        }
+    }
+
+    frame_regions
+        .into_iter()
+        .map(|(start_frame, end_frame)| ActiveRegion {
+            start: start_frame * HOP_SIZE,
+            end: cmp::min((end_frame - 1) * HOP_SIZE + FRAME_SIZE, n_samples),
+        })
+        .collect()
+}
+
+/// Frames where the energy jumps by ONSET_THRESHOLD_DB over the average of the
+/// preceding ONSET_HISTORY_FRAMES frames
+fn find_onsets(frame_energies: &[f32]) -> Vec<usize> {
+    let min_onset_interval_frames = seconds_to_samples(MIN_ONSET_INTERVAL) / HOP_SIZE;
+    // Don't let digital silence make every quiet frame look like an onset
+    let energy_floor = SILENCE_THRESHOLD_DB - 20.0;
+
+    let mut onsets: Vec<usize> = vec![];
+    for frame_i in 1..frame_energies.len() {
+        let energy = frame_energies[frame_i];
+        if energy < SILENCE_THRESHOLD_DB {
+            continue;
+        }
+        let history = &frame_energies[frame_i.saturating_sub(ONSET_HISTORY_FRAMES)..frame_i];
+        let history_mean = history
+            .iter()
+            .map(|history_energy| history_energy.max(energy_floor))
+            .sum::<f32>()
+            / history.len() as f32;
+        let is_far_enough = match onsets.last() {
+            Some(last_onset) => frame_i - last_onset >= min_onset_interval_frames,
+            None => true,
+        };
+        if energy - history_mean >= ONSET_THRESHOLD_DB && is_far_enough {
+            onsets.push(frame_i);
+        }
+    }
+    onsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_index::{SEGMENT_LENGTH, SEGMENT_STEP};
+
+    fn tone(seconds: f32) -> Vec<f32> {
+        (0..seconds_to_samples(seconds))
+            .map(|i| {
+                0.5 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / TARGET_SAMPLE_RATE as f32)
+                    .sin()
+            })
+            .collect()
+    }
+
+    fn silence(seconds: f32) -> Vec<f32> {
+        vec![0.0; seconds_to_samples(seconds)]
+    }
+
+    #[test]
+    fn test_silence_is_skipped() {
+        let pcm_audio = [silence(3.0), tone(2.0), silence(3.0)].concat();
+        let segments = split_audio_into_event_segments(&pcm_audio, SEGMENT_LENGTH, SEGMENT_STEP);
+        assert_eq!(segments.len(), 1);
+        assert!((segments[0].starting_timestamp - 3.0).abs() < 0.05);
+        assert!((segments[0].ending_timestamp - 5.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_separate_events_get_separate_segments() {
+        let pcm_audio = [tone(1.0), silence(4.0), tone(1.0)].concat();
+        let segments = split_audio_into_event_segments(&pcm_audio, SEGMENT_LENGTH, SEGMENT_STEP);
+        assert_eq!(segments.len(), 2);
+        assert!(segments[0].starting_timestamp < 0.05);
+        assert!((segments[1].starting_timestamp - 5.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_window_start_aligns_to_onset() {
+        // A quiet bed with a loud hit 4s in: the second window should start on the hit
+        let quiet_bed = tone(12.0).iter().map(|s| s * 0.01).collect::<Vec<_>>();
+        let mut pcm_audio = quiet_bed;
+        let hit_start = seconds_to_samples(4.0);
+        for (sample, hit) in pcm_audio[hit_start..].iter_mut().zip(tone(1.0)) {
+            *sample += hit;
+        }
+        let segments = split_audio_into_event_segments(&pcm_audio, SEGMENT_LENGTH, SEGMENT_STEP);
+        assert_eq!(segments.len(), 2);
+        assert!((segments[1].starting_timestamp - 4.0).abs() < 0.05);
+        assert!((segments[1].ending_timestamp - 12.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_long_region_is_fully_covered() {
+        let pcm_audio = tone(30.0);
+        let segments = split_audio_into_event_segments(&pcm_audio, SEGMENT_LENGTH, SEGMENT_STEP);
+        assert!(segments.windows(2).all(|pair| {
+            pair[1].starting_timestamp - pair[0].starting_timestamp <= SEGMENT_STEP as f64 + 1e-6
+        }));
+        assert!((segments.last().unwrap().ending_timestamp - 30.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_silent_file_falls_back_to_single_segment() {
+        let pcm_audio = silence(5.0);
+        let segments = split_audio_into_event_segments(&pcm_audio, SEGMENT_LENGTH, SEGMENT_STEP);
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].pcm_audio.len(), pcm_audio.len());
+    }
+
+    #[tokio::test]
+    async fn test_segmentation_defaults_to_fixed_windows() {
+        let pool = crate::state::database::create_test_pool().await;
+        assert_eq!(
+            get_segmentation_strategy_from_db(&pool).await.unwrap(),
+            SegmentationStrategy::FixedWindow
+        );
+    }
+}
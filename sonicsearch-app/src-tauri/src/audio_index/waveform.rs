@@ -0,0 +1,127 @@
+//! A downsampled waveform of each file, computed while indexing so the UI can draw
+//! where a result sits in its file without decoding it again.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+
+use super::{decode_audio_file, LoadedAudioFile};
+use crate::state::database::{decode_f32s, encode_f32s};
+
+/// Buckets per overview, whatever the file's length
+pub const OVERVIEW_BUCKETS: usize = 512;
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, specta::Type)]
+pub struct WaveformOverview {
+    /// The largest absolute sample in each bucket
+    pub peaks: Vec<f32>,
+    /// The RMS level of each bucket
+    pub rms: Vec<f32>,
+}
+
+struct WaveformOverviewRow {
+    peaks: Vec<u8>,
+    rms: Vec<u8>,
+}
+
+/// Split mono samples into [`OVERVIEW_BUCKETS`] equal buckets, or one per sample if there are fewer
+pub fn compute_overview(samples: &[f32]) -> WaveformOverview {
+    let n_buckets = OVERVIEW_BUCKETS.min(samples.len());
+    let (peaks, rms) = (0..n_buckets)
+        .map(|i| {
+            let bucket =
+                &samples[i * samples.len() / n_buckets..(i + 1) * samples.len() / n_buckets];
+            let peak = bucket
+                .iter()
+                .fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+            let mean_square =
+                bucket.iter().map(|sample| sample * sample).sum::<f32>() / bucket.len() as f32;
+            (peak, mean_square.sqrt())
+        })
+        .unzip();
+    WaveformOverview { peaks, rms }
+}
+
+/// Decode a file just to compute its overview
+pub(crate) fn compute_file_overview(path: &Path) -> Result<WaveformOverview> {
+    let audio_file = LoadedAudioFile {
+        file_hash: String::new(),
+        file_path: path.to_owned(),
+    };
+    let decoded_audio = decode_audio_file(&audio_file)?;
+    Ok(compute_overview(&decoded_audio.samples))
+}
+
+pub(crate) async fn get_overview(
+    pool: &SqlitePool,
+    file_hash: &str,
+) -> Result<Option<WaveformOverview>> {
+    let row = sqlx::query_as!(
+        WaveformOverviewRow,
+        "SELECT peaks, rms FROM waveform_overview WHERE file_hash = ?",
+        file_hash
+    )
+    .fetch_optional(pool)
+    .await
+    .context(format!("Failed to get waveform overview of {}", file_hash))?;
+    row.map(|row| {
+        Ok(WaveformOverview {
+            peaks: decode_f32s(&row.peaks)?,
+            rms: decode_f32s(&row.rms)?,
+        })
+    })
+    .transpose()
+}
+
+/// Store the overview of a file indexed before overviews were computed
+pub(crate) async fn save_overview(
+    pool: &SqlitePool,
+    file_hash: &str,
+    overview: &WaveformOverview,
+) -> Result<()> {
+    let mut transaction = pool.begin().await?;
+    save_overview_in_transaction(&mut transaction, file_hash, overview).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Store the overview of a file along with the rest of its index entry
+pub(crate) async fn save_overview_in_transaction(
+    transaction: &mut Transaction<'_, Sqlite>,
+    file_hash: &str,
+    overview: &WaveformOverview,
+) -> Result<()> {
+    let (peaks, rms) = (encode_f32s(&overview.peaks), encode_f32s(&overview.rms));
+    sqlx::query!(
+        "INSERT OR REPLACE INTO waveform_overview (file_hash, peaks, rms) VALUES (?, ?, ?)",
+        file_hash,
+        peaks,
+        rms
+    )
+    .execute(&mut **transaction)
+    .await
+    .context(format!("Failed to save waveform overview of {}", file_hash))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_overview() {
+        let mut samples = vec![0.5_f32; OVERVIEW_BUCKETS * 4];
+        samples[1] = -1.0;
+        let overview = compute_overview(&samples);
+        assert_eq!(overview.peaks.len(), OVERVIEW_BUCKETS);
+        assert_eq!(overview.peaks[0], 1.0);
+        assert_eq!(overview.peaks[1], 0.5);
+        assert!((overview.rms[0] - (1.75_f32 / 4.0).sqrt()).abs() < 1e-6);
+        assert_eq!(overview.rms[1], 0.5);
+
+        // Short files get a bucket per sample
+        assert_eq!(compute_overview(&[0.1, -0.2]).peaks, vec![0.1, 0.2]);
+        assert_eq!(compute_overview(&[]), WaveformOverview::default());
+    }
+}
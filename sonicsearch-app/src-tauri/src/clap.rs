@@ -1,4 +1,6 @@
-use anyhow::Result;
+use std::env::{self, VarError};
+
+use anyhow::{anyhow, Context, Result};
 use ort::{
     Environment,
     ExecutionProvider::{CoreML, CPU, CUDA},
@@ -6,7 +8,65 @@ use ort::{
 };
 use tauri::PathResolver;
 
-pub fn load_clap_models(path_resolver: &PathResolver) -> Result<(Session, Session)> {
+/// The CLAP checkpoints we can run. Each expects a different audio frontend
+/// (see `audio_index::feature_extractor`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClapModel {
+    /// `laion/clap-htsat-unfused`: sees a single 10 second view of each segment
+    #[default]
+    HtsatUnfused,
+    /// `laion/clap-htsat-fused`: fuses a downsampled view of the whole segment
+    /// with three 10 second crops, so longer segments can be embedded as one unit
+    HtsatFused,
+}
+
+/// Chooses the model loaded at startup, by its `model_name`.
+/// Embeddings from different models can't be compared, so changing it needs a fresh index
+const CLAP_MODEL_ENV_VAR: &str = "SONICSEARCH_CLAP_MODEL";
+
+impl ClapModel {
+    const ALL: [ClapModel; 2] = [ClapModel::HtsatUnfused, ClapModel::HtsatFused];
+
+    /// The model named by `SONICSEARCH_CLAP_MODEL`, or the default if it isn't set
+    pub fn from_env() -> Result<Self> {
+        match env::var(CLAP_MODEL_ENV_VAR) {
+            Ok(model_name) => Self::from_model_name(&model_name),
+            Err(VarError::NotPresent) => Ok(ClapModel::default()),
+            Err(err) => Err(err).context(format!("Failed to read {}", CLAP_MODEL_ENV_VAR)),
+        }
+    }
+
+    fn from_model_name(model_name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|model| model.model_name() == model_name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown CLAP model {:?}, expected one of {:?}",
+                    model_name,
+                    Self::ALL.map(|model| model.model_name())
+                )
+            })
+    }
+
+    /// Prefix of the exported .onnx files in `onnx_models`
+    pub fn model_name(&self) -> &'static str {
+        match self {
+            ClapModel::HtsatUnfused => "clap-htsat-unfused",
+            ClapModel::HtsatFused => "clap-htsat-fused",
+        }
+    }
+
+    /// Fused models take an `is_longer` input alongside the 4-channel features
+    pub fn is_fused(&self) -> bool {
+        matches!(self, ClapModel::HtsatFused)
+    }
+}
+
+pub fn load_clap_models(
+    path_resolver: &PathResolver,
+    model: ClapModel,
+) -> Result<(Session, Session)> {
     let environment = Environment::builder()
         .with_execution_providers(vec![
             CUDA(Default::default()),
@@ -17,9 +77,12 @@ pub fn load_clap_models(path_resolver: &PathResolver) -> Result<(Session, Sessio
         .build()?
         .into_arc();
 
-    let text_embedder_model_filename = "onnx_models/clap-htsat-unfused_text_with_projection.onnx";
+    let text_embedder_model_filename = format!(
+        "onnx_models/{}_text_with_projection.onnx",
+        model.model_name()
+    );
     let text_embedder_model_path = path_resolver
-        .resolve_resource(text_embedder_model_filename)
+        .resolve_resource(&text_embedder_model_filename)
         .unwrap_or_else(|| {
             panic!(
                 "Model path {} should resolve.",
@@ -36,9 +99,12 @@ pub fn load_clap_models(path_resolver: &PathResolver) -> Result<(Session, Sessio
             )
         });
 
-    let audio_embedder_model_filename = "onnx_models/clap-htsat-unfused_audio_with_projection.onnx";
+    let audio_embedder_model_filename = format!(
+        "onnx_models/{}_audio_with_projection.onnx",
+        model.model_name()
+    );
     let audio_embedder_model_path = path_resolver
-        .resolve_resource(audio_embedder_model_filename)
+        .resolve_resource(&audio_embedder_model_filename)
         .unwrap_or_else(|| {
             panic!(
                 "Model path {} should resolve.",
@@ -57,3 +123,19 @@ pub fn load_clap_models(path_resolver: &PathResolver) -> Result<(Session, Sessio
 
     Ok((text_embedder_session, audio_embedder_session))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_models_are_found_by_name() {
+        for model in ClapModel::ALL {
+            assert_eq!(
+                ClapModel::from_model_name(model.model_name()).unwrap(),
+                model
+            );
+        }
+        assert!(ClapModel::from_model_name("clap-htsat").is_err());
+    }
+}
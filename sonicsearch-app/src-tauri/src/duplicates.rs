@@ -0,0 +1,299 @@
+//! Finds files that sound the same, even when they differ in sample rate, bit depth,
+//! format or length, by comparing their segment embeddings.
+//! Byte-identical copies are a single file with several locations, so they're always grouped.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::result;
+
+use anyhow::{Context, Result};
+use hnsw_rs::{dist::DistCosine, hnsw::Hnsw};
+use log::debug;
+use sqlx::SqlitePool;
+use tauri::State;
+
+use crate::audio_index::file_location::get_all_locations;
+use crate::embeddings::{cosine_similarity, mean_embedding};
+use crate::paths::serialize_display_path;
+use crate::state::database::vector_index::{DEFAULT_NB_LAYER, EF_C, MAX_NB_CONNECTION};
+use crate::state::{database::decode_embedding, AppState};
+
+/// Files at least this similar are grouped when no threshold is given
+const DEFAULT_MIN_SIMILARITY: f32 = 0.97;
+/// Neighbours looked up per file. Larger groups are still found, through their members
+const N_NEIGHBOURS: usize = 10;
+const EF_SEARCH: usize = 32;
+
+#[derive(Clone, Debug, serde::Serialize, specta::Type)]
+pub struct DuplicateMember {
+    #[serde(serialize_with = "serialize_display_path")]
+    file_path: PathBuf,
+    duration_seconds: Option<f64>,
+    /// Cosine similarity to the first member of the group, 1 for identical copies
+    similarity: f32,
+}
+
+#[derive(Clone, Debug, serde::Serialize, specta::Type)]
+pub struct DuplicateGroup {
+    /// The longest file comes first, since the others are often trimmed or converted copies of it
+    members: Vec<DuplicateMember>,
+    /// The lowest similarity of any member to the first
+    min_similarity: f32,
+}
+
+struct SegmentRow {
+    file_hash: String,
+    duration_seconds: Option<f64>,
+    embedding: Vec<u8>,
+}
+
+/// A file summarized by the mean of its segment embeddings
+struct FileEmbedding {
+    /// Every location of the file
+    file_paths: Vec<PathBuf>,
+    duration_seconds: Option<f64>,
+    /// The mean of the file's segment embeddings
+    embedding: Vec<f32>,
+}
+
+/// Group the indexed files that are at least `min_similarity` alike.
+/// Groups are ordered by size, largest first.
+#[tauri::command]
+#[specta::specta]
+pub async fn find_duplicates(
+    app_state: State<'_, AppState>,
+    min_similarity: Option<f32>,
+) -> result::Result<Vec<DuplicateGroup>, String> {
+    let min_similarity = min_similarity.unwrap_or(DEFAULT_MIN_SIMILARITY);
+    if !(0.0..=1.0).contains(&min_similarity) {
+        return Err(format!(
+            "Minimum similarity must be between 0 and 1, got {}",
+            min_similarity
+        ));
+    }
+    let files = get_file_embeddings(&app_state.pool)
+        .await
+        .map_err(|err| format!("Failed to get file embeddings: {:?}", err))?;
+    // Building the index over every file is CPU-bound, so keep it off the async executor
+    tokio::task::spawn_blocking(move || group_duplicates(&files, min_similarity))
+        .await
+        .map_err(|err| format!("Failed to group duplicates: {:?}", err))
+}
+
+async fn get_file_embeddings(pool: &SqlitePool) -> Result<Vec<FileEmbedding>> {
+    let rows = sqlx::query_as!(
+        SegmentRow,
+        r#"SELECT
+            af.file_hash,
+            af.duration_seconds,
+            afs.embedding
+        FROM audio_file_segment afs
+            JOIN audio_file af ON afs.file_hash = af.file_hash
+        ORDER BY af.file_hash"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to get segment embeddings")?;
+    let mut locations = get_all_locations(pool).await?;
+
+    let mut files: BTreeMap<&str, (&SegmentRow, Vec<Vec<f32>>)> = BTreeMap::new();
+    for row in &rows {
+        let embedding = decode_embedding(&row.embedding)
+            .context(format!("Could not decode embedding of {}", row.file_hash))?;
+        files
+            .entry(&row.file_hash)
+            .or_insert_with(|| (row, vec![]))
+            .1
+            .push(embedding);
+    }
+    Ok(files
+        .into_values()
+        .filter_map(|(row, embeddings)| {
+            Some(FileEmbedding {
+                file_paths: locations.remove(&row.file_hash)?,
+                duration_seconds: row.duration_seconds,
+                embedding: mean_embedding(&embeddings)?,
+            })
+        })
+        .collect())
+}
+
+/// Link each file to its near neighbours that are similar enough,
+/// and return the connected groups with more than one location
+fn group_duplicates(files: &[FileEmbedding], min_similarity: f32) -> Vec<DuplicateGroup> {
+    if files.is_empty() {
+        return vec![];
+    }
+    debug!("Looking for duplicates among {} files", files.len());
+    let nb_layer = DEFAULT_NB_LAYER
+        .min((files.len() as f32).ln().trunc() as usize)
+        .max(1);
+    let mut index = Hnsw::<f32, DistCosine>::new(
+        MAX_NB_CONNECTION,
+        files.len(),
+        nb_layer,
+        EF_C,
+        DistCosine {},
+    );
+    let data = files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| (&file.embedding, i))
+        .collect::<Vec<_>>();
+    index.parallel_insert(&data);
+    index.set_searching_mode(true);
+
+    let mut groups = DisjointSet::new(files.len());
+    for (i, file) in files.iter().enumerate() {
+        for neighbour in index.search(&file.embedding, N_NEIGHBOURS, EF_SEARCH) {
+            let j = neighbour.d_id;
+            if j != i && cosine_similarity(&file.embedding, &files[j].embedding) >= min_similarity {
+                groups.union(i, j);
+            }
+        }
+    }
+
+    let mut members_by_group: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for i in 0..files.len() {
+        members_by_group.entry(groups.find(i)).or_default().push(i);
+    }
+    let mut duplicate_groups = members_by_group
+        .into_values()
+        .filter(|members| {
+            members
+                .iter()
+                .map(|&i| files[i].file_paths.len())
+                .sum::<usize>()
+                > 1
+        })
+        .map(|members| to_duplicate_group(files, members))
+        .collect::<Vec<_>>();
+    duplicate_groups.sort_by(|a, b| {
+        b.members
+            .len()
+            .cmp(&a.members.len())
+            .then_with(|| a.members[0].file_path.cmp(&b.members[0].file_path))
+    });
+    duplicate_groups
+}
+
+fn to_duplicate_group(files: &[FileEmbedding], mut members: Vec<usize>) -> DuplicateGroup {
+    members.sort_by(|&a, &b| {
+        let duration = |i: usize| files[i].duration_seconds.unwrap_or_default();
+        duration(b)
+            .total_cmp(&duration(a))
+            .then_with(|| files[a].file_paths.cmp(&files[b].file_paths))
+    });
+    let first = members[0];
+    let members = members
+        .into_iter()
+        .flat_map(|i| {
+            let similarity = match i == first {
+                true => 1.0,
+                false => cosine_similarity(&files[first].embedding, &files[i].embedding).min(1.0),
+            };
+            files[i]
+                .file_paths
+                .iter()
+                .map(move |file_path| DuplicateMember {
+                    file_path: file_path.clone(),
+                    duration_seconds: files[i].duration_seconds,
+                    similarity,
+                })
+        })
+        .collect::<Vec<_>>();
+    let min_similarity = members
+        .iter()
+        .map(|member| member.similarity)
+        .fold(1.0, f32::min);
+    DuplicateGroup {
+        members,
+        min_similarity,
+    }
+}
+
+/// Union-find over file indices
+struct DisjointSet {
+    parents: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        let parent = self.parents[i];
+        if parent == i {
+            return i;
+        }
+        let root = self.find(parent);
+        self.parents[i] = root;
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parents[b.max(a)] = a.min(b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(file_paths: &[&str], duration_seconds: f64, embedding: &[f32]) -> FileEmbedding {
+        FileEmbedding {
+            file_paths: file_paths.iter().map(PathBuf::from).collect(),
+            duration_seconds: Some(duration_seconds),
+            embedding: mean_embedding(&[embedding.to_vec()]).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_group_duplicates() {
+        let files = [
+            file(&["/Sounds/door_48k.wav"], 2.0, &[1.0, 0.0, 0.0, 0.01]),
+            file(&["/Sounds/hum.wav"], 60.0, &[0.0, 1.0, 0.0, 0.0]),
+            file(&["/Sounds/door.wav"], 2.5, &[1.0, 0.0, 0.0, 0.0]),
+            file(&["/Sounds/steps.wav"], 4.0, &[0.0, 0.0, 1.0, 0.0]),
+            file(&["/Sounds/door_16bit.wav"], 2.5, &[1.0, 0.02, 0.0, 0.0]),
+            file(&["/Sounds/steps_trimmed.wav"], 3.0, &[0.0, 0.05, 1.0, 0.0]),
+            file(&["/A/wind.wav", "/B/wind.wav"], 8.0, &[0.0, 0.0, 0.0, 1.0]),
+        ];
+        let groups = group_duplicates(&files, 0.99);
+
+        let paths = groups
+            .iter()
+            .map(|group| {
+                group
+                    .members
+                    .iter()
+                    .map(|member| member.file_path.to_str().unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                vec![
+                    "/Sounds/door.wav",
+                    "/Sounds/door_16bit.wav",
+                    "/Sounds/door_48k.wav"
+                ],
+                vec!["/A/wind.wav", "/B/wind.wav"],
+                vec!["/Sounds/steps.wav", "/Sounds/steps_trimmed.wav"],
+            ]
+        );
+        assert_eq!(groups[0].members[0].similarity, 1.0);
+        assert!(groups[0].min_similarity >= 0.99 && groups[0].min_similarity < 1.0);
+        assert_eq!(groups[1].min_similarity, 1.0);
+
+        // Identical copies are grouped at any threshold
+        assert_eq!(group_duplicates(&files, 1.0).len(), 1);
+    }
+}
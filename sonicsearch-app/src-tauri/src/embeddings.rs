@@ -0,0 +1,68 @@
+//! Vector math shared by everything that compares CLAP embeddings.
+
+/// The Euclidean length of an embedding
+pub fn l2_norm(embedding: &[f32]) -> f32 {
+    embedding
+        .iter()
+        .map(|coord| coord * coord)
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// The embedding scaled to length 1, or None if it's zero
+pub fn normalize(embedding: &[f32]) -> Option<Vec<f32>> {
+    let norm = l2_norm(embedding);
+    (norm > 0.0).then(|| embedding.iter().map(|coord| coord / norm).collect())
+}
+
+/// The normalized mean of the normalized embeddings, or None if there's nothing to average
+pub fn mean_embedding(embeddings: &[Vec<f32>]) -> Option<Vec<f32>> {
+    let mut mean = vec![0.0; embeddings.first()?.len()];
+    for embedding in embeddings {
+        if embedding.len() != mean.len() {
+            continue;
+        }
+        let Some(embedding) = normalize(embedding) else {
+            continue;
+        };
+        for (mean_coord, coord) in mean.iter_mut().zip(embedding) {
+            *mean_coord += coord;
+        }
+    }
+    normalize(&mean)
+}
+
+/// 1 for embeddings pointing the same way, -1 for opposite ones, and 0 if either is zero
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let norms = l2_norm(a) * l2_norm(b);
+    if norms == 0.0 {
+        return 0.0;
+    }
+    a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>() / norms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(&[3.0, -4.0]), Some(vec![0.6, -0.8]));
+        assert_eq!(normalize(&[0.0, 0.0]), None);
+    }
+
+    #[test]
+    fn test_mean_embedding_ignores_scale() {
+        let mean = mean_embedding(&[vec![2.0, 0.0], vec![0.0, 0.5]]).unwrap();
+        let expected = 1.0 / 2.0_f32.sqrt();
+        assert!((mean[0] - expected).abs() < 1e-6 && (mean[1] - expected).abs() < 1e-6);
+        assert!(mean_embedding(&[vec![0.0, 0.0]]).is_none());
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[2.0, 0.0], &[0.5, 0.5]) - 0.5_f32.sqrt()).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[-3.0, 0.0]), -1.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+}
@@ -0,0 +1,362 @@
+//! Writes search results to files other apps can open: playlists, spreadsheets,
+//! and region lists that DAWs import, so a result set can go straight into a session.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::{fs, result};
+
+use anyhow::{Context, Result};
+use log::info;
+use sqlx::SqlitePool;
+use tauri::State;
+
+use crate::audio_index::file_location::{
+    find_location_by_display_path, get_locations, get_segment_file_hash,
+};
+use crate::paths::{display_path, serialize_display_path, serialize_display_paths};
+use crate::state::{database::vector_index::SearchResult, AppState};
+
+pub mod region;
+
+/// Silence left between results laid out on a DAW timeline
+const TIMELINE_GAP_SECONDS: f64 = 1.0;
+/// EDLs count time in frames, even for audio
+const EDL_FRAME_RATE: f64 = 30.0;
+/// Where the first EDL event starts on the record timeline, by convention
+const EDL_RECORD_START_SECONDS: f64 = 3600.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, specta::Type)]
+pub enum ExportFormat {
+    /// An extended M3U playlist in UTF-8, for .m3u or .m3u8 files
+    M3u,
+    Csv,
+    Json,
+    /// A Reaper project with one item and one region per result
+    ReaperProject,
+    /// A CMX 3600 edit decision list, which most DAWs and video editors import
+    Edl,
+}
+
+/// A search result as written to CSV and JSON
+#[derive(Debug, serde::Serialize)]
+struct ExportedResult {
+    rank: usize,
+    #[serde(serialize_with = "serialize_display_path")]
+    file_path: PathBuf,
+    #[serde(serialize_with = "serialize_display_paths")]
+    other_locations: Vec<PathBuf>,
+    start_seconds: f64,
+    end_seconds: f64,
+    distance: f32,
+    /// Cosine similarity to the query, higher is closer
+    similarity: f32,
+    available: bool,
+}
+
+impl ExportedResult {
+    fn file_name(&self) -> String {
+        self.file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| display_path(&self.file_path))
+    }
+
+    fn length_seconds(&self) -> f64 {
+        (self.end_seconds - self.start_seconds).max(0.0)
+    }
+}
+
+/// Write search results to `destination`, in the order given
+#[tauri::command]
+#[specta::specta]
+pub async fn export_results(
+    app_state: State<'_, AppState>,
+    mut results: Vec<SearchResult>,
+    format: ExportFormat,
+    destination: PathBuf,
+) -> result::Result<(), String> {
+    info!(
+        "Exporting {} results as {:?} to {:?}",
+        results.len(),
+        format,
+        destination
+    );
+    for result in &mut results {
+        resolve_result_locations(&app_state.pool, result)
+            .await
+            .map_err(|err| format!("Failed to export results: {:?}", err))?;
+    }
+    write_export(&results, format, &destination)
+        .map_err(|err| format!("Failed to export results: {:?}", err))
+}
+
+/// Results come back from the UI with the display form of their paths,
+/// so look up the paths they were made from
+async fn resolve_result_locations(pool: &SqlitePool, result: &mut SearchResult) -> Result<()> {
+    let file_hash = get_segment_file_hash(pool, result.segment_id).await?;
+    let display = display_path(&result.file_path);
+    let mut locations = get_locations(pool, &file_hash).await?;
+    let i = locations
+        .iter()
+        .position(|location| display_path(location) == display)
+        .context(format!("{} isn't a location of {}", display, file_hash))?;
+    result.file_path = locations.remove(i);
+    result.other_locations = locations;
+    Ok(())
+}
+
+/// The path of the result whose segment is `segment_id` and whose path is shown as `display`
+pub(crate) async fn find_result_location(
+    pool: &SqlitePool,
+    segment_id: i64,
+    display: &str,
+) -> Result<PathBuf> {
+    let file_hash = get_segment_file_hash(pool, segment_id).await?;
+    find_location_by_display_path(pool, &file_hash, display).await
+}
+
+fn write_export(results: &[SearchResult], format: ExportFormat, destination: &Path) -> Result<()> {
+    let results = results
+        .iter()
+        .enumerate()
+        .map(|(i, result)| ExportedResult {
+            rank: i + 1,
+            file_path: result.file_path.clone(),
+            other_locations: result.other_locations.clone(),
+            start_seconds: result.starting_timestamp,
+            end_seconds: result.ending_timestamp,
+            distance: result.distance,
+            similarity: 1.0 - result.distance,
+            available: result.available,
+        })
+        .collect::<Vec<_>>();
+    let contents = match format {
+        ExportFormat::M3u => to_m3u(&results),
+        ExportFormat::Csv => to_csv(&results),
+        ExportFormat::Json => {
+            serde_json::to_string_pretty(&results).context("Failed to serialize results")?
+        }
+        ExportFormat::ReaperProject => to_reaper_project(&results),
+        ExportFormat::Edl => to_edl(&results),
+    };
+    fs::write(destination, contents).context(format!("Failed to write {:?}", destination))
+}
+
+/// Players that understand `EXTVLCOPT` only play the matching region
+fn to_m3u(results: &[ExportedResult]) -> String {
+    let mut m3u = String::from("#EXTM3U\n");
+    for result in results {
+        let _ = writeln!(
+            m3u,
+            "#EXTINF:{},{} ({:.1}s–{:.1}s)",
+            result.length_seconds().round(),
+            result.file_name(),
+            result.start_seconds,
+            result.end_seconds
+        );
+        let _ = writeln!(m3u, "#EXTVLCOPT:start-time={}", result.start_seconds);
+        let _ = writeln!(m3u, "#EXTVLCOPT:stop-time={}", result.end_seconds);
+        let _ = writeln!(m3u, "{}", display_path(&result.file_path));
+    }
+    m3u
+}
+
+fn to_csv(results: &[ExportedResult]) -> String {
+    let mut csv = String::from(
+        "rank,file_path,start_seconds,end_seconds,distance,similarity,available,other_locations\n",
+    );
+    for result in results {
+        let other_locations = result
+            .other_locations
+            .iter()
+            .map(|path| display_path(path))
+            .collect::<Vec<_>>()
+            .join("|");
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{}",
+            result.rank,
+            csv_field(&display_path(&result.file_path)),
+            result.start_seconds,
+            result.end_seconds,
+            result.distance,
+            result.similarity,
+            result.available,
+            csv_field(&other_locations)
+        );
+    }
+    csv
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The results are laid out one after the other on a single track,
+/// each trimmed to its region and covered by a region named after it
+fn to_reaper_project(results: &[ExportedResult]) -> String {
+    let mut markers = String::new();
+    let mut items = String::new();
+    let mut position = 0.0;
+    for result in results {
+        let length = result.length_seconds();
+        let name = format!("{} #{}", result.file_name(), result.rank);
+        let _ = writeln!(
+            markers,
+            "  MARKER {} {} {} 1",
+            result.rank,
+            position,
+            rpp_string(&name)
+        );
+        let _ = writeln!(
+            markers,
+            "  MARKER {} {} \"\" 1",
+            result.rank,
+            position + length
+        );
+        let _ = write!(
+            items,
+            "    <ITEM\n      POSITION {}\n      LENGTH {}\n      SOFFS {}\n      NAME {}\n      <SOURCE {}\n        FILE {}\n      >\n    >\n",
+            position,
+            length,
+            result.start_seconds,
+            rpp_string(&name),
+            reaper_source_type(&result.file_path),
+            rpp_string(&display_path(&result.file_path))
+        );
+        position += length + TIMELINE_GAP_SECONDS;
+    }
+    format!(
+        "<REAPER_PROJECT 0.1 \"6.0\" 0\n{}  <TRACK\n    NAME \"SonicSearch results\"\n{}  >\n>\n",
+        markers, items
+    )
+}
+
+/// Reaper quotes strings with whichever of `"`, `'` or `` ` `` they don't contain
+fn rpp_string(string: &str) -> String {
+    let quote = ['"', '\'', '`']
+        .into_iter()
+        .find(|quote| !string.contains(*quote))
+        .unwrap_or('"');
+    format!("{}{}{}", quote, string.replace(quote, "'"), quote)
+}
+
+fn reaper_source_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("mp3") => "MP3",
+        Some("flac") => "FLAC",
+        Some("ogg") => "VORBIS",
+        Some("opus") => "OPUS",
+        _ => "WAVE",
+    }
+}
+
+/// One audio event per result, cut from the source at the result's region
+fn to_edl(results: &[ExportedResult]) -> String {
+    let mut edl = String::from("TITLE: SonicSearch results\nFCM: NON-DROP FRAME\n\n");
+    let mut record_start = EDL_RECORD_START_SECONDS;
+    for result in results {
+        let record_end = record_start + result.length_seconds();
+        let _ = writeln!(
+            edl,
+            "{:03}  AX       A     C        {} {} {} {}",
+            result.rank,
+            timecode(result.start_seconds),
+            timecode(result.end_seconds),
+            timecode(record_start),
+            timecode(record_end)
+        );
+        let _ = writeln!(edl, "* FROM CLIP NAME: {}", result.file_name());
+        let _ = writeln!(edl, "* SOURCE FILE: {}\n", display_path(&result.file_path));
+        record_start = record_end;
+    }
+    edl
+}
+
+fn timecode(seconds: f64) -> String {
+    let frames = (seconds.max(0.0) * EDL_FRAME_RATE).round() as u64;
+    let frame_rate = EDL_FRAME_RATE as u64;
+    let (seconds, frames) = (frames / frame_rate, frames % frame_rate);
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        frames
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(
+        rank: usize,
+        file_path: &str,
+        start_seconds: f64,
+        end_seconds: f64,
+    ) -> ExportedResult {
+        ExportedResult {
+            rank,
+            file_path: PathBuf::from(file_path),
+            other_locations: vec![],
+            start_seconds,
+            end_seconds,
+            distance: 0.25,
+            similarity: 0.75,
+            available: true,
+        }
+    }
+
+    #[test]
+    fn test_csv_quotes_fields() {
+        let mut door = result(1, "/Sounds/door, \"slam\".wav", 5.0, 15.0);
+        door.other_locations = vec![PathBuf::from("/A/door.wav"), PathBuf::from("/B/door.wav")];
+        assert_eq!(
+            to_csv(&[door]).lines().nth(1),
+            Some(
+                "1,\"/Sounds/door, \"\"slam\"\".wav\",5,15,0.25,0.75,true,/A/door.wav|/B/door.wav"
+            )
+        );
+    }
+
+    #[test]
+    fn test_edl_timecodes() {
+        let results = [
+            result(1, "/Sounds/door.wav", 5.0, 15.0),
+            result(2, "/Sounds/steps.wav", 61.5, 71.5),
+        ];
+        let events = to_edl(&results)
+            .lines()
+            .filter(|line| line.starts_with('0'))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                "001  AX       A     C        00:00:05:00 00:00:15:00 01:00:00:00 01:00:10:00",
+                "002  AX       A     C        00:01:01:15 00:01:11:15 01:00:10:00 01:00:20:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reaper_project_lays_out_regions() {
+        let results = [
+            result(1, "/Sounds/door.wav", 5.0, 15.0),
+            result(2, "/Sounds/it's \"loud\".mp3", 0.0, 4.0),
+        ];
+        let project = to_reaper_project(&results);
+        assert!(project.contains("  MARKER 1 0 \"door.wav #1\" 1\n  MARKER 1 10 \"\" 1\n"));
+        assert!(project.contains("  MARKER 2 11 `it's \"loud\".mp3 #2` 1\n  MARKER 2 15 \"\" 1\n"));
+        assert!(project.contains("      SOFFS 5\n"));
+        assert!(project.contains("      <SOURCE MP3\n"));
+    }
+}
@@ -0,0 +1,360 @@
+//! Renders the region of a search result to a new WAV file, at the source's sample rate
+//! and bit depth. A BWF `bext` chunk records the source file and where the region
+//! starts, so DAWs can place the clip at the source's timecode.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::result;
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use log::info;
+use tauri::State;
+
+use super::find_result_location;
+use crate::paths::display_path;
+use crate::state::AppState;
+
+const ORIGINATOR: &str = "SonicSearch";
+/// Where TimeReferenceLow sits in a `bext` chunk, after the description,
+/// originator, originator reference, origination date and origination time
+const BEXT_TIME_REFERENCE_OFFSET: usize = 256 + 32 + 32 + 10 + 8;
+/// The fixed-size part of a version 1 `bext` chunk, up to the coding history
+const BEXT_FIXED_LEN: usize = BEXT_TIME_REFERENCE_OFFSET + 8 + 2 + 64 + 190;
+
+#[derive(Clone, Debug, Default, serde::Deserialize, specta::Type)]
+pub struct RegionOptions {
+    /// Extra audio kept before the region, if the source has it
+    pre_roll_seconds: f64,
+    /// Extra audio kept after the region, if the source has it
+    post_roll_seconds: f64,
+    /// Linear fades at the edges of the clip, including the pre- and post-roll
+    fade_in_seconds: f64,
+    fade_out_seconds: f64,
+}
+
+/// Write the part of a result's file between `start_seconds` and `end_seconds` to `destination`.
+/// `file_path` is the result's path as shown, which is looked up among the locations
+/// of the file that `segment_id` was cut from
+#[tauri::command]
+#[specta::specta]
+pub async fn export_region(
+    app_state: State<'_, AppState>,
+    segment_id: i64,
+    file_path: String,
+    start_seconds: f64,
+    end_seconds: f64,
+    options: RegionOptions,
+    destination: PathBuf,
+) -> result::Result<(), String> {
+    info!(
+        "Exporting {} from {}s to {}s to {:?}",
+        file_path, start_seconds, end_seconds, destination
+    );
+    let file_path = find_result_location(&app_state.pool, segment_id, &file_path)
+        .await
+        .map_err(|err| format!("Failed to export region: {:?}", err))?;
+    write_region(
+        &file_path,
+        start_seconds,
+        end_seconds,
+        &options,
+        &destination,
+    )
+    .map_err(|err| format!("Failed to export region: {:?}", err))
+}
+
+fn write_region(
+    file_path: &Path,
+    start_seconds: f64,
+    end_seconds: f64,
+    options: &RegionOptions,
+    destination: &Path,
+) -> Result<()> {
+    let source = File::open(file_path).context(format!("Failed to open {:?}", file_path))?;
+    let clip = render_region(
+        BufReader::new(source),
+        file_path,
+        start_seconds,
+        end_seconds,
+        options,
+    )?;
+    fs::write(destination, clip).context(format!("Failed to write {:?}", destination))
+}
+
+/// Render a region of a WAV file to the bytes of a new WAV file
+fn render_region<R: Read + Seek>(
+    mut source: R,
+    source_path: &Path,
+    start_seconds: f64,
+    end_seconds: f64,
+    options: &RegionOptions,
+) -> Result<Vec<u8>> {
+    if end_seconds <= start_seconds {
+        bail!(
+            "Region must end after it starts, got {}s to {}s",
+            start_seconds,
+            end_seconds
+        );
+    }
+    let source_time_reference = read_time_reference(&mut source)
+        .context("Failed to read the source's timecode")?
+        .unwrap_or(0);
+    source.rewind()?;
+
+    let mut wav_reader = WavReader::new(source).context("Failed to read .wav file")?;
+    let spec = wav_reader.spec();
+    let sample_rate = spec.sample_rate as f64;
+    let start_frame = ((start_seconds - options.pre_roll_seconds).max(0.0) * sample_rate)
+        .round()
+        .min(wav_reader.duration() as f64) as u32;
+    let end_frame = ((end_seconds + options.post_roll_seconds) * sample_rate)
+        .round()
+        .min(wav_reader.duration() as f64) as u32;
+    if end_frame <= start_frame {
+        bail!(
+            "Region {}s to {}s is past the end of the file",
+            start_seconds,
+            end_seconds
+        );
+    }
+    wav_reader
+        .seek(start_frame)
+        .context("Failed to seek to the region")?;
+    let n_frames = (end_frame - start_frame) as usize;
+    let n_samples = n_frames * spec.channels as usize;
+    let fades = Fades {
+        n_frames,
+        fade_in_frames: (options.fade_in_seconds.max(0.0) * sample_rate).round() as usize,
+        fade_out_frames: (options.fade_out_seconds.max(0.0) * sample_rate).round() as usize,
+    };
+
+    let mut clip = Cursor::new(vec![]);
+    let mut wav_writer = WavWriter::new(&mut clip, spec).context("Failed to start the clip")?;
+    match spec.sample_format {
+        SampleFormat::Float => {
+            for (i, sample) in wav_reader.samples::<f32>().take(n_samples).enumerate() {
+                let gain = fades.gain(i / spec.channels as usize);
+                wav_writer.write_sample(sample? * gain as f32)?;
+            }
+        }
+        SampleFormat::Int => {
+            for (i, sample) in wav_reader.samples::<i32>().take(n_samples).enumerate() {
+                let gain = fades.gain(i / spec.channels as usize);
+                wav_writer.write_sample((sample? as f64 * gain).round() as i32)?;
+            }
+        }
+    }
+    wav_writer.finalize().context("Failed to finish the clip")?;
+
+    let bext = bext_chunk(
+        &format!(
+            "{:.3}s to {:.3}s of {}",
+            start_frame as f64 / sample_rate,
+            end_frame as f64 / sample_rate,
+            display_path(source_path)
+        ),
+        &source_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        source_time_reference + start_frame as u64,
+        &spec,
+    );
+    Ok(insert_chunk(clip.into_inner(), &bext))
+}
+
+struct Fades {
+    n_frames: usize,
+    fade_in_frames: usize,
+    fade_out_frames: usize,
+}
+
+impl Fades {
+    /// The gain of a frame of the clip, from 0 at its edges up to 1
+    fn gain(&self, frame: usize) -> f64 {
+        let mut gain = 1.0;
+        if frame < self.fade_in_frames {
+            gain *= frame as f64 / self.fade_in_frames as f64;
+        }
+        let frames_to_end = self.n_frames - 1 - frame;
+        if frames_to_end < self.fade_out_frames {
+            gain *= frames_to_end as f64 / self.fade_out_frames as f64;
+        }
+        gain
+    }
+}
+
+/// The BWF time reference of a WAV file, in samples since midnight, if it has a `bext` chunk
+fn read_time_reference<R: Read + Seek>(source: &mut R) -> Result<Option<u64>> {
+    let mut header = [0; 12];
+    source.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        bail!("Not a RIFF WAVE file");
+    }
+    loop {
+        let mut chunk_header = [0; 8];
+        match source.read_exact(&mut chunk_header) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        let chunk_len = u32::from_le_bytes(chunk_header[4..8].try_into()?) as usize;
+        if &chunk_header[0..4] == b"bext" && chunk_len >= BEXT_TIME_REFERENCE_OFFSET + 8 {
+            source.seek(SeekFrom::Current(BEXT_TIME_REFERENCE_OFFSET as i64))?;
+            let mut time_reference = [0; 8];
+            source.read_exact(&mut time_reference)?;
+            return Ok(Some(u64::from_le_bytes(time_reference)));
+        }
+        // Chunks are padded to an even length
+        source.seek(SeekFrom::Current((chunk_len + chunk_len % 2) as i64))?;
+    }
+}
+
+/// A version 1 `bext` chunk, including its header
+fn bext_chunk(
+    description: &str,
+    originator_reference: &str,
+    time_reference: u64,
+    spec: &WavSpec,
+) -> Vec<u8> {
+    let now = Local::now();
+    let coding_history = format!(
+        "A=PCM,F={},W={},M={},T={}\r\n",
+        spec.sample_rate,
+        spec.bits_per_sample,
+        match spec.channels {
+            1 => "mono",
+            2 => "stereo",
+            _ => "multi",
+        },
+        ORIGINATOR
+    );
+
+    let mut bext = Vec::with_capacity(BEXT_FIXED_LEN + coding_history.len());
+    push_fixed(&mut bext, description, 256);
+    push_fixed(&mut bext, ORIGINATOR, 32);
+    push_fixed(&mut bext, originator_reference, 32);
+    push_fixed(&mut bext, &now.format("%Y-%m-%d").to_string(), 10);
+    push_fixed(&mut bext, &now.format("%H:%M:%S").to_string(), 8);
+    bext.extend_from_slice(&time_reference.to_le_bytes());
+    bext.extend_from_slice(&1_u16.to_le_bytes());
+    // No UMID, and the reserved bytes
+    bext.resize(BEXT_FIXED_LEN, 0);
+    bext.extend_from_slice(coding_history.as_bytes());
+    if bext.len() % 2 == 1 {
+        bext.push(0);
+    }
+
+    let mut chunk = b"bext".to_vec();
+    chunk.extend_from_slice(&(bext.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(&bext);
+    chunk
+}
+
+/// Write `text` into a fixed-size field, truncated on a character boundary and padded with zeros
+fn push_fixed(bytes: &mut Vec<u8>, text: &str, len: usize) {
+    let mut end = text.len().min(len);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    bytes.extend_from_slice(&text.as_bytes()[..end]);
+    bytes.resize(bytes.len() + len - end, 0);
+}
+
+/// Insert a chunk right after the RIFF header, where BWF readers look for `bext` first
+fn insert_chunk(wav: Vec<u8>, chunk: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(wav.len() + chunk.len());
+    output.extend_from_slice(&wav[..12]);
+    output.extend_from_slice(chunk);
+    output.extend_from_slice(&wav[12..]);
+    let riff_len = (output.len() - 8) as u32;
+    output[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One second of a stereo 16-bit ramp at 8kHz, the right channel inverted
+    fn source_wav() -> Vec<u8> {
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut wav = Cursor::new(vec![]);
+        let mut wav_writer = WavWriter::new(&mut wav, spec).unwrap();
+        for frame in 0..8000 {
+            wav_writer.write_sample(frame as i16).unwrap();
+            wav_writer.write_sample(-frame as i16).unwrap();
+        }
+        wav_writer.finalize().unwrap();
+        wav.into_inner()
+    }
+
+    #[test]
+    fn test_render_region_keeps_format_and_timecode() {
+        let options = RegionOptions {
+            pre_roll_seconds: 0.125,
+            post_roll_seconds: 10.0,
+            fade_in_seconds: 0.0,
+            fade_out_seconds: 0.0,
+        };
+        let clip = render_region(
+            Cursor::new(source_wav()),
+            Path::new("/Sounds/ramp.wav"),
+            0.25,
+            0.5,
+            &options,
+        )
+        .unwrap();
+
+        let mut clip_reader = WavReader::new(Cursor::new(&clip)).unwrap();
+        assert_eq!(clip_reader.spec().sample_rate, 8000);
+        assert_eq!(clip_reader.spec().bits_per_sample, 16);
+        // The post-roll stops at the end of the source
+        assert_eq!(clip_reader.duration(), 7000);
+        let samples = clip_reader
+            .samples::<i16>()
+            .take(2)
+            .collect::<result::Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(samples, vec![1000, -1000]);
+        assert_eq!(
+            read_time_reference(&mut Cursor::new(&clip)).unwrap(),
+            Some(1000)
+        );
+
+        // A clip of a clip keeps counting from the first source's timecode
+        let clip_of_clip = render_region(
+            Cursor::new(clip),
+            Path::new("/Clips/ramp.wav"),
+            0.5,
+            0.75,
+            &RegionOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            read_time_reference(&mut Cursor::new(&clip_of_clip)).unwrap(),
+            Some(5000)
+        );
+    }
+
+    #[test]
+    fn test_fades_reach_zero_at_the_edges() {
+        let fades = Fades {
+            n_frames: 100,
+            fade_in_frames: 10,
+            fade_out_frames: 20,
+        };
+        assert_eq!(fades.gain(0), 0.0);
+        assert_eq!(fades.gain(5), 0.5);
+        assert_eq!(fades.gain(50), 1.0);
+        assert_eq!(fades.gain(89), 0.5);
+        assert_eq!(fades.gain(99), 0.0);
+    }
+}
@@ -0,0 +1,305 @@
+//! Relevance feedback: refine a search from the results the user marked as good or bad,
+//! by moving the query towards the good segments and away from the bad ones (Rocchio).
+
+use std::result;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use log::{info, warn};
+use sqlx::SqlitePool;
+use tauri::State;
+
+use crate::search::{combine_embeddings, mark_unavailable_results};
+use crate::state::database::vector_index::{self, SearchResult};
+use crate::state::{
+    database::{decode_embedding, encode_embedding},
+    AppState,
+};
+
+/// Rocchio's α, β and γ: how much the original query, the mean of the relevant segments
+/// and the mean of the irrelevant ones count towards the refined query
+const QUERY_WEIGHT: f32 = 1.0;
+const RELEVANT_WEIGHT: f32 = 0.75;
+const IRRELEVANT_WEIGHT: f32 = 0.15;
+
+#[derive(serde::Serialize, specta::Type)]
+pub struct RefinedSearch {
+    /// The refined query, which found the results
+    query_embedding: Vec<f32>,
+    results: Vec<SearchResult>,
+}
+
+/// Search again with the original query refined by the segments marked so far, in every round.
+/// `query_embedding` is the query that found the results the user is looking at,
+/// which is saved along with the original query if `persist_feedback` is set.
+/// Segments marked irrelevant are left out of the new results.
+#[tauri::command]
+#[specta::specta]
+pub async fn refine_search(
+    app_state: State<'_, AppState>,
+    original_query_embedding: Vec<f32>,
+    query_embedding: Vec<f32>,
+    relevant_segment_ids: Vec<i64>,
+    irrelevant_segment_ids: Vec<i64>,
+    persist_feedback: bool,
+) -> result::Result<RefinedSearch, String> {
+    info!(
+        "Refining search with {} relevant and {} irrelevant segments",
+        relevant_segment_ids.len(),
+        irrelevant_segment_ids.len()
+    );
+    let feedback = Feedback {
+        original_query_embedding: &original_query_embedding,
+        query_embedding: &query_embedding,
+        relevant_segment_ids: &relevant_segment_ids,
+        irrelevant_segment_ids: &irrelevant_segment_ids,
+    };
+    refine(&app_state, &feedback, persist_feedback)
+        .await
+        .map_err(|err| format!("Failed to refine search: {:?}", err))
+}
+
+/// The segments marked in every round of refining a query
+struct Feedback<'a> {
+    original_query_embedding: &'a [f32],
+    /// The query that found the results last shown
+    query_embedding: &'a [f32],
+    relevant_segment_ids: &'a [i64],
+    irrelevant_segment_ids: &'a [i64],
+}
+
+async fn refine(
+    app_state: &AppState,
+    feedback: &Feedback<'_>,
+    persist_feedback: bool,
+) -> Result<RefinedSearch> {
+    let pool = &app_state.pool;
+    let relevant = get_segment_embeddings(pool, feedback.relevant_segment_ids).await?;
+    let irrelevant = get_segment_embeddings(pool, feedback.irrelevant_segment_ids).await?;
+    // From the original query, since the marks of earlier rounds are sent again
+    let refined_embedding = rocchio(feedback.original_query_embedding, &relevant, &irrelevant)?;
+    if persist_feedback {
+        save_feedback(pool, feedback).await?;
+    }
+
+    let vector_index = app_state.vector_index.read().await;
+    let mut results = vector_index::get_knn(&refined_embedding, pool, &vector_index)
+        .await?
+        .into_iter()
+        .filter(|result| !feedback.irrelevant_segment_ids.contains(&result.segment_id))
+        .collect::<Vec<_>>();
+    mark_unavailable_results(&mut results, pool).await?;
+    Ok(RefinedSearch {
+        query_embedding: refined_embedding,
+        results,
+    })
+}
+
+/// Segments that are no longer indexed are skipped
+async fn get_segment_embeddings(pool: &SqlitePool, segment_ids: &[i64]) -> Result<Vec<Vec<f32>>> {
+    let mut embeddings = vec![];
+    for segment_id in segment_ids {
+        let embedding = sqlx::query_scalar!(
+            "SELECT embedding FROM audio_file_segment WHERE rowid = ?",
+            segment_id
+        )
+        .fetch_optional(pool)
+        .await
+        .context(format!("Failed to get embedding of segment {}", segment_id))?;
+        match embedding {
+            Some(embedding) => embeddings.push(decode_embedding(&embedding).context(format!(
+                "Could not decode embedding of segment {}",
+                segment_id
+            ))?),
+            None => warn!("Skipping feedback on missing segment {}", segment_id),
+        }
+    }
+    Ok(embeddings)
+}
+
+/// The query plus β times the mean of the relevant embeddings, minus γ times the mean
+/// of the irrelevant ones, all normalized first
+fn rocchio(query: &[f32], relevant: &[Vec<f32>], irrelevant: &[Vec<f32>]) -> Result<Vec<f32>> {
+    let mut weighted_embeddings = vec![(query.to_vec(), QUERY_WEIGHT)];
+    weighted_embeddings.extend(
+        relevant
+            .iter()
+            .map(|embedding| (embedding.clone(), RELEVANT_WEIGHT / relevant.len() as f32)),
+    );
+    weighted_embeddings.extend(irrelevant.iter().map(|embedding| {
+        (
+            embedding.clone(),
+            -IRRELEVANT_WEIGHT / irrelevant.len() as f32,
+        )
+    }));
+    combine_embeddings(&weighted_embeddings)
+}
+
+/// A segment marked in an earlier round keeps the query it was marked for,
+/// but takes its new mark if the user changed it
+async fn save_feedback(pool: &SqlitePool, feedback: &Feedback<'_>) -> Result<()> {
+    let encoded_original_query_embedding = encode_embedding(feedback.original_query_embedding);
+    let encoded_query_embedding = encode_embedding(feedback.query_embedding);
+    let created_at = Utc::now().timestamp();
+    let marks = feedback
+        .relevant_segment_ids
+        .iter()
+        .map(|segment_id| (segment_id, true))
+        .chain(
+            feedback
+                .irrelevant_segment_ids
+                .iter()
+                .map(|segment_id| (segment_id, false)),
+        );
+    let mut sql_transaction = pool
+        .begin()
+        .await
+        .context("Failed to start saving feedback")?;
+    for (segment_id, relevant) in marks {
+        sqlx::query!(
+            r#"INSERT INTO search_feedback
+                (original_query_embedding, query_embedding, segment_id, relevant, created_at)
+            SELECT ?, ?, rowid, ?, ? FROM audio_file_segment WHERE rowid = ?
+            ON CONFLICT (original_query_embedding, segment_id) DO UPDATE
+            SET relevant = excluded.relevant, created_at = excluded.created_at
+            WHERE relevant != excluded.relevant"#,
+            encoded_original_query_embedding,
+            encoded_query_embedding,
+            relevant,
+            created_at,
+            segment_id
+        )
+        .execute(&mut *sql_transaction)
+        .await
+        .context(format!("Failed to save feedback on segment {}", segment_id))?;
+    }
+    sql_transaction
+        .commit()
+        .await
+        .context("Failed to save feedback")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::normalize;
+    use crate::state::database::create_test_pool;
+
+    #[test]
+    fn test_rocchio() {
+        let query = [1.0, 0.0, 0.0];
+        let relevant = [vec![0.0, 2.0, 0.0], vec![0.0, 0.0, 2.0]];
+        let irrelevant = [vec![-1.0, 0.0, 0.0]];
+        let refined = rocchio(&query, &relevant, &irrelevant).unwrap();
+
+        let expected = normalize(&[1.15, 0.375, 0.375]).unwrap();
+        for (refined_coord, expected_coord) in refined.iter().zip(expected) {
+            assert!((refined_coord - expected_coord).abs() < 1e-6);
+        }
+        // Without feedback, the query stays put
+        assert_eq!(rocchio(&query, &[], &[]).unwrap(), query.to_vec());
+    }
+
+    async fn create_pool_with_segments() -> SqlitePool {
+        let pool = create_test_pool().await;
+        let embedding = encode_embedding(&[1.0, 0.0]);
+        sqlx::query!("INSERT INTO audio_file (file_hash) VALUES ('rain_hash')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query!(
+            "INSERT INTO audio_file_segment
+                (rowid, file_hash, starting_timestamp, ending_timestamp, embedding)
+            VALUES (1, 'rain_hash', 0, 10, ?), (2, 'rain_hash', 5, 15, ?)",
+            embedding,
+            embedding
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool
+    }
+
+    fn feedback<'a>(
+        query_embedding: &'a [f32],
+        relevant_segment_ids: &'a [i64],
+        irrelevant_segment_ids: &'a [i64],
+    ) -> Feedback<'a> {
+        Feedback {
+            original_query_embedding: &[0.0, 1.0],
+            query_embedding,
+            relevant_segment_ids,
+            irrelevant_segment_ids,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_save_feedback_skips_missing_segments() {
+        let pool = create_pool_with_segments().await;
+        save_feedback(&pool, &feedback(&[0.0, 1.0], &[1], &[2, 3]))
+            .await
+            .unwrap();
+        let feedback = sqlx::query!(
+            r#"SELECT segment_id, relevant as "relevant: bool" FROM search_feedback ORDER BY segment_id"#
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.segment_id, row.relevant))
+        .collect::<Vec<_>>();
+        assert_eq!(feedback, vec![(1, true), (2, false)]);
+        assert_eq!(
+            get_segment_embeddings(&pool, &[2, 3]).await.unwrap(),
+            vec![vec![1.0, 0.0]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_feedback_from_earlier_rounds_is_saved_once() {
+        let pool = create_pool_with_segments().await;
+        let (first_query, refined_query) = ([0.0, 1.0], [0.5, 0.5]);
+        save_feedback(&pool, &feedback(&first_query, &[1], &[]))
+            .await
+            .unwrap();
+        save_feedback(&pool, &feedback(&refined_query, &[1], &[2]))
+            .await
+            .unwrap();
+        // The user changed their mind about the first result
+        save_feedback(&pool, &feedback(&refined_query, &[], &[1, 2]))
+            .await
+            .unwrap();
+
+        let rows = sqlx::query!(
+            r#"SELECT
+                original_query_embedding as "original_query_embedding!",
+                query_embedding,
+                segment_id,
+                relevant as "relevant: bool"
+            FROM search_feedback
+            ORDER BY segment_id"#
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        let saved = rows
+            .into_iter()
+            .map(|row| {
+                (
+                    decode_embedding(&row.original_query_embedding).unwrap(),
+                    decode_embedding(&row.query_embedding).unwrap(),
+                    row.segment_id,
+                    row.relevant,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            saved,
+            vec![
+                (first_query.to_vec(), first_query.to_vec(), 1, false),
+                (first_query.to_vec(), refined_query.to_vec(), 2, false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! What the UI needs to show where a query matches in a result's file:
+//! the file's waveform, and how similar each of its segments is to the query.
+
+use std::result;
+
+use anyhow::{Context, Result};
+use log::warn;
+use sqlx::SqlitePool;
+use tauri::State;
+
+use crate::audio_index::file_location::{find_location_by_display_path, get_segment_file_hash};
+use crate::audio_index::waveform::{
+    compute_file_overview, get_overview, save_overview, WaveformOverview,
+};
+use crate::embeddings::cosine_similarity;
+use crate::state::{database::decode_embedding, AppState};
+
+#[derive(Clone, Debug, serde::Serialize, specta::Type)]
+pub struct FileOverview {
+    duration_seconds: Option<f64>,
+    waveform: WaveformOverview,
+    /// Every segment of the file in order, or none without a query
+    segments: Vec<SegmentSimilarity>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, specta::Type)]
+pub struct SegmentSimilarity {
+    starting_timestamp: f64,
+    ending_timestamp: f64,
+    /// Cosine similarity to the query, higher is closer
+    similarity: f32,
+}
+
+struct SegmentRow {
+    starting_timestamp: f64,
+    ending_timestamp: f64,
+    embedding: Vec<u8>,
+}
+
+/// Get the waveform of a result's file, and the similarity of each of its segments to `query_embedding`,
+/// the embedding the results were searched with.
+/// `file_path` is the result's path as shown, which is looked up among the locations
+/// of the file that `segment_id` was cut from
+#[tauri::command]
+#[specta::specta]
+pub async fn get_file_overview(
+    app_state: State<'_, AppState>,
+    segment_id: i64,
+    file_path: String,
+    query_embedding: Option<Vec<f32>>,
+) -> result::Result<FileOverview, String> {
+    build_file_overview(
+        &app_state,
+        segment_id,
+        &file_path,
+        query_embedding.as_deref(),
+    )
+    .await
+    .map_err(|err| format!("Failed to get file overview: {:?}", err))
+}
+
+async fn build_file_overview(
+    app_state: &AppState,
+    segment_id: i64,
+    file_path: &str,
+    query_embedding: Option<&[f32]>,
+) -> Result<FileOverview> {
+    let pool = &app_state.pool;
+    let file_hash = get_segment_file_hash(pool, segment_id).await?;
+    let duration_seconds = sqlx::query_scalar!(
+        "SELECT duration_seconds FROM audio_file WHERE file_hash = ?",
+        file_hash
+    )
+    .fetch_one(pool)
+    .await
+    .context(format!("Failed to get duration of {}", file_path))?;
+
+    let waveform = match get_overview(pool, &file_hash).await? {
+        Some(waveform) => waveform,
+        // Files indexed before overviews were computed
+        None => {
+            let file_path = find_location_by_display_path(pool, &file_hash, file_path).await?;
+            // Decoding the whole file is CPU-bound, so keep it off the async executor
+            let waveform = tokio::task::spawn_blocking(move || compute_file_overview(&file_path))
+                .await
+                .context("Failed to join waveform overview task")??;
+            if let Err(err) = save_overview(pool, &file_hash, &waveform).await {
+                warn!("{:?}", err);
+            }
+            waveform
+        }
+    };
+
+    let segments = match query_embedding {
+        Some(query_embedding) => {
+            get_segment_similarities(pool, &file_hash, query_embedding).await?
+        }
+        None => vec![],
+    };
+
+    Ok(FileOverview {
+        duration_seconds,
+        waveform,
+        segments,
+    })
+}
+
+async fn get_segment_similarities(
+    pool: &SqlitePool,
+    file_hash: &str,
+    query_embedding: &[f32],
+) -> Result<Vec<SegmentSimilarity>> {
+    let rows = sqlx::query_as!(
+        SegmentRow,
+        r#"SELECT
+            starting_timestamp,
+            ending_timestamp,
+            embedding
+        FROM audio_file_segment
+        WHERE file_hash = ?
+        ORDER BY starting_timestamp"#,
+        file_hash
+    )
+    .fetch_all(pool)
+    .await
+    .context(format!("Failed to get segments of {}", file_hash))?;
+    rows.into_iter()
+        .map(|row| {
+            let embedding = decode_embedding(&row.embedding)
+                .context(format!("Could not decode embedding of {}", file_hash))?;
+            Ok(SegmentSimilarity {
+                starting_timestamp: row.starting_timestamp,
+                ending_timestamp: row.ending_timestamp,
+                similarity: cosine_similarity(query_embedding, &embedding),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,555 @@
+//! An opt-in JSON API on localhost, so scripts and other tools can search the library
+//! and manage its roots without the GUI. Every request needs the API token,
+//! sent as `Authorization: Bearer <token>`.
+
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::result;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::extract::{Query, Request, State as ApiState};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use futures::lock::Mutex;
+use futures::stream::{self, Stream, StreamExt};
+use log::{error, info};
+use sqlx::SqlitePool;
+use tauri::async_runtime::{self, JoinHandle};
+use tauri::{AppHandle, Manager, State};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+
+use crate::audio_index::indexing_status::Status;
+use crate::index_paths::{
+    add_path_to_db, canonicalize_path, delete_path_from_index, get_index_roots, set_root_options,
+    IndexRoot, RootOptions,
+};
+use crate::search::{
+    search_by_audio_file, search_index, search_with_structured_query, StructuredQuery,
+};
+use crate::spawn_update_audio_index;
+use crate::state::database::vector_index::SearchResult;
+use crate::state::AppState;
+
+#[derive(Clone, Debug, serde::Serialize, specta::Type)]
+pub struct HttpApiSettings {
+    enabled: bool,
+    /// The server only listens on localhost, at this port
+    port: u16,
+    token: String,
+}
+
+struct HttpApiSettingsRow {
+    enabled: bool,
+    port: i64,
+    token: String,
+}
+
+/// How long open requests get to finish when the server stops, before they're dropped
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The running server, if the API is enabled
+#[derive(Default)]
+pub struct HttpApi {
+    server: Mutex<Option<RunningServer>>,
+}
+
+/// A server that can be stopped, closing every connection to it
+struct RunningServer {
+    shutdown: watch::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl RunningServer {
+    /// Serve `router` until stopped. Anything that holds a connection open,
+    /// like a status stream, must end once `shutdown` is sent to
+    fn spawn(listener: TcpListener, router: Router, shutdown: watch::Sender<()>) -> Self {
+        let shutdown_receiver = shutdown.subscribe();
+        let handle = async_runtime::spawn(async move {
+            if let Err(err) = axum::serve(listener, router)
+                .with_graceful_shutdown(stopped(shutdown_receiver))
+                .await
+            {
+                error!("HTTP API stopped: {:?}", err);
+            }
+        });
+        Self { shutdown, handle }
+    }
+
+    /// Let open requests finish, then close every connection, kept-alive ones included,
+    /// so clients have to reconnect and present the current token
+    async fn stop(mut self) {
+        // Only fails if the server has already stopped
+        let _ = self.shutdown.send(());
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, &mut self.handle)
+            .await
+            .is_err()
+        {
+            self.handle.abort();
+        }
+    }
+}
+
+/// Resolves once the server is told to stop
+async fn stopped(mut shutdown: watch::Receiver<()>) {
+    // Errors if the server is gone, which means it's stopped too
+    let _ = shutdown.changed().await;
+}
+
+#[derive(Clone)]
+struct ApiContext {
+    app_handle: AppHandle,
+    shutdown: watch::Receiver<()>,
+}
+
+/// Errors are returned as `{ "error": message }`
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<String> for ApiError {
+    fn from(message: String) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+}
+
+type ApiResult<T> = result::Result<Json<T>, ApiError>;
+
+#[derive(serde::Deserialize)]
+struct SearchQuery {
+    q: String,
+}
+
+#[derive(serde::Deserialize)]
+struct PathBody {
+    path: String,
+}
+
+#[derive(serde::Deserialize)]
+struct RootOptionsBody {
+    path: String,
+    options: RootOptions,
+}
+
+/// Get whether the local API is enabled, along with its port and token
+#[tauri::command]
+#[specta::specta]
+pub async fn get_http_api_settings(
+    app_state: State<'_, AppState>,
+) -> result::Result<HttpApiSettings, String> {
+    get_settings_from_db(&app_state.pool)
+        .await
+        .map_err(|err| format!("Failed to get HTTP API settings: {:?}", err))
+}
+
+/// Enable or disable the local API, restarting it on the new port
+#[tauri::command]
+#[specta::specta]
+pub async fn set_http_api_settings(
+    app_handle: AppHandle,
+    app_state: State<'_, AppState>,
+    enabled: bool,
+    port: u16,
+) -> result::Result<HttpApiSettings, String> {
+    if port == 0 {
+        return Err("Port must be between 1 and 65535".to_string());
+    }
+    sqlx::query!(
+        "UPDATE http_api_settings SET enabled = ?, port = ? WHERE id = 1",
+        enabled,
+        port
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(|err| format!("Failed to save HTTP API settings: {:?}", err))?;
+    restart_server(&app_handle, &app_state)
+        .await
+        .map_err(|err| format!("Failed to start HTTP API: {:?}", err))
+}
+
+/// Replace the API token, so clients with the old one are locked out
+#[tauri::command]
+#[specta::specta]
+pub async fn reset_http_api_token(
+    app_handle: AppHandle,
+    app_state: State<'_, AppState>,
+) -> result::Result<HttpApiSettings, String> {
+    sqlx::query!("UPDATE http_api_settings SET token = lower(hex(randomblob(16))) WHERE id = 1")
+        .execute(&app_state.pool)
+        .await
+        .map_err(|err| format!("Failed to reset HTTP API token: {:?}", err))?;
+    restart_server(&app_handle, &app_state)
+        .await
+        .map_err(|err| format!("Failed to restart HTTP API: {:?}", err))
+}
+
+/// Start the server on launch if the user has enabled it
+pub async fn start_http_api_if_enabled(app_handle: &AppHandle) -> Result<()> {
+    restart_server(app_handle, &app_handle.state::<AppState>()).await?;
+    Ok(())
+}
+
+async fn get_settings_from_db(pool: &SqlitePool) -> Result<HttpApiSettings> {
+    let row = sqlx::query_as!(
+        HttpApiSettingsRow,
+        "SELECT enabled, port, token FROM http_api_settings WHERE id = 1"
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to get HTTP API settings")?;
+    Ok(HttpApiSettings {
+        enabled: row.enabled,
+        port: row.port as u16,
+        token: row.token,
+    })
+}
+
+/// Stop the server if it's running, then start it again with the saved settings if enabled
+async fn restart_server(app_handle: &AppHandle, app_state: &AppState) -> Result<HttpApiSettings> {
+    let settings = get_settings_from_db(&app_state.pool).await?;
+    let mut server = app_state.http_api.server.lock().await;
+    if let Some(running_server) = server.take() {
+        running_server.stop().await;
+        info!("Stopped HTTP API");
+    }
+    if !settings.enabled {
+        return Ok(settings);
+    }
+
+    // Bind before spawning, so a port that's taken is reported to the caller
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, settings.port))
+        .await
+        .context(format!("Failed to listen on port {}", settings.port))?;
+    let (shutdown, shutdown_receiver) = watch::channel(());
+    let router = build_router(
+        ApiContext {
+            app_handle: app_handle.clone(),
+            shutdown: shutdown_receiver,
+        },
+        settings.token.clone(),
+    );
+    info!("HTTP API listening on http://localhost:{}", settings.port);
+    *server = Some(RunningServer::spawn(listener, router, shutdown));
+    Ok(settings)
+}
+
+fn build_router(context: ApiContext, token: String) -> Router {
+    let routes = Router::new()
+        .route("/search", get(search).post(search_structured))
+        .route("/search/audio", post(search_by_audio))
+        .route("/status", get(status))
+        .route("/status/events", get(status_events))
+        .route("/roots", get(roots).post(add_root).delete(delete_root))
+        .route("/roots/options", put(update_root_options))
+        .route("/index/update", post(update_index));
+    with_token_check(routes, token).with_state(context)
+}
+
+/// Reject requests to `routes` that don't carry `token`, before they're parsed
+fn with_token_check<S>(routes: Router<S>, token: String) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    routes.layer(middleware::from_fn_with_state(token, require_token))
+}
+
+async fn require_token(
+    ApiState(expected_token): ApiState<String>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    match authorization.and_then(bearer_token) {
+        Some(token) if tokens_match(token, &expected_token) => next.run(request).await,
+        _ => ApiError(
+            StatusCode::UNAUTHORIZED,
+            "Missing or wrong API token".into(),
+        )
+        .into_response(),
+    }
+}
+
+fn bearer_token(authorization: &str) -> Option<&str> {
+    authorization
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim())
+        .filter(|token| !token.is_empty())
+}
+
+/// Compares every byte, so the time taken doesn't reveal how much of a guess was right
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// `GET /search?q=...`
+async fn search(
+    ApiState(context): ApiState<ApiContext>,
+    Query(query): Query<SearchQuery>,
+) -> ApiResult<Vec<SearchResult>> {
+    let app_state = context.app_handle.state::<AppState>();
+    Ok(Json(
+        search_index(app_state, context.app_handle.clone(), &query.q).await?,
+    ))
+}
+
+/// `POST /search` with a structured query, e.g. rain without thunder:
+/// `{ "text_terms": [{ "text": "rain", "weight": 1 }, { "text": "thunder", "weight": -0.5 }] }`
+async fn search_structured(
+    ApiState(context): ApiState<ApiContext>,
+    Json(query): Json<StructuredQuery>,
+) -> ApiResult<Vec<SearchResult>> {
+    let app_state = context.app_handle.state::<AppState>();
+    search_with_structured_query(&app_state, &context.app_handle, &query)
+        .await
+        .map(Json)
+        .map_err(|err| format!("Failed to search: {:?}", err).into())
+}
+
+/// `POST /search/audio` with `{ "path": ... }`, a file on this machine to find sounds like
+async fn search_by_audio(
+    ApiState(context): ApiState<ApiContext>,
+    Json(body): Json<PathBody>,
+) -> ApiResult<Vec<SearchResult>> {
+    let path = PathBuf::from(&body.path);
+    if !path.is_file() {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            format!("{} is not a file", body.path),
+        ));
+    }
+    let app_state = context.app_handle.state::<AppState>();
+    search_by_audio_file(&app_state, &path)
+        .await
+        .map(Json)
+        .map_err(|err| format!("Failed to search by audio: {:?}", err).into())
+}
+
+/// `GET /status`
+async fn status(ApiState(context): ApiState<ApiContext>) -> ApiResult<Status> {
+    let app_state = context.app_handle.state::<AppState>();
+    Ok(Json(app_state.indexing_status.get_status().await))
+}
+
+/// `GET /status/events`, a stream of server-sent events starting with the current status
+async fn status_events(
+    ApiState(context): ApiState<ApiContext>,
+) -> Sse<impl Stream<Item = result::Result<Event, axum::Error>>> {
+    let app_state = context.app_handle.state::<AppState>();
+    let receiver = app_state.indexing_status.subscribe();
+    let current_status = app_state.indexing_status.get_status().await;
+    let updates = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(status) => return Some((status, receiver)),
+                // Only the latest status matters, so missed ones are skipped
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    let events = stream::once(async { current_status })
+        .chain(updates)
+        .map(|status| Event::default().json_data(status))
+        // Otherwise the stream would keep its connection, and the server, running forever
+        .take_until(stopped(context.shutdown.clone()));
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// `GET /roots`
+async fn roots(ApiState(context): ApiState<ApiContext>) -> ApiResult<Vec<IndexRoot>> {
+    let app_state = context.app_handle.state::<AppState>();
+    Ok(Json(get_index_roots(app_state).await?))
+}
+
+/// `POST /roots` with `{ "path": ... }`. Indexing the new root carries on after the response
+async fn add_root(
+    ApiState(context): ApiState<ApiContext>,
+    Json(body): Json<PathBody>,
+) -> ApiResult<Vec<IndexRoot>> {
+    let path =
+        canonicalize_path(&body.path).map_err(|err| ApiError(StatusCode::BAD_REQUEST, err))?;
+    let app_state = context.app_handle.state::<AppState>();
+    add_path_to_db(&app_state.pool, path).await?;
+    spawn_update_audio_index(context.app_handle.clone());
+    Ok(Json(get_index_roots(app_state).await?))
+}
+
+/// `DELETE /roots` with `{ "path": ... }`
+async fn delete_root(
+    ApiState(context): ApiState<ApiContext>,
+    Json(body): Json<PathBody>,
+) -> ApiResult<Vec<IndexRoot>> {
+    delete_path_from_index(context.app_handle.state::<AppState>(), body.path).await?;
+    Ok(Json(
+        get_index_roots(context.app_handle.state::<AppState>()).await?,
+    ))
+}
+
+/// `PUT /roots/options` with `{ "path": ..., "options": ... }`
+async fn update_root_options(
+    ApiState(context): ApiState<ApiContext>,
+    Json(body): Json<RootOptionsBody>,
+) -> ApiResult<Vec<IndexRoot>> {
+    let app_state = context.app_handle.state::<AppState>();
+    set_root_options(app_state, body.path, body.options)
+        .await
+        .map(Json)
+        .map_err(|err| ApiError(StatusCode::BAD_REQUEST, err))
+}
+
+/// `POST /index/update`. Returns straight away; follow the progress on `/status/events`
+async fn update_index(ApiState(context): ApiState<ApiContext>) -> StatusCode {
+    spawn_update_audio_index(context.app_handle);
+    StatusCode::ACCEPTED
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{self, Body};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// The API's own routes need the running app, so the token check
+    /// is tried on a route that only parses the search query
+    fn search_routes() -> Router {
+        Router::new().route(
+            "/search",
+            get(|Query(query): Query<SearchQuery>| async move { query.q }),
+        )
+    }
+
+    async fn send(uri: &str, authorization: Option<&str>) -> (StatusCode, String) {
+        let mut request = axum::http::Request::builder().uri(uri);
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        let response = with_token_check(search_routes(), "abc123".to_string())
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token("Bearer abc123"), Some("abc123"));
+        assert_eq!(bearer_token("Bearer "), None);
+        assert_eq!(bearer_token("Basic abc123"), None);
+
+        assert!(tokens_match("abc123", "abc123"));
+        assert!(!tokens_match("abc124", "abc123"));
+        assert!(!tokens_match("abc12", "abc123"));
+    }
+
+    #[tokio::test]
+    async fn test_requests_without_the_token_are_unauthorized() {
+        for authorization in [None, Some("Bearer abc124"), Some("abc123")] {
+            let (status, body) = send("/search?q=rain", authorization).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(body, r#"{"error":"Missing or wrong API token"}"#);
+        }
+        // Checked before the request is parsed
+        let (status, _) = send("/search", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_requests_with_the_token_are_handled() {
+        let (status, body) = send("/search?q=rain", Some("Bearer abc123")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "rain");
+    }
+
+    #[tokio::test]
+    async fn test_bad_requests_are_rejected() {
+        let (status, _) = send("/search", Some("Bearer abc123")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    fn serve(listener: TcpListener, token: &str) -> RunningServer {
+        let (shutdown, _) = watch::channel(());
+        let router = with_token_check(search_routes(), token.to_string());
+        RunningServer::spawn(listener, router, shutdown)
+    }
+
+    /// Search on an open connection, keeping it alive, and return the response's status line
+    async fn search_on(connection: &mut TcpStream, token: &str) -> String {
+        let request = format!(
+            "GET /search?q=rain HTTP/1.1\r\nhost: localhost\r\nauthorization: Bearer {}\r\n\r\n",
+            token
+        );
+        connection.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        let mut buffer = [0; 1024];
+        loop {
+            let n_read = connection.read(&mut buffer).await.unwrap();
+            assert!(n_read > 0, "Connection closed before the response ended");
+            response.push_str(std::str::from_utf8(&buffer[..n_read]).unwrap());
+            if let Some((head, body)) = response.split_once("\r\n\r\n") {
+                let content_length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length: "))
+                    .map_or(0, |length| length.parse().unwrap());
+                if body.len() >= content_length {
+                    return head.lines().next().unwrap().to_string();
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restarting_closes_kept_alive_connections() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = serve(listener, "abc123");
+        let mut connection = TcpStream::connect(address).await.unwrap();
+        assert_eq!(
+            search_on(&mut connection, "abc123").await,
+            "HTTP/1.1 200 OK"
+        );
+
+        // Restart with a new token, as resetting it does
+        server.stop().await;
+        let server = serve(TcpListener::bind(address).await.unwrap(), "def456");
+        // The old connection is closed, rather than kept open with the old token
+        let read = tokio::time::timeout(SHUTDOWN_TIMEOUT, connection.read(&mut [0; 1024]))
+            .await
+            .expect("Kept-alive connection should be closed");
+        assert!(matches!(read, Ok(0) | Err(_)));
+        let mut connection = TcpStream::connect(address).await.unwrap();
+        assert_eq!(
+            search_on(&mut connection, "abc123").await,
+            "HTTP/1.1 401 Unauthorized"
+        );
+        assert_eq!(
+            search_on(&mut connection, "def456").await,
+            "HTTP/1.1 200 OK"
+        );
+        server.stop().await;
+    }
+}
@@ -1,10 +1,99 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use globset::Glob;
 use sqlx::SqlitePool;
 use tauri::State;
 
-use crate::{audio_index::update_audio_index, state::AppState};
+use crate::{
+    audio_index::{timestamp_to_date_time, update_audio_index},
+    paths::{
+        decode_path, display_path, encode_path, serialize_display_path,
+        serialize_display_path_option,
+    },
+    state::AppState,
+};
+
+/// How a root path is scanned for audio files
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize, specta::Type)]
+pub struct RootOptions {
+    /// Disabled roots are skipped when scanning, but stay indexed
+    pub enabled: bool,
+    /// If any are given, only files matching one of these are indexed.
+    /// Globs are matched against the path relative to the root, e.g. `**/Foley/**`
+    pub include_globs: Vec<String>,
+    /// Files matching any of these are skipped, e.g. `**/Bounces/**` or `*_preview.wav`
+    pub exclude_globs: Vec<String>,
+    /// How many directories deep to look. The root's own files are at depth 1
+    pub max_depth: Option<u32>,
+    pub follow_symlinks: bool,
+    pub min_duration_seconds: Option<f64>,
+    pub max_duration_seconds: Option<f64>,
+}
+
+impl Default for RootOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            include_globs: vec![],
+            exclude_globs: vec![],
+            max_depth: None,
+            follow_symlinks: false,
+            min_duration_seconds: None,
+            max_duration_seconds: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, specta::Type)]
+pub struct IndexRoot {
+    #[serde(serialize_with = "serialize_display_path")]
+    pub path: PathBuf,
+    pub options: RootOptions,
+    /// The innermost other root this one is nested in, if any.
+    /// A nested root's options override its parent's for the nested subtree
+    #[serde(serialize_with = "serialize_display_path_option")]
+    pub parent: Option<PathBuf>,
+    /// Offline roots, e.g. on an unmounted drive, aren't scanned but stay indexed
+    pub online: bool,
+    pub last_seen_at: Option<DateTime<Utc>>,
+}
+
+struct IndexRootRow {
+    path: Vec<u8>,
+    enabled: bool,
+    include_globs: String,
+    exclude_globs: String,
+    max_depth: Option<i64>,
+    follow_symlinks: bool,
+    min_duration_seconds: Option<f64>,
+    max_duration_seconds: Option<f64>,
+    online: bool,
+    last_seen_at: Option<i64>,
+}
+
+impl TryFrom<IndexRootRow> for IndexRoot {
+    type Error = serde_json::Error;
+
+    fn try_from(row: IndexRootRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            path: decode_path(&row.path),
+            options: RootOptions {
+                enabled: row.enabled,
+                include_globs: serde_json::from_str(&row.include_globs)?,
+                exclude_globs: serde_json::from_str(&row.exclude_globs)?,
+                max_depth: row.max_depth.map(|max_depth| max_depth as u32),
+                follow_symlinks: row.follow_symlinks,
+                min_duration_seconds: row.min_duration_seconds,
+                max_duration_seconds: row.max_duration_seconds,
+            },
+            parent: None,
+            online: row.online,
+            last_seen_at: row.last_seen_at.map(timestamp_to_date_time),
+        })
+    }
+}
 
 /// Add a path to the index
 #[tauri::command]
@@ -12,8 +101,8 @@ use crate::{audio_index::update_audio_index, state::AppState};
 pub async fn add_path_to_index(
     app_state: State<'_, AppState>,
     path: String,
-) -> Result<Vec<PathBuf>, String> {
-    let parsed_path = parse_path(&path).map_err(|e| e.to_string())?;
+) -> Result<Vec<String>, String> {
+    let parsed_path = canonicalize_path(&path)?;
     add_path_to_db(&app_state.pool, parsed_path).await?;
     // TODO: Dangerous clone!
     update_audio_index(app_state.clone())
@@ -29,9 +118,9 @@ pub async fn add_path_to_index(
 pub async fn add_paths_to_index(
     app_state: State<'_, AppState>,
     paths: Vec<String>,
-) -> Result<Vec<PathBuf>, String> {
+) -> Result<Vec<String>, String> {
     for path in &paths {
-        let parsed_path = parse_path(path)?;
+        let parsed_path = canonicalize_path(path)?;
         let pool = app_state.pool.clone();
         add_path_to_db(&pool, parsed_path).await?;
     }
@@ -44,15 +133,28 @@ pub async fn add_paths_to_index(
         .map_err(|e| e.to_string())
 }
 
-async fn add_path_to_db(pool: &SqlitePool, path: PathBuf) -> Result<(), String> {
-    let path = path.to_str().ok_or("Path is not valid UTF-8")?;
-    sqlx::query!("INSERT INTO dir_paths (path) VALUES (?)", path)
-        .execute(pool)
-        .await
-        .map_err(|e| e.to_string())?;
+/// Adding a path that's already in the index is a no-op.
+/// Paths nested in another root are kept as their own root, so they can override its options
+pub(crate) async fn add_path_to_db(pool: &SqlitePool, path: PathBuf) -> Result<(), String> {
+    let encoded_path = encode_path(&path);
+    sqlx::query!(
+        "INSERT OR IGNORE INTO dir_paths (path) VALUES (?)",
+        encoded_path
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
     Ok(())
 }
 
+/// Resolve symlinks, `..` and trailing separators,
+/// so the same directory can't be added twice under different names
+pub(crate) fn canonicalize_path(path: &str) -> Result<PathBuf, String> {
+    let path = parse_path(path)?;
+    path.canonicalize()
+        .map_err(|err| format!("Failed to canonicalize path {:?}: {:?}", path, err))
+}
+
 fn parse_path(path: &str) -> Result<PathBuf, String> {
     let path = PathBuf::new().join(path);
     match path.try_exists().map_err(|err| {
@@ -62,49 +164,195 @@ fn parse_path(path: &str) -> Result<PathBuf, String> {
         )
     })? {
         true => Ok(path),
-        false => Err(format!(
-            "Path does not exist: {}",
-            path.to_str().ok_or("[unparseable path]")?
-        )),
+        false => Err(format!("Path does not exist: {}", display_path(&path))),
     }
 }
 
 /// Get all paths from the index
 #[tauri::command]
 #[specta::specta]
-pub async fn get_paths_from_index(app_state: State<'_, AppState>) -> Result<Vec<PathBuf>, String> {
-    get_paths_from_db(&app_state.pool).await
+pub async fn get_paths_from_index(app_state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    get_display_paths_from_db(&app_state.pool).await
 }
 
-async fn get_paths_from_db(pool: &SqlitePool) -> Result<Vec<PathBuf>, String> {
+pub(crate) async fn get_paths_from_db(pool: &SqlitePool) -> Result<Vec<PathBuf>, String> {
     let paths = sqlx::query!("SELECT path FROM dir_paths")
         .fetch_all(pool)
         .await
         .map_err(|e| e.to_string())?;
     let paths = paths
         .into_iter()
-        .map(|path| PathBuf::new().join(path.path))
+        .map(|path| decode_path(&path.path))
         .collect();
     Ok(paths)
 }
 
-/// Delete a path from the index
+async fn get_display_paths_from_db(pool: &SqlitePool) -> Result<Vec<String>, String> {
+    let paths = get_paths_from_db(pool).await?;
+    Ok(paths.iter().map(|path| display_path(path)).collect())
+}
+
+/// The UI only knows the display form of each root,
+/// so find the stored path that it was made from
+async fn find_root_by_display_path(pool: &SqlitePool, display: &str) -> Result<PathBuf, String> {
+    get_paths_from_db(pool)
+        .await?
+        .into_iter()
+        .find(|path| display_path(path) == display)
+        .ok_or(format!("Path is not in the index: {}", display))
+}
+
+/// Get all paths from the index, along with how each is scanned
+#[tauri::command]
+#[specta::specta]
+pub async fn get_index_roots(app_state: State<'_, AppState>) -> Result<Vec<IndexRoot>, String> {
+    get_index_roots_from_db(&app_state.pool).await
+}
+
+pub(crate) async fn get_index_roots_from_db(pool: &SqlitePool) -> Result<Vec<IndexRoot>, String> {
+    let rows = sqlx::query_as!(
+        IndexRootRow,
+        r#"SELECT
+            path,
+            enabled as "enabled: bool",
+            include_globs,
+            exclude_globs,
+            max_depth,
+            follow_symlinks as "follow_symlinks: bool",
+            min_duration_seconds,
+            max_duration_seconds,
+            online as "online: bool",
+            last_seen_at
+        FROM dir_paths"#
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let mut roots = rows
+        .into_iter()
+        .map(|row| IndexRoot::try_from(row).map_err(|e| e.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let paths = roots
+        .iter()
+        .map(|root| root.path.clone())
+        .collect::<Vec<_>>();
+    for root in &mut roots {
+        root.parent = find_parent_root(&root.path, &paths).cloned();
+    }
+    Ok(roots)
+}
+
+/// The innermost root that `path` is under, or is
+pub(crate) fn find_innermost_root<'a>(
+    path: &Path,
+    roots: &'a [IndexRoot],
+) -> Option<&'a IndexRoot> {
+    roots
+        .iter()
+        .filter(|root| path.starts_with(&root.path))
+        .max_by_key(|root| root.path.components().count())
+}
+
+fn find_parent_root<'a>(path: &Path, roots: &'a [PathBuf]) -> Option<&'a PathBuf> {
+    roots
+        .iter()
+        .filter(|root| root.as_path() != path && path.starts_with(root))
+        .max_by_key(|root| root.components().count())
+}
+
+/// Set how a path in the index is scanned. Takes effect on the next index update
+#[tauri::command]
+#[specta::specta]
+pub async fn set_root_options(
+    app_state: State<'_, AppState>,
+    path: String,
+    options: RootOptions,
+) -> Result<Vec<IndexRoot>, String> {
+    validate_root_options(&options)?;
+    let root_path = encode_path(&find_root_by_display_path(&app_state.pool, &path).await?);
+    let include_globs = serde_json::to_string(&options.include_globs).map_err(|e| e.to_string())?;
+    let exclude_globs = serde_json::to_string(&options.exclude_globs).map_err(|e| e.to_string())?;
+    sqlx::query!(
+        r#"UPDATE dir_paths SET
+            enabled = ?,
+            include_globs = ?,
+            exclude_globs = ?,
+            max_depth = ?,
+            follow_symlinks = ?,
+            min_duration_seconds = ?,
+            max_duration_seconds = ?,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE path = ?"#,
+        options.enabled,
+        include_globs,
+        exclude_globs,
+        options.max_depth,
+        options.follow_symlinks,
+        options.min_duration_seconds,
+        options.max_duration_seconds,
+        root_path
+    )
+    .execute(&app_state.pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    get_index_roots_from_db(&app_state.pool).await
+}
+
+fn validate_root_options(options: &RootOptions) -> Result<(), String> {
+    for glob in options.include_globs.iter().chain(&options.exclude_globs) {
+        Glob::new(glob).map_err(|e| format!("Invalid glob {}: {}", glob, e))?;
+    }
+    if options.max_depth == Some(0) {
+        return Err("Max depth must be at least 1".to_string());
+    }
+    if let (Some(min), Some(max)) = (options.min_duration_seconds, options.max_duration_seconds) {
+        if min > max {
+            return Err(format!(
+                "Min duration {}s is longer than max duration {}s",
+                min, max
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Delete a path from the index. The path doesn't need to exist,
+/// so roots on volumes that are gone for good can still be removed
 #[tauri::command]
 #[specta::specta]
 pub async fn delete_path_from_index(
     app_state: State<'_, AppState>,
     path: String,
-) -> Result<Vec<PathBuf>, String> {
-    let parsed_path = parse_path(&path).map_err(|e| e.to_string())?;
-    delete_path_from_db(&app_state.pool, parsed_path).await?;
-    get_paths_from_db(&app_state.pool).await
+) -> Result<Vec<String>, String> {
+    let root_path = find_root_by_display_path(&app_state.pool, &path).await?;
+    delete_path_from_db(&app_state.pool, &root_path).await?;
+    get_display_paths_from_db(&app_state.pool).await
 }
 
-async fn delete_path_from_db(pool: &SqlitePool, path: PathBuf) -> Result<(), String> {
-    let path = path.to_str();
-    sqlx::query!("DELETE FROM dir_paths WHERE path = ?", path)
+async fn delete_path_from_db(pool: &SqlitePool, path: &Path) -> Result<(), String> {
+    let encoded_path = encode_path(path);
+    sqlx::query!("DELETE FROM dir_paths WHERE path = ?", encoded_path)
         .execute(pool)
         .await
         .map_err(|e| e.to_string())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_parent_root() {
+        let roots = [
+            PathBuf::from("/Sounds"),
+            PathBuf::from("/Sounds/Foley"),
+            PathBuf::from("/Sounds/Foley/Steps"),
+            PathBuf::from("/Sounds Archive"),
+        ];
+        assert_eq!(find_parent_root(&roots[0], &roots), None);
+        assert_eq!(find_parent_root(&roots[1], &roots), Some(&roots[0]));
+        assert_eq!(find_parent_root(&roots[2], &roots), Some(&roots[1]));
+        assert_eq!(find_parent_root(&roots[3], &roots), None);
+    }
+}
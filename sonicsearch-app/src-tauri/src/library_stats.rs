@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::result;
+
+use anyhow::{Context, Result};
+use sqlx::SqlitePool;
+use tauri::State;
+
+use crate::audio_index::index_run::{get_recent_runs, IndexRun};
+use crate::index_paths::get_paths_from_db;
+use crate::paths::{decode_path, serialize_display_path};
+use crate::state::AppState;
+
+/// How many of the latest indexing runs to include in the stats
+const N_RECENT_RUNS: u32 = 10;
+const BYTES_PER_MB: f64 = 1024.0 * 1024.0;
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, specta::Type)]
+pub struct FileCounts {
+    n_files: u32,
+    duration_seconds: f64,
+    n_segments: u32,
+}
+
+#[derive(Clone, Debug, serde::Serialize, specta::Type)]
+pub struct RootStats {
+    #[serde(serialize_with = "serialize_display_path")]
+    path: PathBuf,
+    counts: FileCounts,
+}
+
+#[derive(Clone, Debug, serde::Serialize, specta::Type)]
+pub struct FormatStats {
+    /// Lowercase file extension, without the dot
+    extension: String,
+    counts: FileCounts,
+}
+
+#[derive(Clone, Debug, serde::Serialize, specta::Type)]
+pub struct LibraryStats {
+    total: FileCounts,
+    /// Files in the index that are under none of the roots are only counted in the total.
+    /// A file with copies under several roots counts towards each of them.
+    roots: Vec<RootStats>,
+    formats: Vec<FormatStats>,
+    database_size_mb: f64,
+    embeddings_size_mb: f64,
+    /// Segments currently searchable in the vector index
+    vector_index_segments: u32,
+    recent_runs: Vec<IndexRun>,
+}
+
+/// One row per location of each file
+struct IndexedFileRow {
+    file_hash: String,
+    file_path: Vec<u8>,
+    duration_seconds: Option<f64>,
+    n_segments: i64,
+    last_segment_end: Option<f64>,
+}
+
+impl IndexedFileRow {
+    /// Files indexed before durations were recorded fall back to the end of their last segment
+    fn duration_seconds(&self) -> f64 {
+        self.duration_seconds
+            .or(self.last_segment_end)
+            .unwrap_or_default()
+    }
+}
+
+impl FileCounts {
+    fn add(&mut self, file: &IndexedFileRow) {
+        self.n_files += 1;
+        self.duration_seconds += file.duration_seconds();
+        self.n_segments += file.n_segments as u32;
+    }
+}
+
+/// Get the files, duration and segments in the index, broken down by root and format,
+/// along with the size of the database and the latest indexing runs
+#[tauri::command]
+#[specta::specta]
+pub async fn get_library_stats(
+    app_state: State<'_, AppState>,
+) -> result::Result<LibraryStats, String> {
+    let pool = &app_state.pool;
+    let vector_index_segments = app_state.vector_index.read().await.n_indexed() as u32;
+    get_library_stats_from_db(pool, vector_index_segments)
+        .await
+        .map_err(|err| format!("Failed to get library stats: {:?}", err))
+}
+
+async fn get_library_stats_from_db(
+    pool: &SqlitePool,
+    vector_index_segments: u32,
+) -> Result<LibraryStats> {
+    let files = sqlx::query_as!(
+        IndexedFileRow,
+        r#"SELECT
+            af.file_hash as "file_hash!",
+            fl.file_path as "file_path!",
+            af.duration_seconds,
+            COALESCE(segments.n_segments, 0) as "n_segments!: i64",
+            segments.last_segment_end as "last_segment_end?: f64"
+        FROM audio_file af
+            JOIN file_location fl ON fl.file_hash = af.file_hash
+            LEFT JOIN (
+                SELECT
+                    file_hash,
+                    COUNT(*) as n_segments,
+                    MAX(ending_timestamp) as last_segment_end
+                FROM audio_file_segment
+                GROUP BY file_hash
+            ) segments ON segments.file_hash = af.file_hash
+        ORDER BY af.file_hash, fl.file_path"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to get indexed files")?;
+    let roots = get_paths_from_db(pool).await.map_err(anyhow::Error::msg)?;
+    let (total, roots, formats) = summarize_files(&files, &roots);
+
+    let database_size_bytes = sqlx::query_scalar!(
+        r#"SELECT page_count * page_size as "size!: i64"
+        FROM pragma_page_count(), pragma_page_size()"#
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to get database size")?;
+    let embeddings_size_bytes = sqlx::query_scalar!(
+        r#"SELECT COALESCE(SUM(LENGTH(embedding)), 0) as "size!: i64" FROM audio_file_segment"#
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to get embeddings size")?;
+
+    Ok(LibraryStats {
+        total,
+        roots,
+        formats,
+        database_size_mb: database_size_bytes as f64 / BYTES_PER_MB,
+        embeddings_size_mb: embeddings_size_bytes as f64 / BYTES_PER_MB,
+        vector_index_segments,
+        recent_runs: get_recent_runs(pool, N_RECENT_RUNS).await?,
+    })
+}
+
+/// Count the files in total, under each root and for each format.
+/// Files under nested roots are counted towards the innermost one.
+/// The format of a file with several locations is taken from the first.
+fn summarize_files(
+    files: &[IndexedFileRow],
+    roots: &[PathBuf],
+) -> (FileCounts, Vec<RootStats>, Vec<FormatStats>) {
+    let mut total = FileCounts::default();
+    let mut root_counts = vec![FileCounts::default(); roots.len()];
+    let mut format_counts: BTreeMap<String, FileCounts> = BTreeMap::new();
+    let mut counted_files = HashSet::new();
+    let mut counted_root_files = HashSet::new();
+    for file in files {
+        let path = decode_path(&file.file_path);
+        let innermost_root = roots
+            .iter()
+            .enumerate()
+            .filter(|(_, root)| path.starts_with(root))
+            .max_by_key(|(_, root)| root.components().count());
+        if let Some((root_i, _)) = innermost_root {
+            if counted_root_files.insert((root_i, &file.file_hash)) {
+                root_counts[root_i].add(file);
+            }
+        }
+        if !counted_files.insert(&file.file_hash) {
+            continue;
+        }
+        total.add(file);
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        format_counts.entry(extension).or_default().add(file);
+    }
+
+    let roots = roots
+        .iter()
+        .zip(root_counts)
+        .map(|(path, counts)| RootStats {
+            path: path.clone(),
+            counts,
+        })
+        .collect();
+    let formats = format_counts
+        .into_iter()
+        .map(|(extension, counts)| FormatStats { extension, counts })
+        .collect();
+    (total, roots, formats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::encode_path;
+    use std::path::Path;
+
+    fn file(file_path: &str, duration_seconds: Option<f64>, n_segments: i64) -> IndexedFileRow {
+        IndexedFileRow {
+            file_hash: format!("{}_hash", file_path),
+            file_path: encode_path(Path::new(file_path)),
+            duration_seconds,
+            n_segments,
+            last_segment_end: Some(10.0),
+        }
+    }
+
+    #[test]
+    fn test_summarize_files() {
+        let files = [
+            file("/Sounds/door.wav", Some(2.0), 1),
+            file("/Sounds/Foley/steps.WAV", Some(30.0), 5),
+            file("/Sounds/Foley/cloth.flac", None, 2),
+            file("/Elsewhere/hum.wav", Some(60.0), 11),
+            // A copy is counted under its root, but only once in the totals
+            IndexedFileRow {
+                file_path: encode_path(Path::new("/Sounds/hum copy.wav")),
+                ..file("/Elsewhere/hum.wav", Some(60.0), 11)
+            },
+        ];
+        let roots = [PathBuf::from("/Sounds"), PathBuf::from("/Sounds/Foley")];
+        let (total, roots, formats) = summarize_files(&files, &roots);
+
+        assert_eq!(
+            total,
+            FileCounts {
+                n_files: 4,
+                duration_seconds: 102.0,
+                n_segments: 19
+            }
+        );
+        assert_eq!(roots[0].counts.n_files, 2);
+        assert_eq!(roots[1].counts.n_files, 2);
+        // Falls back to the end of the last segment
+        assert_eq!(roots[1].counts.duration_seconds, 40.0);
+        let extensions = formats
+            .iter()
+            .map(|format| (format.extension.as_str(), format.counts.n_files))
+            .collect::<Vec<_>>();
+        assert_eq!(extensions, vec![("flac", 1), ("wav", 3)]);
+    }
+}
@@ -3,23 +3,45 @@
 
 mod audio_index;
 mod clap;
+mod duplicates;
+mod embeddings;
+mod export;
+mod feedback;
+mod file_overview;
+mod http_api;
 pub mod index_paths;
+mod library_stats;
+mod paths;
 mod search;
 mod state;
+mod volumes;
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Context;
 use futures::lock::Mutex;
 
 use audio_index::{
-    indexing_status::{IndexingStatus, IndexingStatusChanged},
+    has_interrupted_index_job,
+    index_error::{get_index_errors, retry_failed_files},
+    indexing_status::{IndexingStatus, IndexingStatusChanged, Status},
+    segmentation::{get_segmentation_strategy, set_segmentation_strategy},
     update_audio_index, UpdateAudioIndex,
 };
-use search::search_index;
+use duplicates::find_duplicates;
+use export::{export_results, region::export_region};
+use feedback::refine_search;
+use file_overview::get_file_overview;
+use http_api::{
+    get_http_api_settings, reset_http_api_token, set_http_api_settings, start_http_api_if_enabled,
+    HttpApi,
+};
+use library_stats::get_library_stats;
+use search::{embed_search_query, search_index, search_index_structured};
 use sqlx::SqlitePool;
 use state::{
-    audio_embedder::AudioEmbedder,
+    audio_embedder::{get_embedder_metrics, AudioEmbedder},
     database::{
         self,
         vector_index::{initialize_index, synchronize_index},
@@ -28,12 +50,14 @@ use state::{
 };
 use tauri::{
     async_runtime::{self, RwLock},
-    Manager, State,
+    AppHandle, Manager, State,
 };
 use tauri_specta::Event;
+use volumes::refresh_root_availability;
 
 use crate::index_paths::{
-    add_path_to_index, add_paths_to_index, delete_path_from_index, get_paths_from_index,
+    add_path_to_index, add_paths_to_index, delete_path_from_index, get_index_roots,
+    get_paths_from_index, set_root_options,
 };
 
 /// Called on mount of the main App.
@@ -42,19 +66,68 @@ use crate::index_paths::{
 /// but after the window is rendered
 #[tauri::command]
 #[specta::specta]
-async fn initialize_backend(app_state: State<'_, AppState>) -> Result<(), String> {
+async fn initialize_backend(
+    app_handle: AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<(), String> {
     let pool = app_state.pool.clone();
     let mut vector_index = app_state.vector_index.write().await;
     let synchronize_res = synchronize_index(&pool, &mut vector_index)
         .await
         .map_err(|e| e.to_string());
-    match synchronize_res {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            log::error!("Error while synchronizing index: {:?}", e);
-            Err(e)
-        }
+    drop(vector_index);
+    if let Err(e) = synchronize_res {
+        log::error!("Error while synchronizing index: {:?}", e);
+        return Err(e);
+    }
+
+    // Pick up where we left off if the app quit while indexing
+    match has_interrupted_index_job(&pool).await {
+        Ok(true) => spawn_update_audio_index(app_handle),
+        Ok(false) => {}
+        Err(e) => log::error!("Error while checking for an interrupted index job: {:?}", e),
     }
+    Ok(())
+}
+
+fn spawn_update_audio_index(handle: AppHandle) {
+    async_runtime::spawn(async move {
+        match update_audio_index(handle.state::<AppState>()).await {
+            Ok(_) => {}
+            Err(e) => {
+                log::error!("Error while updating audio index: {:?}", e);
+            }
+        }
+    });
+}
+
+/// How often to check whether drives and shares have been mounted or unmounted
+const VOLUME_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Update the index whenever a root's volume comes back,
+/// so files added to it while it was away get picked up
+fn spawn_volume_watcher(handle: AppHandle) {
+    async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(VOLUME_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let app_state = handle.state::<AppState>();
+            // Indexing refreshes the roots when it starts, and probing drives would slow it down
+            if matches!(
+                app_state.indexing_status.get_status().await,
+                Status::InProgress(_)
+            ) {
+                continue;
+            }
+            match refresh_root_availability(&app_state.pool).await {
+                Ok(came_online) if !came_online.is_empty() => {
+                    spawn_update_audio_index(handle.clone())
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Error while checking root availability: {:?}", e),
+            }
+        }
+    });
 }
 
 fn main() {
@@ -68,7 +141,25 @@ fn main() {
                 add_paths_to_index,
                 get_paths_from_index,
                 delete_path_from_index,
-                initialize_backend
+                initialize_backend,
+                get_embedder_metrics,
+                get_index_errors,
+                retry_failed_files,
+                get_library_stats,
+                get_index_roots,
+                set_root_options,
+                find_duplicates,
+                get_http_api_settings,
+                set_http_api_settings,
+                reset_http_api_token,
+                get_segmentation_strategy,
+                set_segmentation_strategy,
+                export_results,
+                export_region,
+                get_file_overview,
+                search_index_structured,
+                embed_search_query,
+                refine_search
             ])
             .events(tauri_specta::collect_events![
                 IndexingStatusChanged,
@@ -90,12 +181,32 @@ fn main() {
             get_paths_from_index,
             delete_path_from_index,
             initialize_backend,
+            get_embedder_metrics,
+            get_index_errors,
+            retry_failed_files,
+            get_library_stats,
+            get_index_roots,
+            set_root_options,
+            find_duplicates,
+            get_http_api_settings,
+            set_http_api_settings,
+            reset_http_api_token,
+            get_segmentation_strategy,
+            set_segmentation_strategy,
+            export_results,
+            export_region,
+            get_file_overview,
+            search_index_structured,
+            embed_search_query,
+            refine_search,
         ])
         .setup(|app| {
             let handle = app.handle();
 
+            let clap_model = clap::ClapModel::from_env()?;
             let (clap_model_text_embedder, clap_model_audio_embedder) =
-                clap::load_clap_models(&app.path_resolver()).expect("Failed to load clap model");
+                clap::load_clap_models(&app.path_resolver(), clap_model)
+                    .expect("Failed to load clap model");
 
             let vector_index = initialize_index(None);
 
@@ -105,22 +216,26 @@ fn main() {
 
             app.manage(AppState {
                 pool,
-                clap_model_audio_embedder: AudioEmbedder::new(clap_model_audio_embedder),
+                clap_model_audio_embedder: AudioEmbedder::new(
+                    clap_model_audio_embedder,
+                    clap_model,
+                ),
                 clap_model_text_embedder: Arc::new(Mutex::new(clap_model_text_embedder)),
                 indexing_status: IndexingStatus::new(handle.clone()),
                 vector_index: RwLock::new(vector_index),
+                http_api: HttpApi::default(),
+            });
+
+            spawn_volume_watcher(handle.clone());
+            let api_handle = handle.clone();
+            async_runtime::spawn(async move {
+                if let Err(e) = start_http_api_if_enabled(&api_handle).await {
+                    log::error!("Error while starting HTTP API: {:?}", e);
+                }
             });
 
             UpdateAudioIndex::listen_global(&handle.clone(), move |_| {
-                let handle = handle.clone();
-                async_runtime::spawn(async move {
-                    match update_audio_index(handle.state::<AppState>()).await {
-                        Ok(_) => {}
-                        Err(e) => {
-                            log::error!("Error while updating audio index: {:?}", e);
-                        }
-                    }
-                });
+                spawn_update_audio_index(handle.clone());
             });
 
             Ok(())
@@ -0,0 +1,177 @@
+//! Paths are stored as raw bytes, since file names from older libraries aren't always
+//! valid UTF-8. They're only turned into strings to be shown in the UI.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serializer;
+
+/// Encode a path for the database, without loss
+#[cfg(unix)]
+pub fn encode_path(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+/// Windows paths are UTF-16 that may contain unpaired surrogates, so they're stored as
+/// WTF-8. Valid paths have the same bytes as in UTF-8
+#[cfg(windows)]
+pub fn encode_path(path: &Path) -> Vec<u8> {
+    use std::os::windows::ffi::OsStrExt;
+
+    wtf8_from_wide(&path.as_os_str().encode_wide().collect::<Vec<_>>())
+}
+
+#[cfg(unix)]
+pub fn decode_path(bytes: &[u8]) -> PathBuf {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(windows)]
+pub fn decode_path(bytes: &[u8]) -> PathBuf {
+    use std::{ffi::OsString, os::windows::ffi::OsStringExt};
+
+    PathBuf::from(OsString::from_wide(&wide_from_wtf8(bytes)))
+}
+
+/// UTF-8, except that unpaired surrogates are encoded like any other code point
+#[cfg_attr(not(windows), allow(dead_code))]
+fn wtf8_from_wide(wide: &[u16]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for code_point in char::decode_utf16(wide.iter().copied()) {
+        match code_point {
+            Ok(c) => bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            Err(err) => {
+                let surrogate = err.unpaired_surrogate();
+                bytes.extend([
+                    0xE0 | (surrogate >> 12) as u8,
+                    0x80 | ((surrogate >> 6) & 0x3F) as u8,
+                    0x80 | (surrogate & 0x3F) as u8,
+                ]);
+            }
+        }
+    }
+    bytes
+}
+
+/// The inverse of [`wtf8_from_wide`]. Bytes that aren't WTF-8 become U+FFFD
+#[cfg_attr(not(windows), allow(dead_code))]
+fn wide_from_wtf8(bytes: &[u8]) -> Vec<u16> {
+    let mut wide = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let length = match bytes[i] {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 0,
+        };
+        let sequence = bytes.get(i..i + length.max(1)).unwrap_or(&bytes[i..]);
+        let is_well_formed = length > 0
+            && sequence.len() == length
+            && sequence[1..].iter().all(|byte| byte & 0xC0 == 0x80);
+        if !is_well_formed {
+            wide.push(char::REPLACEMENT_CHARACTER as u16);
+            i += 1;
+            continue;
+        }
+        let code_point = match length {
+            1 => sequence[0] as u32,
+            _ => sequence[1..].iter().fold(
+                (sequence[0] & (0x7F >> length)) as u32,
+                |code_point, byte| (code_point << 6) | (byte & 0x3F) as u32,
+            ),
+        };
+        match char::from_u32(code_point) {
+            Some(c) => wide.extend(c.encode_utf16(&mut [0; 2]).iter()),
+            // An unpaired surrogate
+            None if code_point <= 0xFFFF => wide.push(code_point as u16),
+            None => wide.push(char::REPLACEMENT_CHARACTER as u16),
+        }
+        i += length;
+    }
+    wide
+}
+
+/// How a path is shown in the UI. Invalid UTF-8 is replaced, so this can't be used
+/// to find the file again; compare it with [`display_path`] of the known paths instead
+pub fn display_path(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// For `#[serde(serialize_with)]` on paths sent to the UI,
+/// since serializing a non-UTF-8 PathBuf fails
+pub fn serialize_display_path<S: Serializer>(
+    path: &Path,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&display_path(path))
+}
+
+pub fn serialize_display_path_option<S: Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serializer.serialize_some(&display_path(path)),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn serialize_display_paths<S: Serializer>(
+    paths: &[PathBuf],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(paths.iter().map(|path| display_path(path)))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    #[test]
+    fn test_wtf8_round_trip() {
+        let cafe = "C:\\Sounds\\Café 🎧.wav".encode_utf16().collect::<Vec<_>>();
+        assert_eq!(wtf8_from_wide(&cafe), "C:\\Sounds\\Café 🎧.wav".as_bytes());
+        assert_eq!(wide_from_wtf8(&wtf8_from_wide(&cafe)), cafe);
+
+        // A lone surrogate, which Windows allows in file names
+        let mut unpaired = "C:\\".encode_utf16().collect::<Vec<_>>();
+        unpaired.extend([0xD800, 'a' as u16]);
+        assert_eq!(wtf8_from_wide(&unpaired), b"C:\\\xED\xA0\x80a");
+        assert_eq!(wide_from_wtf8(&wtf8_from_wide(&unpaired)), unpaired);
+    }
+
+    /// "Café.wav" as encoded by an old Latin-1 system
+    fn latin1_path() -> PathBuf {
+        PathBuf::from(OsStr::from_bytes(b"/Sounds/Caf\xe9.wav"))
+    }
+
+    #[test]
+    fn test_non_utf8_path_round_trip() {
+        let path = latin1_path();
+        assert!(path.to_str().is_none());
+        let encoded = encode_path(&path);
+        assert_eq!(encoded, b"/Sounds/Caf\xe9.wav");
+        assert_eq!(decode_path(&encoded), path);
+        assert_eq!(display_path(&path), "/Sounds/Caf\u{FFFD}.wav");
+    }
+
+    #[test]
+    fn test_serialize_non_utf8_path() {
+        #[derive(serde::Serialize)]
+        struct WithPath {
+            #[serde(serialize_with = "serialize_display_path")]
+            path: PathBuf,
+        }
+        let serialized = serde_json::to_string(&WithPath {
+            path: latin1_path(),
+        })
+        .unwrap();
+        assert_eq!(serialized, "{\"path\":\"/Sounds/Caf\u{FFFD}.wav\"}");
+    }
+}
@@ -1,6 +1,7 @@
+use std::path::{Path, PathBuf};
 use std::result;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use log::{debug, info, warn};
 use ndarray::{arr1, Axis, CowArray};
 use ort::Session;
@@ -8,6 +9,10 @@ use sqlx::SqlitePool;
 use tauri::{AppHandle, PathResolver};
 use tokenizers::{tokenizer::Tokenizer, Encoding};
 
+use crate::audio_index::embed_query_file;
+use crate::audio_index::segmentation::get_segmentation_strategy_from_db;
+use crate::embeddings::normalize;
+use crate::index_paths::{find_innermost_root, get_index_roots_from_db};
 use crate::state::{
     database::vector_index::{self, SearchResult, VectorIndex},
     AppState,
@@ -38,6 +43,140 @@ pub async fn search_index(
     })
 }
 
+/// A text description to search for, or away from if its weight is negative
+#[derive(Clone, Debug, serde::Deserialize, specta::Type)]
+pub struct TextTerm {
+    text: String,
+    weight: f32,
+}
+
+/// A file to search for sounds like, or unlike if its weight is negative. It needn't be indexed
+#[derive(Clone, Debug, serde::Deserialize, specta::Type)]
+pub struct AudioTerm {
+    file_path: PathBuf,
+    weight: f32,
+}
+
+/// Terms combined into a single query, e.g. "rain" at 1 and "thunder" at -0.5
+/// for rain without thunder. At least one term must have a positive weight.
+#[derive(Clone, Debug, serde::Deserialize, specta::Type)]
+pub struct StructuredQuery {
+    #[serde(default)]
+    text_terms: Vec<TextTerm>,
+    #[serde(default)]
+    audio_terms: Vec<AudioTerm>,
+}
+
+/// Search with weighted text and audio terms, summed into one query embedding
+#[tauri::command]
+#[specta::specta]
+pub async fn search_index_structured(
+    app_state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+    query: StructuredQuery,
+) -> result::Result<Vec<SearchResult>, String> {
+    info!("Searching for: {:?}", query);
+    search_with_structured_query(&app_state, &app_handle, &query)
+        .await
+        .map_err(|e| {
+            warn!("Error during search: {:?}", e);
+            format!("ERROR during search: {:?}", e.to_string())
+        })
+}
+
+/// The embedding a structured query searches with, to refine it with feedback later
+#[tauri::command]
+#[specta::specta]
+pub async fn embed_search_query(
+    app_state: tauri::State<'_, AppState>,
+    app_handle: AppHandle,
+    query: StructuredQuery,
+) -> result::Result<Vec<f32>, String> {
+    embed_structured_query(&app_state, &app_handle, &query)
+        .await
+        .map_err(|e| format!("Failed to embed query: {:?}", e))
+}
+
+pub(crate) async fn search_with_structured_query(
+    app_state: &AppState,
+    app_handle: &AppHandle,
+    query: &StructuredQuery,
+) -> Result<Vec<SearchResult>> {
+    let query_embedding = embed_structured_query(app_state, app_handle, query).await?;
+    let vector_index = app_state.vector_index.read().await;
+    let mut search_results =
+        vector_index::get_knn(&query_embedding, &app_state.pool, &vector_index).await?;
+    mark_unavailable_results(&mut search_results, &app_state.pool).await?;
+    Ok(search_results)
+}
+
+async fn embed_structured_query(
+    app_state: &AppState,
+    app_handle: &AppHandle,
+    query: &StructuredQuery,
+) -> Result<Vec<f32>> {
+    let mut weighted_embeddings = vec![];
+    if !query.text_terms.is_empty() {
+        let text_embedder = app_state.clap_model_text_embedder.lock().await;
+        for term in &query.text_terms {
+            let embedding = embed_search_string(&term.text, &text_embedder, app_handle).await?;
+            weighted_embeddings.push((embedding, term.weight));
+        }
+    }
+    let segmentation = get_segmentation_strategy_from_db(&app_state.pool).await?;
+    for term in &query.audio_terms {
+        let embedding = embed_query_file(
+            &term.file_path,
+            &app_state.clap_model_audio_embedder,
+            segmentation,
+        )
+        .await
+        .context(format!("Failed to embed {:?}", term.file_path))?;
+        weighted_embeddings.push((embedding, term.weight));
+    }
+    combine_embeddings(&weighted_embeddings)
+}
+
+/// The normalized weighted sum of the normalized embeddings, so each term counts by its weight alone
+pub(crate) fn combine_embeddings(weighted_embeddings: &[(Vec<f32>, f32)]) -> Result<Vec<f32>> {
+    if let Some((_, weight)) = weighted_embeddings
+        .iter()
+        .find(|(_, weight)| !weight.is_finite())
+    {
+        bail!("Query term weights must be finite, got {}", weight);
+    }
+    if !weighted_embeddings.iter().any(|(_, weight)| *weight > 0.0) {
+        bail!("A query needs at least one term with a positive weight");
+    }
+    let mut combined = vec![0.0; weighted_embeddings[0].0.len()];
+    for (embedding, weight) in weighted_embeddings {
+        let normalized = normalize(embedding).filter(|_| embedding.len() == combined.len());
+        let Some(normalized) = normalized else {
+            bail!("Query terms must have non-zero embeddings of the same size");
+        };
+        for (combined_coord, coord) in combined.iter_mut().zip(normalized) {
+            *combined_coord += weight * coord;
+        }
+    }
+    normalize(&combined).context("Query terms cancel each other out")
+}
+
+/// Search for the segments that sound most like an audio file, which needn't be indexed
+pub(crate) async fn search_by_audio_file(
+    app_state: &AppState,
+    path: &Path,
+) -> Result<Vec<SearchResult>> {
+    info!("Searching by audio: {:?}", path);
+    let segmentation = get_segmentation_strategy_from_db(&app_state.pool).await?;
+    let embedding =
+        embed_query_file(path, &app_state.clap_model_audio_embedder, segmentation).await?;
+    let vector_index = app_state.vector_index.read().await;
+    let mut search_results =
+        vector_index::get_knn(&embedding, &app_state.pool, &vector_index).await?;
+    mark_unavailable_results(&mut search_results, &app_state.pool).await?;
+    Ok(search_results)
+}
+
 async fn get_search_results(
     search_string: &str,
     pool: &SqlitePool,
@@ -46,18 +185,65 @@ async fn get_search_results(
     app_handle: &AppHandle,
 ) -> Result<Vec<vector_index::SearchResult>> {
     info!("Searching for: {}", search_string);
+    let embedded_search_string =
+        embed_search_string(search_string, text_embedder, app_handle).await?;
+    debug!(
+        "Searching with embedding of size {}",
+        embedded_search_string.len()
+    );
+    let mut search_results =
+        vector_index::get_knn(&embedded_search_string, pool, vector_index).await?;
+    mark_unavailable_results(&mut search_results, pool).await?;
+    Ok(search_results)
+}
+
+/// Embed a search string with the text model, into the same space as the audio segments
+async fn embed_search_string(
+    search_string: &str,
+    text_embedder: &Session,
+    app_handle: &AppHandle,
+) -> Result<Vec<f32>> {
     debug!("Preprocessing search string: {}", search_string);
     let preprocessed_search_string = preprocess_search_string(search_string);
     debug!("Tokenizing search string: {}", preprocessed_search_string);
     let search_string_encoding = tokenize(preprocessed_search_string, &app_handle.path_resolver())
         .map_err(|e| anyhow!(e.to_string()))?;
     debug!("Embedding encoding {:?}", search_string_encoding);
-    let embedded_search_string = embed(search_string_encoding, text_embedder).await?;
-    debug!(
-        "Searching with embedding of size {}",
-        embedded_search_string.len()
-    );
-    vector_index::get_knn(&embedded_search_string, pool, vector_index).await
+    embed(search_string_encoding, text_embedder).await
+}
+
+/// Results on offline roots are still returned, so the user knows which drive to connect
+pub(crate) async fn mark_unavailable_results(
+    search_results: &mut [vector_index::SearchResult],
+    pool: &SqlitePool,
+) -> Result<()> {
+    let roots = get_index_roots_from_db(pool)
+        .await
+        .map_err(|e| anyhow!(e))
+        .context("Failed to get roots to check availability")?;
+    let is_online = |path: &Path| {
+        find_innermost_root(path, &roots)
+            .map(|root| root.online)
+            .unwrap_or(true)
+    };
+    for search_result in search_results {
+        // Prefer a copy that can be played right now
+        let online_location = match is_online(&search_result.file_path) {
+            true => None,
+            false => search_result
+                .other_locations
+                .iter()
+                .position(|location| is_online(location)),
+        };
+        if let Some(i) = online_location {
+            std::mem::swap(
+                &mut search_result.file_path,
+                &mut search_result.other_locations[i],
+            );
+        }
+        search_result.available = is_online(&search_result.file_path);
+    }
+    Ok(())
 }
 
 async fn embed(
@@ -127,3 +313,28 @@ fn preprocess_search_string(search_string: &str) -> String {
         _ => search_string.to_string(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combine_embeddings() {
+        // Scale doesn't matter, only weight
+        let rain_without_thunder =
+            combine_embeddings(&[(vec![2.0, 0.0, 0.0], 1.0), (vec![0.0, 0.0, 0.5], -1.0)]).unwrap();
+        let expected = 1.0 / 2.0_f32.sqrt();
+        assert!((rain_without_thunder[0] - expected).abs() < 1e-6);
+        assert!((rain_without_thunder[2] + expected).abs() < 1e-6);
+
+        assert!(combine_embeddings(&[(vec![1.0, 0.0], -1.0)]).is_err());
+        assert!(combine_embeddings(&[(vec![1.0, 0.0], 1.0), (vec![2.0, 0.0], -1.0)]).is_err());
+
+        // Weights that aren't numbers would poison every coordinate
+        for weight in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert!(
+                combine_embeddings(&[(vec![1.0, 0.0], 1.0), (vec![0.0, 1.0], weight)]).is_err()
+            );
+        }
+    }
+}
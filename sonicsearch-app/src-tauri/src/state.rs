@@ -12,6 +12,7 @@ use audio_embedder::AudioEmbedder;
 
 
 use crate::audio_index::indexing_status::IndexingStatus;
+use crate::http_api::HttpApi;
 
 use self::database::vector_index::VectorIndex;
 
@@ -21,4 +22,5 @@ pub struct AppState {
     pub clap_model_audio_embedder: AudioEmbedder,
     pub indexing_status: IndexingStatus,
     pub vector_index: RwLock<VectorIndex>,
+    pub http_api: HttpApi,
 }
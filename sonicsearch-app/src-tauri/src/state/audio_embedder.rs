@@ -1,52 +1,118 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use futures::lock::Mutex;
-use log::{debug, info, trace};
-use ndarray::{stack, Array1, Array3, Axis, CowArray};
+use log::{debug, info, trace, warn};
+use ndarray::{stack, Array1, Array2, Axis, CowArray};
 use ort::Session;
 use std::sync::Arc;
+use std::time::Instant;
+use tauri::State;
 use tokio::sync::{
     oneshot::{self, Sender},
-    Notify,
+    Notify, OwnedSemaphorePermit, Semaphore,
 };
 
-pub struct MelSpecAndSender(Array3<f64>, Sender<Array1<f32>>);
+use crate::audio_index::feature_extractor::AudioFeatures;
+use crate::clap::ClapModel;
+
+use self::batch_size::AdaptiveBatchSize;
+
+use super::AppState;
+
+mod batch_size;
+
+type EmbeddingSender = Sender<Result<Array1<f32>>>;
+
+/// A queued input. The permit reserves its share of QUEUE_MEMORY_LIMIT
+/// and is released once the input has been embedded.
+pub struct MelSpecAndSender(AudioFeatures, EmbeddingSender, OwnedSemaphorePermit);
+
+/// Upper bound on any batch, regardless of how fast batches are
+const MAX_BATCH_SIZE: usize = 256;
+/// Upper bound on the features in one batch
+const MAX_BATCH_BYTES: usize = 128 * 1024 * 1024;
+/// Producers wait once this much is queued or being embedded
+const QUEUE_MEMORY_LIMIT: usize = 512 * 1024 * 1024;
+/// The queue's semaphore counts memory in units of this many bytes
+const QUEUE_PERMIT_BYTES: usize = 1024;
+/// Weight of the latest batch in the throughput average
+const THROUGHPUT_SMOOTHING: f64 = 0.2;
+
+#[derive(Clone, Debug, Default, serde::Serialize, specta::Type)]
+pub struct EmbedderMetrics {
+    /// Inputs waiting to be embedded
+    queue_depth: u32,
+    /// Memory held by queued and in-flight inputs
+    queue_memory_mb: f64,
+    queue_memory_limit_mb: f64,
+    batch_size: u32,
+    max_batch_size: u32,
+    batches_embedded: u32,
+    inputs_embedded: u32,
+    inputs_failed: u32,
+    last_batch_latency_ms: f64,
+    /// Moving average over recent batches
+    inputs_per_second: f64,
+}
 
 pub struct AudioEmbedder {
     pub(crate) session: Arc<Mutex<Session>>,
+    pub(crate) model: ClapModel,
     pub(crate) input_queue: Arc<Mutex<Vec<MelSpecAndSender>>>,
+    pub(crate) queue_capacity: Arc<Semaphore>,
     pub(crate) queue_has_contents: Arc<Notify>,
     pub(crate) stop_processing_queue: Arc<Notify>,
+    pub(crate) batch_size: Mutex<AdaptiveBatchSize>,
+    pub(crate) metrics: Mutex<EmbedderMetrics>,
 }
 
 /// This is a wrapper around the ONNX runtime session that allows us to queue up
 /// audio for batch processing. Multiple threads can add inputs to the queue,
 /// and a single thread will process the queue in batches.
 impl AudioEmbedder {
-    pub fn new(session: Session) -> Self {
+    pub fn new(session: Session, model: ClapModel) -> Self {
         Self {
             session: Arc::new(Mutex::new(session)),
+            model,
             input_queue: Arc::new(Mutex::new(Vec::new())),
+            queue_capacity: Arc::new(Semaphore::new(QUEUE_MEMORY_LIMIT / QUEUE_PERMIT_BYTES)),
             queue_has_contents: Arc::new(Notify::new()),
             stop_processing_queue: Arc::new(Notify::new()),
+            batch_size: Mutex::new(AdaptiveBatchSize::new(MAX_BATCH_SIZE)),
+            metrics: Mutex::new(EmbedderMetrics {
+                queue_memory_limit_mb: bytes_to_mb(QUEUE_MEMORY_LIMIT),
+                ..Default::default()
+            }),
         }
     }
 
     /// Other threads can call this to queue up audio for batch processing.
     /// This is a blocking call that will wait until the input has been processed,
     /// then return the output for the given input.
-    pub async fn queue_for_batch_processing(&self, input: Array3<f64>) -> Result<Array1<f32>> {
+    /// If the queue is full, this first waits for room in the queue.
+    pub async fn queue_for_batch_processing(&self, input: AudioFeatures) -> Result<Array1<f32>> {
         let (sender, receiver) = oneshot::channel();
 
+        let permits = input
+            .size_in_bytes()
+            .div_ceil(QUEUE_PERMIT_BYTES)
+            .clamp(1, QUEUE_MEMORY_LIMIT / QUEUE_PERMIT_BYTES) as u32;
+        let permit = self
+            .queue_capacity
+            .clone()
+            .acquire_many_owned(permits)
+            .await
+            .context("Audio embedder queue was closed")?;
         {
             let mut input_queue = self.input_queue.lock().await;
-            (*input_queue).push(MelSpecAndSender(input, sender));
+            (*input_queue).push(MelSpecAndSender(input, sender, permit));
         }
         // If process_queue is waiting for inputs, wake it up
         self.queue_has_contents.notify_one();
 
         let result = receiver
             .await
-            .context("Did not receive output from audio embedder")?;
+            .context("Did not receive output from audio embedder")?
+            .context("Audio embedder failed to embed input")?;
 
         debug!("Received output of shape {:?}", result.shape());
         Ok(result)
@@ -56,15 +122,23 @@ impl AudioEmbedder {
     /// It continually runs and waits for inputs to be added to the queue.
     pub async fn begin_processing_queue(&self) -> Result<()> {
         debug!("Starting to process queue");
-        const MAX_BATCH_SIZE: usize = 256;
         loop {
             let mut inputs_to_process = Vec::new();
             let session = self.session.lock().await;
-            // Read up to MAX_BATCH_SIZE items from the queue and release the lock
+            let max_inputs = self.batch_size.lock().await.get();
+            // Read up to max_inputs items (and MAX_BATCH_BYTES) from the queue and release the lock
             {
                 let mut input_queue = self.input_queue.lock().await;
                 let input_queue_len = input_queue.len();
-                let batch_size = MAX_BATCH_SIZE.min(input_queue_len);
+                let mut batch_size = 0;
+                let mut batch_bytes = 0;
+                for MelSpecAndSender(input, _, _) in input_queue.iter().take(max_inputs) {
+                    batch_bytes += input.size_in_bytes();
+                    if batch_size > 0 && batch_bytes > MAX_BATCH_BYTES {
+                        break;
+                    }
+                    batch_size += 1;
+                }
                 inputs_to_process
                     .append(&mut (*input_queue).drain(0..batch_size).collect::<Vec<_>>());
                 debug!(
@@ -74,6 +148,8 @@ impl AudioEmbedder {
             }
             if inputs_to_process.is_empty() {
                 info!("No inputs to process. ");
+                // Let embed_now use the session while the queue is empty
+                drop(session);
                 // block until either queue_has_contents or stop_processing_queue notifies
                 // if queue_has_contents is notified, then we continue processing
                 // if stop_processing_queue is notified, then we break
@@ -90,65 +166,211 @@ impl AudioEmbedder {
             }
 
             debug!("Embedding {} input(s)", inputs_to_process.len());
-            let (input_batch, senders): (Vec<Array3<f64>>, Vec<Sender<Array1<f32>>>) =
-                inputs_to_process
-                    .into_iter()
-                    .map(|MelSpecAndSender(input, sender)| (input, sender))
-                    .unzip();
-            // Process the inputs in a batch
-            let input_batch = stack(
+            // The permits are held until the batch is done, since its memory is still in use
+            let (input_batch, senders): (Vec<AudioFeatures>, Vec<_>) = inputs_to_process
+                .into_iter()
+                .map(|MelSpecAndSender(input, sender, permit)| (input, (sender, permit)))
+                .unzip();
+
+            let batch_start = Instant::now();
+            match self.embed_batch(&session, &input_batch) {
+                Ok(outputs) => {
+                    let latency = batch_start.elapsed();
+                    self.batch_size
+                        .lock()
+                        .await
+                        .record_success(input_batch.len(), latency);
+                    self.record_batch_metrics(outputs.len(), 0, latency).await;
+                    debug!("Finished embedding. Sending {} outputs.", outputs.len());
+                    for (output, (sender, _permit)) in outputs.into_iter().zip(senders) {
+                        trace!("Sending output of shape {:?} to sender", output.shape());
+                        send_result(sender, Ok(output));
+                    }
+                }
+                Err(err) if input_batch.len() == 1 => {
+                    warn!("Failed to embed input: {:?}", err);
+                    self.record_batch_metrics(0, 1, batch_start.elapsed()).await;
+                    if let Some((sender, _permit)) = senders.into_iter().next() {
+                        send_result(sender, Err(err));
+                    }
+                }
+                Err(err) => {
+                    // Retry one at a time, so only the input that caused the failure gets an error
+                    warn!(
+                        "Failed to embed batch of {}. Retrying inputs individually. {:?}",
+                        input_batch.len(),
+                        err
+                    );
+                    let mut n_failed = 0;
+                    for (input, (sender, _permit)) in input_batch.iter().zip(senders) {
+                        let result = self
+                            .embed_batch(&session, std::slice::from_ref(input))
+                            .and_then(|outputs| {
+                                outputs
+                                    .into_iter()
+                                    .next()
+                                    .context("Embedder returned no output")
+                            });
+                        if let Err(err) = &result {
+                            warn!("Failed to embed input: {:?}", err);
+                            n_failed += 1;
+                        }
+                        send_result(sender, result);
+                    }
+                    if n_failed == 0 {
+                        self.batch_size
+                            .lock()
+                            .await
+                            .record_batch_too_large(input_batch.len());
+                    }
+                    self.record_batch_metrics(
+                        input_batch.len() - n_failed,
+                        n_failed,
+                        batch_start.elapsed(),
+                    )
+                    .await;
+                }
+            }
+        }
+        debug!("Exiting process queue");
+        Ok(())
+    }
+
+    /// Embed inputs right away rather than through the queue,
+    /// for one-off requests that can't wait for an indexing run to process it
+    pub async fn embed_now(&self, inputs: &[AudioFeatures]) -> Result<Vec<Array1<f32>>> {
+        let session = self.session.lock().await;
+        let batch_size = self.batch_size.lock().await.get();
+        let mut outputs = Vec::with_capacity(inputs.len());
+        for batch in inputs.chunks(batch_size.max(1)) {
+            outputs.extend(self.embed_batch(&session, batch)?);
+        }
+        Ok(outputs)
+    }
+
+    pub async fn get_metrics(&self) -> EmbedderMetrics {
+        let queue_depth = self.input_queue.lock().await.len() as u32;
+        let (batch_size, max_batch_size) = {
+            let batch_size = self.batch_size.lock().await;
+            (batch_size.get() as u32, batch_size.max() as u32)
+        };
+        let available_permits = self.queue_capacity.available_permits();
+        let metrics = self.metrics.lock().await;
+        EmbedderMetrics {
+            queue_depth,
+            queue_memory_mb: bytes_to_mb(
+                QUEUE_MEMORY_LIMIT - available_permits * QUEUE_PERMIT_BYTES,
+            ),
+            batch_size,
+            max_batch_size,
+            ..metrics.clone()
+        }
+    }
+
+    async fn record_batch_metrics(
+        &self,
+        n_embedded: usize,
+        n_failed: usize,
+        latency: std::time::Duration,
+    ) {
+        let mut metrics = self.metrics.lock().await;
+        metrics.batches_embedded += 1;
+        metrics.inputs_embedded += n_embedded as u32;
+        metrics.inputs_failed += n_failed as u32;
+        metrics.last_batch_latency_ms = latency.as_secs_f64() * 1000.0;
+        let batch_throughput = (n_embedded + n_failed) as f64 / latency.as_secs_f64().max(1e-6);
+        metrics.inputs_per_second = match metrics.batches_embedded {
+            1 => batch_throughput,
+            _ => {
+                THROUGHPUT_SMOOTHING * batch_throughput
+                    + (1.0 - THROUGHPUT_SMOOTHING) * metrics.inputs_per_second
+            }
+        };
+    }
+
+    pub fn stop_processing_queue(&self) {
+        self.stop_processing_queue.notify_one();
+    }
+
+    /// Run the model on a batch of inputs, returning one embedding per input
+    fn embed_batch(
+        &self,
+        session: &Session,
+        input_batch: &[AudioFeatures],
+    ) -> Result<Vec<Array1<f32>>> {
+        let input_features = CowArray::from(
+            stack(
                 Axis(0),
                 input_batch
                     .iter()
-                    .map(|x| x.view())
+                    .map(|x| x.input_features.view())
                     .collect::<Vec<_>>()
                     .as_slice(),
-            )?;
-
-            let outputs = session
-                .run(vec![ort::Value::from_array(
-                    session.allocator(),
-                    &CowArray::from(input_batch.mapv(|x| x as f32).into_dyn()),
-                )
-                .context("Failed to create ort::Value from array")?])
-                .context("Failed to run session")?;
-
-            // Send the outputs back to the threads that requested them
-            // This is definitely wrong but actually is reasonably fast
-            let outputs: Vec<Array1<f32>> = outputs
-                .get(0)
-                .context("Output 0 should contain embeddings")?
-                .try_extract::<f32>()
-                .context("Failed to extract embeddings")?
-                .view()
-                .axis_iter(Axis(0))
-                .map(|x| {
-                    Ok(x.to_shape((x.len(),))
-                        .context("Failed to reshape output")?
-                        .to_owned())
-                })
-                .collect::<Result<Vec<_>>>()?;
-            assert_eq!(outputs.len(), senders.len());
-            debug!(
-                "Finished embedding. Sending {} outputs of size {}.",
-                outputs.len(),
-                outputs
-                    .get(0)
-                    .context("Failed to have at least one output")?
-                    .len()
+            )
+            .context("Failed to stack inputs into a batch")?
+            .mapv(|x| x as f32)
+            .into_dyn(),
+        );
+        // [batch, 1]
+        let is_longer = CowArray::from(
+            Array2::from_shape_fn((input_batch.len(), 1), |(i, _)| input_batch[i].is_longer)
+                .into_dyn(),
+        );
+
+        let mut session_inputs = vec![ort::Value::from_array(session.allocator(), &input_features)
+            .context("Failed to create ort::Value from array")?];
+        // Fused models are exported with (input_features, is_longer) inputs
+        if self.model.is_fused() {
+            session_inputs.push(
+                ort::Value::from_array(session.allocator(), &is_longer)
+                    .context("Failed to create ort::Value from is_longer")?,
             );
+        }
+        let outputs = session
+            .run(session_inputs)
+            .context("Failed to run session")?;
 
-            for (output, sender) in outputs.into_iter().zip(senders.into_iter()) {
-                trace!("Sending output of shape {:?} to sender", output.shape());
-                sender.send(output).expect("Failed to send output");
-                trace!("Sent output");
-            }
+        let outputs: Vec<Array1<f32>> = outputs
+            .get(0)
+            .context("Output 0 should contain embeddings")?
+            .try_extract::<f32>()
+            .context("Failed to extract embeddings")?
+            .view()
+            .axis_iter(Axis(0))
+            .map(|x| {
+                Ok(x.to_shape((x.len(),))
+                    .context("Failed to reshape output")?
+                    .to_owned())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if outputs.len() != input_batch.len() {
+            return Err(anyhow!(
+                "Embedder returned {} outputs for {} inputs",
+                outputs.len(),
+                input_batch.len()
+            ));
         }
-        debug!("Exiting process queue");
-        Ok(())
+        Ok(outputs)
     }
+}
 
-    pub fn stop_processing_queue(&self) {
-        self.stop_processing_queue.notify_one();
+/// The caller may have given up waiting (e.g. its file failed to index), so a closed
+/// channel is expected and not an error
+fn send_result(sender: EmbeddingSender, result: Result<Array1<f32>>) {
+    if sender.send(result).is_err() {
+        debug!("Embedding receiver was dropped before the output was sent");
     }
 }
+
+fn bytes_to_mb(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+/// Get the audio embedder's queue depth, batch size and throughput
+#[tauri::command]
+#[specta::specta]
+pub async fn get_embedder_metrics(
+    app_state: State<'_, AppState>,
+) -> std::result::Result<EmbedderMetrics, String> {
+    Ok(app_state.clap_model_audio_embedder.get_metrics().await)
+}
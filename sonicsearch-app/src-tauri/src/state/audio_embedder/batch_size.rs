@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use log::debug;
+
+/// Where to start before we know how the execution provider behaves
+const INITIAL_BATCH_SIZE: usize = 8;
+/// Grow while batches finish faster than this, and shrink when they are slower
+const TARGET_BATCH_LATENCY: Duration = Duration::from_secs(2);
+/// How much a batch grows after a fast, full batch
+const GROWTH_FACTOR: f64 = 1.25;
+
+/// Picks the embedding batch size from how recent batches went.
+///
+/// Grows multiplicatively while full batches finish within TARGET_BATCH_LATENCY
+/// and halves when they take longer. A batch that fails as a whole, but whose
+/// inputs all succeed on their own, was too big for the execution provider
+/// (e.g. CoreML's "max length of an inner dim"), so the ceiling is lowered for good.
+#[derive(Debug)]
+pub struct AdaptiveBatchSize {
+    batch_size: usize,
+    max_batch_size: usize,
+}
+
+impl AdaptiveBatchSize {
+    pub fn new(max_batch_size: usize) -> Self {
+        Self {
+            batch_size: INITIAL_BATCH_SIZE.min(max_batch_size),
+            max_batch_size,
+        }
+    }
+
+    pub fn get(&self) -> usize {
+        self.batch_size
+    }
+
+    pub fn max(&self) -> usize {
+        self.max_batch_size
+    }
+
+    pub fn record_success(&mut self, batch_size: usize, latency: Duration) {
+        if latency > TARGET_BATCH_LATENCY {
+            self.batch_size = (self.batch_size / 2).max(1);
+            debug!(
+                "Batch of {} took {:?}. Shrinking batch size to {}",
+                batch_size, latency, self.batch_size
+            );
+        } else if batch_size >= self.batch_size {
+            // Only grow when the queue could fill the batch, otherwise we learn nothing
+            self.batch_size =
+                ((self.batch_size as f64 * GROWTH_FACTOR).ceil() as usize).min(self.max_batch_size);
+        }
+    }
+
+    /// Record a batch that failed, although each of its inputs succeeded on its own
+    pub fn record_batch_too_large(&mut self, batch_size: usize) {
+        self.max_batch_size = (batch_size / 2).max(1);
+        self.batch_size = self.batch_size.min(self.max_batch_size);
+        debug!(
+            "Batch of {} failed. Limiting batch size to {}",
+            batch_size, self.max_batch_size
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grows_while_fast_and_full() {
+        let mut batch_size = AdaptiveBatchSize::new(256);
+        for _ in 0..50 {
+            let current = batch_size.get();
+            batch_size.record_success(current, Duration::from_millis(100));
+        }
+        assert_eq!(batch_size.get(), 256);
+    }
+
+    #[test]
+    fn test_does_not_grow_on_partial_batches() {
+        let mut batch_size = AdaptiveBatchSize::new(256);
+        batch_size.record_success(1, Duration::from_millis(100));
+        assert_eq!(batch_size.get(), INITIAL_BATCH_SIZE);
+    }
+
+    #[test]
+    fn test_shrinks_when_slow() {
+        let mut batch_size = AdaptiveBatchSize::new(256);
+        batch_size.record_success(INITIAL_BATCH_SIZE, Duration::from_secs(5));
+        assert_eq!(batch_size.get(), INITIAL_BATCH_SIZE / 2);
+    }
+
+    #[test]
+    fn test_failed_batch_lowers_ceiling() {
+        let mut batch_size = AdaptiveBatchSize::new(256);
+        batch_size.record_batch_too_large(8);
+        assert_eq!(batch_size.max(), 4);
+        for _ in 0..10 {
+            let current = batch_size.get();
+            batch_size.record_success(current, Duration::from_millis(100));
+        }
+        assert_eq!(batch_size.get(), 4);
+    }
+}
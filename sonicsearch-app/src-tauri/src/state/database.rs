@@ -45,22 +45,46 @@ pub async fn initialize_database(app_handle: &AppHandle) -> Result<SqlitePool> {
     Ok(pool)
 }
 
+/// A migrated in-memory database, on a single connection since every in-memory
+/// connection is its own database
+#[cfg(test)]
+pub(crate) async fn create_test_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("In-memory database should open");
+    sqlx::migrate!()
+        .run(&pool)
+        .await
+        .expect("Migrations should run");
+    pool
+}
+
 pub fn encode_embedding(embedding: &[f32]) -> Vec<u8> {
-    embedding
+    encode_f32s(embedding)
+}
+
+pub fn decode_embedding(db_embedding: &[u8]) -> Result<Vec<f32>> {
+    decode_f32s(db_embedding).context("Could not decode embedding")
+}
+
+/// Any list of f32s, e.g. an embedding or a waveform overview, as a blob
+pub fn encode_f32s(values: &[f32]) -> Vec<u8> {
+    values
         .iter()
-        .flat_map(|coord| f32::to_ne_bytes(*coord))
+        .flat_map(|value| f32::to_ne_bytes(*value))
         .collect::<Vec<u8>>()
 }
 
-pub fn decode_embedding(db_embedding: &[u8]) -> Result<Vec<f32>> {
-    if db_embedding.len() % 4 != 0 {
+pub fn decode_f32s(blob: &[u8]) -> Result<Vec<f32>> {
+    if blob.len() % 4 != 0 {
         return Err(anyhow::anyhow!(
-            "Could not decode: Embedding length {} is not a multiple of 4",
-            db_embedding.len()
+            "Could not decode: Blob length {} is not a multiple of 4",
+            blob.len()
         ));
     }
-    db_embedding
-        .chunks_exact(4)
+    blob.chunks_exact(4)
         .map(|chunk| {
             let mut bytes = [0; 4];
             bytes.copy_from_slice(chunk);
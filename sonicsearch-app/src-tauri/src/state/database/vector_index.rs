@@ -1,18 +1,22 @@
+use std::path::PathBuf;
+
 use anyhow::{Context, Result};
 
 use futures::future::join_all;
 use hnsw_rs::{dist::DistCosine, hnsw::Hnsw};
-use log::debug;
+use log::{debug, warn};
 use sqlx::SqlitePool;
 
+use crate::audio_index::file_location::get_locations;
+use crate::paths::{serialize_display_path, serialize_display_paths};
 use crate::state::database::decode_embedding;
 
 const DEFAULT_NB_ELEM: usize = 5_000;
-const MAX_NB_CONNECTION: usize = 16;
-const EF_C: usize = 200;
+pub(crate) const MAX_NB_CONNECTION: usize = 16;
+pub(crate) const EF_C: usize = 200;
 const K_LIMIT: usize = 10;
 const EF_ARG: usize = 12;
-const DEFAULT_NB_LAYER: usize = 8;
+pub(crate) const DEFAULT_NB_LAYER: usize = 8;
 
 // "The parameter ef controls the width of the search in the lowest level, it must be greater than number of neighbours asked.
 // A rule of thumb could be between knbn and max_nb_connection."
@@ -43,6 +47,13 @@ pub fn initialize_index(nb_elem: Option<usize>) -> VectorIndex {
     }
 }
 
+impl VectorIndex {
+    /// The number of embeddings currently in the index
+    pub fn n_indexed(&self) -> usize {
+        self.indexed_ids.len()
+    }
+}
+
 struct IndexRow {
     rowid: Option<i64>,
     embedding: Vec<u8>,
@@ -115,15 +126,25 @@ pub async fn synchronize_index(pool: &SqlitePool, vector_index: &mut VectorIndex
 
 #[derive(serde::Serialize, serde::Deserialize, specta::Type)]
 pub struct SearchResult {
-    file_path: String,
-    starting_timestamp: f64,
-    distance: f32,
+    // The segment's rowid, to give feedback on it
+    pub(crate) segment_id: i64,
+    #[serde(serialize_with = "serialize_display_path")]
+    pub(crate) file_path: PathBuf,
+    // Where else the same file can be found
+    #[serde(serialize_with = "serialize_display_paths")]
+    pub(crate) other_locations: Vec<PathBuf>,
+    pub(crate) starting_timestamp: f64,
+    pub(crate) ending_timestamp: f64,
+    pub(crate) distance: f32,
+    // False if the file's root is on a volume that's currently offline
+    pub(crate) available: bool,
 }
 
 #[derive(sqlx::FromRow)]
 struct SearchRow {
-    file_path: String,
+    file_hash: String,
     starting_timestamp: f64,
+    ending_timestamp: f64,
 }
 
 /// Returns K_LIMIT nearest neighbours of the given embedding
@@ -157,10 +178,10 @@ pub async fn get_knn(
                 SearchRow,
                 r#"
             SELECT
-                af.file_path,
-                afs.starting_timestamp
-            FROM audio_file_segment afs 
-                JOIN audio_file af ON afs.file_hash = af.file_hash
+                afs.file_hash,
+                afs.starting_timestamp,
+                afs.ending_timestamp
+            FROM audio_file_segment afs
             WHERE afs.rowid == ?
             "#,
                 rowid
@@ -168,14 +189,28 @@ pub async fn get_knn(
             .fetch_one(&pool)
             .await
             .context(format!(
-                "Failed to fetch path and timestamp from database for rowid {}",
+                "Failed to fetch path and timestamps from database for rowid {}",
                 rowid
             ))?;
-            Ok(SearchResult {
-                file_path: search_rows.file_path,
+            let mut locations = get_locations(&pool, &search_rows.file_hash)
+                .await?
+                .into_iter();
+            let Some(file_path) = locations.next() else {
+                warn!(
+                    "Skipping result without a location: {}",
+                    search_rows.file_hash
+                );
+                return Ok(None);
+            };
+            Ok(Some(SearchResult {
+                segment_id: rowid,
+                file_path,
+                other_locations: locations.collect(),
                 starting_timestamp: search_rows.starting_timestamp,
+                ending_timestamp: search_rows.ending_timestamp,
                 distance: neighbor.distance,
-            })
+                available: true,
+            }))
         })
         .collect::<Vec<_>>();
 
@@ -185,5 +220,5 @@ pub async fn get_knn(
         .collect::<Result<Vec<_>>>()
         .context("Failed to get path and timestamps")?;
 
-    Ok(search_results)
+    Ok(search_results.into_iter().flatten().collect())
 }
@@ -0,0 +1,610 @@
+//! Tracks which volume each root lives on. Roots on unmounted drives and shares are
+//! marked offline instead of looking deleted, and are found again if their volume
+//! comes back at a different mount point.
+
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use log::{debug, info};
+use sqlx::SqlitePool;
+
+use crate::paths::{decode_path, encode_path};
+
+/// How many of a root's files are recorded to recognise its volume by
+const FINGERPRINT_SIZE: usize = 8;
+
+/// A file under a root, used to check that a volume mounted elsewhere is the same one
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct FingerprintFile {
+    /// Relative to the root, encoded like paths in the database
+    relative_path: Vec<u8>,
+    size: u64,
+}
+
+struct VolumeRow {
+    path: Vec<u8>,
+    online: bool,
+    volume_mount_point: Option<Vec<u8>>,
+    volume_relative_path: Option<Vec<u8>>,
+    volume_fingerprint: String,
+}
+
+/// Check whether every root is reachable, and relocate the ones whose volume has been
+/// mounted somewhere else. Returns the roots that came back online, under their new
+/// path if they were relocated
+pub async fn refresh_root_availability(pool: &SqlitePool) -> Result<Vec<PathBuf>> {
+    canonicalize_roots(pool).await?;
+    let rows = sqlx::query_as!(
+        VolumeRow,
+        r#"SELECT
+            path,
+            online as "online: bool",
+            volume_mount_point,
+            volume_relative_path,
+            volume_fingerprint
+        FROM dir_paths"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to get root volumes")?;
+    let root_paths = rows
+        .iter()
+        .map(|row| decode_path(&row.path))
+        .collect::<Vec<_>>();
+    // Probing can wait on slow or sleeping drives, so keep it off the async executor
+    let (rows, probes) = tokio::task::spawn_blocking(move || {
+        let probes = rows
+            .iter()
+            .map(|row| probe_root(row, &root_paths))
+            .collect::<Vec<_>>();
+        (rows, probes)
+    })
+    .await
+    .context("Failed to probe roots")?;
+
+    let now = Utc::now().timestamp();
+    let mut came_online = vec![];
+    for (row, probe) in rows.into_iter().zip(probes) {
+        let path = decode_path(&row.path);
+        match probe {
+            RootProbe::Present => {
+                sqlx::query!(
+                    "UPDATE dir_paths SET online = TRUE, last_seen_at = ? WHERE path = ?",
+                    now,
+                    row.path
+                )
+                .execute(pool)
+                .await
+                .context(format!("Failed to mark {:?} as online", path))?;
+                if !row.online {
+                    info!("{:?} is back online", path);
+                    came_online.push(path);
+                }
+            }
+            RootProbe::Relocated(new_path) => {
+                info!("{:?} has been mounted at {:?}", path, new_path);
+                relocate_root(pool, &path, &new_path).await?;
+                let encoded_new_path = encode_path(&new_path);
+                sqlx::query!(
+                    "UPDATE dir_paths SET online = TRUE, last_seen_at = ? WHERE path = ?",
+                    now,
+                    encoded_new_path
+                )
+                .execute(pool)
+                .await
+                .context(format!("Failed to mark {:?} as online", new_path))?;
+                came_online.push(new_path);
+            }
+            RootProbe::Missing if row.online => {
+                info!("{:?} has gone offline", path);
+                sqlx::query!(
+                    "UPDATE dir_paths SET online = FALSE WHERE path = ?",
+                    row.path
+                )
+                .execute(pool)
+                .await
+                .context(format!("Failed to mark {:?} as offline", path))?;
+            }
+            RootProbe::Missing => {}
+        }
+    }
+    Ok(came_online)
+}
+
+/// Roots are canonicalized when they're added, so that nested roots are recognised.
+/// Roots added before that, or through a symlink that has since changed, are
+/// relocated to their canonical path, one at a time since nested roots move with them
+async fn canonicalize_roots(pool: &SqlitePool) -> Result<()> {
+    loop {
+        let root_paths = sqlx::query_scalar!("SELECT path FROM dir_paths ORDER BY length(path)")
+            .fetch_all(pool)
+            .await
+            .context("Failed to get roots")?
+            .into_iter()
+            .map(|path| decode_path(&path))
+            .collect::<Vec<_>>();
+        let canonical_paths = tokio::task::spawn_blocking({
+            let root_paths = root_paths.clone();
+            move || {
+                root_paths
+                    .iter()
+                    .map(|path| path.canonicalize().ok())
+                    .collect::<Vec<_>>()
+            }
+        })
+        .await
+        .context("Failed to canonicalize roots")?;
+        let non_canonical_root =
+            root_paths
+                .iter()
+                .zip(canonical_paths)
+                .find_map(|(path, canonical_path)| {
+                    let canonical_path = canonical_path?;
+                    (canonical_path != *path).then_some((path, canonical_path))
+                });
+        let Some((path, canonical_path)) = non_canonical_root else {
+            return Ok(());
+        };
+        info!("Canonicalizing root {:?} to {:?}", path, canonical_path);
+        if root_paths.contains(&canonical_path) {
+            // Added twice under different names. Its files are kept, under the other one
+            let encoded_path = encode_path(path);
+            sqlx::query!("DELETE FROM dir_paths WHERE path = ?", encoded_path)
+                .execute(pool)
+                .await
+                .context(format!("Failed to remove duplicate root {:?}", path))?;
+        }
+        relocate_root(pool, path, &canonical_path).await?;
+    }
+}
+
+/// Remember which volume `root` is on, using a sample of the audio files just found under it
+pub async fn record_volume_identity(
+    pool: &SqlitePool,
+    root: &Path,
+    audio_files: &[PathBuf],
+) -> Result<()> {
+    let fingerprint = sample_fingerprint(root, audio_files);
+    // Without any files, there'd be no way to tell the volume apart from another
+    if fingerprint.is_empty() {
+        return Ok(());
+    }
+    let mount_point = find_mount_point(root);
+    let relative_path = encode_path(root.strip_prefix(&mount_point).unwrap_or(Path::new("")));
+    let mount_point = encode_path(&mount_point);
+    let fingerprint =
+        serde_json::to_string(&fingerprint).context("Failed to serialize volume fingerprint")?;
+    let encoded_root = encode_path(root);
+    sqlx::query!(
+        r#"UPDATE dir_paths SET
+            volume_mount_point = ?,
+            volume_relative_path = ?,
+            volume_fingerprint = ?
+        WHERE path = ?"#,
+        mount_point,
+        relative_path,
+        fingerprint,
+        encoded_root
+    )
+    .execute(pool)
+    .await
+    .context(format!("Failed to record volume of {:?}", root))?;
+    Ok(())
+}
+
+/// Point a root, and every root, indexed file and queued file under it, at its new location
+async fn relocate_root(pool: &SqlitePool, old_root: &Path, new_root: &Path) -> Result<()> {
+    let encoded_old_root = encode_path(old_root);
+    let encoded_new_root = encode_path(new_root);
+    let mut old_prefix = encoded_old_root.clone();
+    old_prefix.extend(MAIN_SEPARATOR.to_string().as_bytes());
+    // substr counts bytes in blobs. The rest of each path starts at the separator
+    // after the old root, and `||` returns text, so it's cast back to a blob
+    let old_prefix_length = old_prefix.len() as i64;
+
+    let mut sql_transaction = pool
+        .begin()
+        .await
+        .context("Failed to begin transaction to relocate root")?;
+    // Roots nested in this one move with it. Anything already at the new path is
+    // replaced, since it's the same file or root under another name
+    sqlx::query!(
+        r#"UPDATE OR REPLACE dir_paths SET
+            path = CAST(? || substr(path, ?) AS BLOB),
+            updated_at = CURRENT_TIMESTAMP
+        WHERE path = ? OR substr(path, 1, ?) = ?"#,
+        encoded_new_root,
+        old_prefix_length,
+        encoded_old_root,
+        old_prefix_length,
+        old_prefix
+    )
+    .execute(&mut *sql_transaction)
+    .await
+    .context(format!("Failed to relocate root {:?}", old_root))?;
+    sqlx::query!(
+        r#"UPDATE OR REPLACE file_location SET file_path = CAST(? || substr(file_path, ?) AS BLOB)
+        WHERE file_path = ? OR substr(file_path, 1, ?) = ?"#,
+        encoded_new_root,
+        old_prefix_length,
+        encoded_old_root,
+        old_prefix_length,
+        old_prefix
+    )
+    .execute(&mut *sql_transaction)
+    .await
+    .context(format!("Failed to relocate files under {:?}", old_root))?;
+    sqlx::query!(
+        r#"UPDATE OR REPLACE index_error SET file_path = CAST(? || substr(file_path, ?) AS BLOB)
+        WHERE file_path = ? OR substr(file_path, 1, ?) = ?"#,
+        encoded_new_root,
+        old_prefix_length,
+        encoded_old_root,
+        old_prefix_length,
+        old_prefix
+    )
+    .execute(&mut *sql_transaction)
+    .await
+    .context(format!(
+        "Failed to relocate index errors under {:?}",
+        old_root
+    ))?;
+    // Otherwise an interrupted run would resume on paths that no longer exist
+    sqlx::query!(
+        r#"UPDATE OR REPLACE index_job SET file_path = CAST(? || substr(file_path, ?) AS BLOB)
+        WHERE file_path = ? OR substr(file_path, 1, ?) = ?"#,
+        encoded_new_root,
+        old_prefix_length,
+        encoded_old_root,
+        old_prefix_length,
+        old_prefix
+    )
+    .execute(&mut *sql_transaction)
+    .await
+    .context(format!(
+        "Failed to relocate queued files under {:?}",
+        old_root
+    ))?;
+    sql_transaction
+        .commit()
+        .await
+        .context("Failed to commit relocation of root")?;
+    Ok(())
+}
+
+/// Where `probe_root` found a root
+enum RootProbe {
+    Present,
+    /// Its volume is mounted somewhere else now
+    Relocated(PathBuf),
+    Missing,
+}
+
+/// Look for a root on disk, or for its volume mounted somewhere other than
+/// at one of the `root_paths`. Blocks on the filesystem
+fn probe_root(row: &VolumeRow, root_paths: &[PathBuf]) -> RootProbe {
+    if decode_path(&row.path).exists() {
+        return RootProbe::Present;
+    }
+    match find_relocated_root(row).filter(|new_path| !root_paths.contains(new_path)) {
+        Some(new_path) => RootProbe::Relocated(new_path),
+        None => RootProbe::Missing,
+    }
+}
+
+/// Look for the root's volume at the other places volumes get mounted
+fn find_relocated_root(row: &VolumeRow) -> Option<PathBuf> {
+    let (Some(mount_point), Some(relative_path)) =
+        (&row.volume_mount_point, &row.volume_relative_path)
+    else {
+        return None;
+    };
+    let fingerprint: Vec<FingerprintFile> =
+        serde_json::from_str(&row.volume_fingerprint).unwrap_or_default();
+    if fingerprint.is_empty() {
+        return None;
+    }
+    let root = decode_path(&row.path);
+    let relative_path = decode_path(relative_path);
+    candidate_mount_points(&decode_path(mount_point))
+        .into_iter()
+        .map(|candidate| join_relative(&candidate, &relative_path))
+        .filter(|candidate| *candidate != root)
+        .find(|candidate| {
+            debug!("Checking whether {:?} is {:?}", candidate, root);
+            candidate.exists() && fingerprint_matches(candidate, &fingerprint)
+        })
+}
+
+/// At least half of the fingerprinted files are there, with the same sizes
+fn fingerprint_matches(root: &Path, fingerprint: &[FingerprintFile]) -> bool {
+    let n_matching = fingerprint
+        .iter()
+        .filter(|file| {
+            join_relative(root, &decode_path(&file.relative_path))
+                .metadata()
+                .is_ok_and(|metadata| metadata.len() == file.size)
+        })
+        .count();
+    n_matching > 0 && n_matching * 2 >= fingerprint.len()
+}
+
+/// Files spread evenly through `audio_files`, so one folder being reorganized doesn't
+/// throw the whole fingerprint off
+fn sample_fingerprint(root: &Path, audio_files: &[PathBuf]) -> Vec<FingerprintFile> {
+    let step = (audio_files.len() / FINGERPRINT_SIZE).max(1);
+    audio_files
+        .iter()
+        .step_by(step)
+        .take(FINGERPRINT_SIZE)
+        .filter_map(|path| {
+            Some(FingerprintFile {
+                relative_path: encode_path(path.strip_prefix(root).ok()?),
+                size: path.metadata().ok()?.len(),
+            })
+        })
+        .collect()
+}
+
+/// `base.join("")` would add a trailing separator
+fn join_relative(base: &Path, relative_path: &Path) -> PathBuf {
+    match relative_path.as_os_str().is_empty() {
+        true => base.to_path_buf(),
+        false => base.join(relative_path),
+    }
+}
+
+/// The outermost ancestor of `path` on the same device
+#[cfg(unix)]
+fn find_mount_point(path: &Path) -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(device) = path.metadata().map(|metadata| metadata.dev()) else {
+        return path.to_path_buf();
+    };
+    path.ancestors()
+        .take_while(|ancestor| {
+            ancestor
+                .metadata()
+                .is_ok_and(|metadata| metadata.dev() == device)
+        })
+        .last()
+        .unwrap_or(path)
+        .to_path_buf()
+}
+
+/// The drive `path` is on
+#[cfg(not(unix))]
+fn find_mount_point(path: &Path) -> PathBuf {
+    path.ancestors().last().unwrap_or(path).to_path_buf()
+}
+
+/// Everything mounted next to `mount_point`, and in the usual places for removable volumes
+#[cfg(unix)]
+fn candidate_mount_points(mount_point: &Path) -> Vec<PathBuf> {
+    let mut parents = vec![];
+    parents.extend(mount_point.parent().map(Path::to_path_buf));
+    parents.extend(["/Volumes", "/media", "/mnt"].map(PathBuf::from));
+    if let Ok(user) = std::env::var("USER") {
+        parents.push(Path::new("/media").join(&user));
+        parents.push(Path::new("/run/media").join(&user));
+    }
+    parents.dedup();
+    parents
+        .iter()
+        .filter_map(|parent| parent.read_dir().ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|candidate| candidate.is_dir())
+        .collect()
+}
+
+/// Every drive letter
+#[cfg(not(unix))]
+fn candidate_mount_points(_mount_point: &Path) -> Vec<PathBuf> {
+    ('A'..='Z')
+        .map(|letter| PathBuf::from(format!("{}:\\", letter)))
+        .filter(|candidate| candidate.exists())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::database::create_test_pool;
+
+    #[test]
+    fn test_fingerprint_matches() {
+        let root = Path::new("test_resources/audio");
+        let audio_files = [
+            "audio_00.wav",
+            "audio_01.wav",
+            "bit_depths/sine_16bit_int.wav",
+        ]
+        .map(|file| root.join(file));
+        let fingerprint = sample_fingerprint(root, &audio_files);
+        assert_eq!(fingerprint.len(), 3);
+        assert!(fingerprint_matches(root, &fingerprint));
+        assert!(!fingerprint_matches(
+            Path::new("test_resources"),
+            &fingerprint
+        ));
+
+        let mut resized = fingerprint.clone();
+        for file in &mut resized[..2] {
+            file.size += 1;
+        }
+        assert!(!fingerprint_matches(root, &resized));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_relocate_root() {
+        let pool = create_test_pool().await;
+        for root in [
+            b"/Volumes/SFX/Foley".as_slice(),
+            b"/Volumes/SFX/Foley/Cloth",
+            b"/Volumes/SFX/Foley Archive",
+        ] {
+            sqlx::query("INSERT INTO dir_paths (path) VALUES (?)")
+                .bind(root)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let files: [(&str, &[u8]); 4] = [
+            ("a", b"/Volumes/SFX/Foley/steps.wav"),
+            ("b", b"/Volumes/SFX/Foley/Cloth/rustle.wav"),
+            ("c", b"/Volumes/SFX/Foley Archive/steps.wav"),
+            // Latin-1 names from an old CD-ROM library aren't valid UTF-8
+            ("d", b"/Volumes/SFX/Foley/Caf\xe9.wav"),
+        ];
+        for (hash, path) in files {
+            sqlx::query("INSERT INTO audio_file (file_hash) VALUES (?)")
+                .bind(hash)
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO file_location (file_path, file_hash) VALUES (?, ?)")
+                .bind(path)
+                .bind(hash)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        relocate_root(
+            &pool,
+            Path::new("/Volumes/SFX/Foley"),
+            Path::new("/Volumes/SFX 1/Foley"),
+        )
+        .await
+        .unwrap();
+
+        let paths: Vec<Vec<u8>> =
+            sqlx::query_scalar("SELECT file_path FROM file_location ORDER BY file_hash")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                b"/Volumes/SFX 1/Foley/steps.wav".to_vec(),
+                b"/Volumes/SFX 1/Foley/Cloth/rustle.wav".to_vec(),
+                b"/Volumes/SFX/Foley Archive/steps.wav".to_vec(),
+                b"/Volumes/SFX 1/Foley/Caf\xe9.wav".to_vec(),
+            ]
+        );
+        let roots: Vec<Vec<u8>> = sqlx::query_scalar("SELECT path FROM dir_paths ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            roots,
+            vec![
+                b"/Volumes/SFX 1/Foley".to_vec(),
+                b"/Volumes/SFX 1/Foley/Cloth".to_vec(),
+                b"/Volumes/SFX/Foley Archive".to_vec(),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_relocate_root_with_pending_job() {
+        let pool = create_test_pool().await;
+        sqlx::query("INSERT INTO dir_paths (path) VALUES (?)")
+            .bind(b"/Volumes/SFX/Foley".as_slice())
+            .execute(&pool)
+            .await
+            .unwrap();
+        let queued_files: [(&str, &[u8]); 2] = [
+            ("steps_hash", b"/Volumes/SFX/Foley/steps.wav"),
+            ("door_hash", b"/Volumes/SFX/Doors/door.wav"),
+        ];
+        for (hash, path) in queued_files {
+            sqlx::query(
+                "INSERT INTO index_job (file_path, file_hash, state) VALUES (?, ?, 'pending')",
+            )
+            .bind(path)
+            .bind(hash)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        relocate_root(
+            &pool,
+            Path::new("/Volumes/SFX/Foley"),
+            Path::new("/Volumes/SFX 1/Foley"),
+        )
+        .await
+        .unwrap();
+
+        let queued_paths: Vec<Vec<u8>> =
+            sqlx::query_scalar("SELECT file_path FROM index_job ORDER BY rowid")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            queued_paths,
+            vec![
+                b"/Volumes/SFX 1/Foley/steps.wav".to_vec(),
+                b"/Volumes/SFX/Doors/door.wav".to_vec(),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_roots_are_canonicalized() {
+        let dir = std::env::temp_dir().join(format!("volumes_canonical_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("Foley")).unwrap();
+        // The temporary directory may itself be behind a symlink
+        let dir = dir.canonicalize().unwrap();
+        let link = dir.join("SFX");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(dir.join("Foley"), &link).unwrap();
+
+        let pool = create_test_pool().await;
+        for root in [link.clone(), link.join("Cloth")] {
+            sqlx::query("INSERT INTO dir_paths (path) VALUES (?)")
+                .bind(encode_path(&root))
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        sqlx::query("INSERT INTO audio_file (file_hash) VALUES ('steps_hash')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO file_location (file_path, file_hash) VALUES (?, 'steps_hash')")
+            .bind(encode_path(&link.join("steps.wav")))
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        canonicalize_roots(&pool).await.unwrap();
+
+        let roots: Vec<Vec<u8>> = sqlx::query_scalar("SELECT path FROM dir_paths ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            roots
+                .iter()
+                .map(|root| decode_path(root))
+                .collect::<Vec<_>>(),
+            // The nested root doesn't exist, so it only moves with its parent
+            vec![dir.join("Foley"), dir.join("Foley/Cloth")]
+        );
+        let file_path: Vec<u8> = sqlx::query_scalar("SELECT file_path FROM file_location")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(decode_path(&file_path), dir.join("Foley/steps.wav"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+import { createSignal, onMount } from "solid-js";
+import { HttpApiSettings, commands } from "../lib/specta-bindings";
+
+export function HttpApiSettingsSection() {
+  const [settings, setSettings] = createSignal<HttpApiSettings | null>(null);
+  const [error, setError] = createSignal<string | null>(null);
+
+  async function syncSettings() {
+    try {
+      const settingsRes = await commands.getHttpApiSettings();
+      if (settingsRes.status === "error") {
+        console.error(settingsRes.error);
+      } else {
+        setSettings(settingsRes.data);
+      }
+    } catch (e) {
+      console.error(e);
+    }
+  }
+
+  async function saveSettings(enabled: boolean, port: number) {
+    const settingsRes = await commands.setHttpApiSettings(enabled, port);
+    if (settingsRes.status === "error") {
+      console.error(settingsRes.error);
+      setError(settingsRes.error);
+      // The setting is saved even if the server couldn't start
+      await syncSettings();
+    } else {
+      setError(null);
+      setSettings(settingsRes.data);
+    }
+  }
+
+  async function resetToken() {
+    const settingsRes = await commands.resetHttpApiToken();
+    if (settingsRes.status === "error") {
+      console.error(settingsRes.error);
+      setError(settingsRes.error);
+    } else {
+      setSettings(settingsRes.data);
+    }
+  }
+
+  onMount(syncSettings);
+
+  return (
+    <>
+      {settings() && (
+        <div class="http-api-settings">
+          <label>
+            <input
+              type="checkbox"
+              checked={settings()!.enabled}
+              onChange={(e) =>
+                saveSettings(e.currentTarget.checked, settings()!.port)
+              }
+            />
+            Local API on port{" "}
+            <input
+              type="number"
+              min="1"
+              max="65535"
+              value={settings()!.port}
+              onChange={(e) =>
+                saveSettings(settings()!.enabled, e.currentTarget.valueAsNumber)
+              }
+            />
+          </label>
+          {settings()!.enabled && (
+            <div class="http-api-token">
+              <p class="small">Token: {settings()!.token}</p>
+              <button onClick={resetToken}>Reset Token</button>
+            </div>
+          )}
+          {error() && <p class="small">{error()}</p>}
+        </div>
+      )}
+    </>
+  );
+}
@@ -0,0 +1,65 @@
+import { createEffect, createSignal } from "solid-js";
+import { indexingStatus, isIndexing } from "../App";
+import { IndexError, commands } from "../lib/specta-bindings";
+
+function getLastPortionOfPath(path: string) {
+  const splitPath = path.split("/");
+  return splitPath[splitPath.length - 1];
+}
+
+export function IndexErrors() {
+  const [indexErrors, setIndexErrors] = createSignal<IndexError[]>([]);
+
+  async function syncIndexErrors() {
+    try {
+      const errorsRes = await commands.getIndexErrors();
+      if (errorsRes.status === "error") {
+        console.error(errorsRes.error);
+      } else {
+        setIndexErrors(errorsRes.data);
+      }
+    } catch (e) {
+      console.error(e);
+    }
+  }
+
+  async function retryFailedFiles() {
+    const retryRes = await commands.retryFailedFiles();
+    if (retryRes.status === "error") {
+      console.error(retryRes.error);
+    }
+  }
+
+  // Refresh whenever an indexing run finishes
+  createEffect(() => {
+    if (indexingStatus() === "Idle") syncIndexErrors();
+  });
+
+  return (
+    <>
+      {indexErrors().length > 0 && (
+        <div class="index-errors">
+          <p>
+            {indexErrors().length} file{indexErrors().length === 1 ? "" : "s"}{" "}
+            failed to index
+          </p>
+          <div class="indexed-paths">
+            <ul>
+              {indexErrors().map((indexError) => (
+                <li>
+                  <div class="indexed-path" title={indexError.message}>
+                    <p>{getLastPortionOfPath(indexError.file_path)}</p>
+                    <p class="small">{indexError.kind}</p>
+                  </div>
+                </li>
+              ))}
+            </ul>
+          </div>
+          <button disabled={isIndexing()} onClick={retryFailedFiles}>
+            Retry Failed Files
+          </button>
+        </div>
+      )}
+    </>
+  );
+}
@@ -3,27 +3,22 @@ import { indexingStatus, isIndexing, updateAudioIndex } from "../App";
 
 const getProgressPercentage = () => {
   const status = indexingStatus();
-  if (status === "Started") return 0;
   if (status === "Idle") return 100;
-  if (status.InProgress.indexing === null)
-    return (
-      (status.InProgress.preindexing.preindexed / status.InProgress.total) * 100
-    );
-  else if (status.InProgress.indexing !== null) {
-    return (
-      ((status.InProgress.total -
-        status.InProgress.indexing.total_to_index +
-        status.InProgress.indexing.newly_indexed) /
-        status.InProgress.total) *
-      100
-    );
-  }
-  return 0;
+  const { total, preindexing, indexing } = status.InProgress;
+  if (total === 0) return 0;
+  if (indexing === null) return (preindexing.preindexed / total) * 100;
+  return (
+    ((total -
+      indexing.total_to_index +
+      indexing.newly_indexed +
+      indexing.failed) /
+      total) *
+    100
+  );
 };
 
 const getProgressColor = () => {
   const status = indexingStatus();
-  if (status === "Started") return "rgba(128, 128, 128, 0.5)";
   if (status === "Idle") return "rgba(128, 128, 128, 0.5)";
   if (status.InProgress.indexing === null) return "rgba(128, 128, 128, 0.4)";
   return "rgba(128, 128, 128, 0.5)";
@@ -32,12 +27,26 @@ const getProgressColor = () => {
 const getButtonText = () => {
   const status = indexingStatus();
   if (status === "Idle") return "Refresh Index";
-  if (status === "Started" || status.InProgress.indexing === null)
-    return "Preparing...";
-  if (status.InProgress.indexing !== null) return "Indexing...";
-  return "Refresh Index";
+  switch (status.InProgress.phase) {
+    case "Scanning":
+      return "Scanning folders...";
+    case "Hashing":
+      return "Preparing...";
+    case "Decoding":
+    case "Embedding":
+      return status.InProgress.resumed
+        ? "Resuming indexing..."
+        : "Indexing...";
+    case "SyncingIndex":
+      return "Finishing up...";
+  }
 };
 
+function getFileName(path: string) {
+  const splitPath = path.split(/[\\/]/);
+  return splitPath[splitPath.length - 1];
+}
+
 function trimLeadingZero(str: string) {
   return str[0] === "0" ? str.slice(1) : str;
 }
@@ -58,45 +67,44 @@ function secondsToString(seconds: number): string | null {
 
 function getSubtitleText(): string | null {
   const status = indexingStatus();
-  if (status === "Idle" || status === "Started") {
+  if (status === "Idle") {
     return null;
   } else if (status.InProgress.indexing === null) {
     return "This shouldn't take long";
-  } else if (status.InProgress.indexing !== null) {
+  } else {
     const defaultText = "Calculating time remaining...";
+    const { indexing } = status.InProgress;
     // Avoid overpromising:
     // wait until at least some of the index has been processed to estimate
     if (
-      status.InProgress.indexing.newly_indexed <
-      status.InProgress.indexing.total_to_index * 0.12
+      indexing.eta_seconds === null ||
+      indexing.newly_indexed + indexing.failed < indexing.total_to_index * 0.12
     )
       return defaultText;
-    console.debug("Computing estimated time remaining for:", status);
-    const { indexing } = status.InProgress;
-    const secondsElapsed =
-      (Date.now() - Date.parse(indexing.started_indexing)) / 1000;
-    if (secondsElapsed === 0) {
-      return defaultText;
-    }
-    const indexedPerSecond = indexing.newly_indexed / secondsElapsed;
-    if (indexedPerSecond === 0) {
-      return defaultText;
-    }
-    const estimatedSecondsRemaining =
-      (indexing.total_to_index - indexing.newly_indexed) / indexedPerSecond;
-    const timeText = secondsToString(estimatedSecondsRemaining);
+    const timeText = secondsToString(indexing.eta_seconds);
     return timeText ? `About ${timeText} remaining` : defaultText;
-  } else {
-    return null;
   }
 }
 
+function getDetailText(): string | null {
+  const status = indexingStatus();
+  if (status === "Idle" || status.InProgress.indexing === null) return null;
+  const { indexing, current_file } = status.InProgress;
+  const details = [`${indexing.segments_embedded} segments`];
+  if (indexing.files_per_second !== null)
+    details.push(`${indexing.files_per_second.toFixed(1)} files/s`);
+  if (indexing.failed > 0) details.push(`${indexing.failed} failed`);
+  if (current_file !== null) details.push(getFileName(current_file));
+  return details.join(" · ");
+}
+
 export const IndexingButton = () => {
   const progress = createMemo(getProgressPercentage);
   const buttonText = createMemo(getButtonText);
   const progressColor = createMemo(getProgressColor);
 
   const subtitleText = createMemo(getSubtitleText);
+  const detailText = createMemo(getDetailText);
 
   return (
     <button
@@ -110,6 +118,7 @@ export const IndexingButton = () => {
     >
       <p>{buttonText()}</p>
       {subtitleText() && <p class="small">{subtitleText()}</p>}
+      {detailText() && <p class="small">{detailText()}</p>}
     </button>
   );
 };
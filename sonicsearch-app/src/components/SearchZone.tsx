@@ -1,14 +1,72 @@
 import { basename } from "@tauri-apps/api/path";
+import { save } from "@tauri-apps/api/dialog";
 import { Command } from "@tauri-apps/api/shell";
 import { createSignal } from "solid-js";
 import { AudioPlayer } from "./AudioPlayer";
-import { AiFillFolderOpen } from "solid-icons/ai";
-import { commands } from "../lib/specta-bindings";
+import { WaveformOverview } from "./WaveformOverview";
+import {
+  AiFillDislike,
+  AiFillFolderOpen,
+  AiFillLike,
+  AiOutlineDislike,
+  AiOutlineLike,
+  AiOutlineScissor,
+} from "solid-icons/ai";
+import {
+  ExportFormat,
+  RegionOptions,
+  StructuredQuery,
+  commands,
+} from "../lib/specta-bindings";
 
 type ProcessedSearchResult = {
+  segmentId: number;
   fullPath: string;
+  otherLocations: string[];
   basename: string;
   startingTimestamp: number;
+  endingTimestamp: number;
+  available: boolean;
+};
+
+type SearchResult = Parameters<typeof commands.exportResults>[0][number];
+
+const EXPORT_FORMATS: {
+  format: ExportFormat;
+  name: string;
+  extensions: string[];
+}[] = [
+  { format: "M3u", name: "Playlist", extensions: ["m3u8", "m3u"] },
+  { format: "Csv", name: "CSV", extensions: ["csv"] },
+  { format: "Json", name: "JSON", extensions: ["json"] },
+  { format: "ReaperProject", name: "Reaper project", extensions: ["rpp"] },
+  { format: "Edl", name: "EDL", extensions: ["edl"] },
+];
+
+// "rain -thunder" searches for rain, steering away from thunder
+const NEGATIVE_TERM_WEIGHT = -0.5;
+
+function parseSearchString(searchString: string): StructuredQuery | null {
+  const [positive, ...negatives] = searchString.split(/\s+-(?=\S)/);
+  if (negatives.length === 0) return null;
+  return {
+    text_terms: [
+      { text: positive.trim(), weight: 1 },
+      ...negatives.map((text) => ({
+        text: text.trim(),
+        weight: NEGATIVE_TERM_WEIGHT,
+      })),
+    ],
+    audio_terms: [],
+  };
+}
+
+// Short fades so clips cut mid-sound don't click
+const CLIP_OPTIONS: RegionOptions = {
+  pre_roll_seconds: 0.5,
+  post_roll_seconds: 0.5,
+  fade_in_seconds: 0.01,
+  fade_out_seconds: 0.01,
 };
 
 function secondsToString(seconds: number) {
@@ -26,13 +84,34 @@ export function SearchZone() {
   const [searchResults, setSearchResults] = createSignal<
     ProcessedSearchResult[]
   >([]);
+  const [rawSearchResults, setRawSearchResults] = createSignal<SearchResult[]>(
+    []
+  );
+  const [exportFormat, setExportFormat] = createSignal(EXPORT_FORMATS[0]);
   const [isSearching, setIsSearching] = createSignal(false);
   const [searchString, setSearchString] = createSignal("");
+  // Thumbs up or down on results, by segment, kept across rounds of refining
+  const [feedback, setFeedback] = createSignal<Map<number, boolean>>(
+    new Map()
+  );
+  const [persistFeedback, setPersistFeedback] = createSignal(true);
+  // What the user searched with, which every round of refining starts from
+  const [originalQueryEmbedding, setOriginalQueryEmbedding] = createSignal<
+    number[] | null
+  >(null);
+  // What the results were searched with, refined along with them
+  const [queryEmbedding, setQueryEmbedding] = createSignal<number[] | null>(
+    null
+  );
+
   async function search() {
     setIsSearching(true);
     const currentSearchString = searchString();
     console.log(`Searching for ${currentSearchString}`);
-    const res = await commands.searchIndex(currentSearchString);
+    const structuredQuery = parseSearchString(currentSearchString);
+    const res = structuredQuery
+      ? await commands.searchIndexStructured(structuredQuery)
+      : await commands.searchIndex(currentSearchString);
     setIsSearching(false);
 
     console.log(res);
@@ -40,20 +119,109 @@ export function SearchZone() {
       console.error(res.error);
       return;
     }
-    const parsedRes = res.data;
+    const embeddingRes = await commands.embedSearchQuery(
+      structuredQuery ?? {
+        text_terms: [{ text: currentSearchString, weight: 1 }],
+        audio_terms: [],
+      }
+    );
+    if (embeddingRes.status === "error") {
+      console.error(embeddingRes.error);
+    }
+    const embedding = embeddingRes.status === "ok" ? embeddingRes.data : null;
+    setOriginalQueryEmbedding(embedding);
+    setQueryEmbedding(embedding);
+    setFeedback(new Map());
+    await showResults(res.data);
+  }
 
+  async function refine() {
+    const originalEmbedding = originalQueryEmbedding();
+    const embedding = queryEmbedding();
+    if (originalEmbedding === null || embedding === null) return;
+    setIsSearching(true);
+    const marked = [...feedback().entries()];
+    const res = await commands.refineSearch(
+      originalEmbedding,
+      embedding,
+      marked.filter(([, relevant]) => relevant).map(([id]) => id),
+      marked.filter(([, relevant]) => !relevant).map(([id]) => id),
+      persistFeedback()
+    );
+    setIsSearching(false);
+    if (res.status === "error") {
+      console.error(res.error);
+      return;
+    }
+    setQueryEmbedding(res.data.query_embedding);
+    await showResults(res.data.results);
+  }
+
+  function toggleFeedback(segmentId: number, relevant: boolean) {
+    const newFeedback = new Map(feedback());
+    if (newFeedback.get(segmentId) === relevant) {
+      newFeedback.delete(segmentId);
+    } else {
+      newFeedback.set(segmentId, relevant);
+    }
+    setFeedback(newFeedback);
+  }
+
+  async function showResults(parsedRes: SearchResult[]) {
     const processedRes = await Promise.all(
       parsedRes.map(async (res) => {
         return {
+          segmentId: res.segment_id,
           fullPath: res.file_path,
+          otherLocations: res.other_locations,
           basename: await basename(res.file_path),
           startingTimestamp: res.starting_timestamp,
+          endingTimestamp: res.ending_timestamp,
+          available: res.available,
         } satisfies ProcessedSearchResult;
       })
     );
+    setRawSearchResults(parsedRes);
     setSearchResults(processedRes);
   }
 
+  async function exportResults() {
+    const { format, name, extensions } = exportFormat();
+    const destination = await save({
+      defaultPath: `${searchString() || "results"}.${extensions[0]}`,
+      filters: [{ name, extensions }],
+    });
+    if (destination === null) return;
+    const exportRes = await commands.exportResults(
+      rawSearchResults(),
+      format,
+      destination
+    );
+    if (exportRes.status === "error") {
+      console.error(exportRes.error);
+    }
+  }
+
+  async function exportClip(searchResult: ProcessedSearchResult) {
+    const stem = searchResult.basename.replace(/\.[^.]*$/, "");
+    const destination = await save({
+      defaultPath: `${stem} ${Math.round(searchResult.startingTimestamp)}s.wav`,
+      filters: [{ name: "WAV", extensions: ["wav"] }],
+    });
+    if (destination === null) return;
+    const exportRes = await commands.exportRegion(
+      searchResult.segmentId,
+      searchResult.fullPath,
+      searchResult.startingTimestamp,
+      searchResult.endingTimestamp,
+      CLIP_OPTIONS,
+      destination
+    );
+    if (exportRes.status === "error") {
+      console.error(exportRes.error);
+    }
+  }
+
   return (
     <div class="search-zone">
       <form
@@ -78,10 +246,46 @@ export function SearchZone() {
         </button>
       </form>
 
+      {searchResults().length > 0 && (
+        <div class="row export-results">
+          <select
+            onChange={(e) =>
+              setExportFormat(EXPORT_FORMATS[e.currentTarget.selectedIndex])
+            }
+          >
+            {EXPORT_FORMATS.map(({ name }) => (
+              <option>{name}</option>
+            ))}
+          </select>
+          <button onClick={exportResults}>Export</button>
+          {feedback().size > 0 && (
+            <>
+              <label title="Keep the marked results to improve search later">
+                <input
+                  type="checkbox"
+                  checked={persistFeedback()}
+                  onChange={(e) => setPersistFeedback(e.currentTarget.checked)}
+                />
+                Remember marks
+              </label>
+              <button disabled={isSearching()} onClick={refine}>
+                Refine
+              </button>
+            </>
+          )}
+        </div>
+      )}
+
       {searchResults().length > 0 && (
         <ul class="search-results">
           {searchResults().map((searchResult) => (
-            <li class="search-result">
+            <li
+              class={
+                searchResult.available
+                  ? "search-result"
+                  : "search-result unavailable"
+              }
+            >
               <div class="search-result">
                 <div class="search-result-left">
                   <div>
@@ -92,18 +296,82 @@ export function SearchZone() {
                       <span class="search-result-starting-timestamp">
                         {" (" +
                           secondsToString(searchResult.startingTimestamp) +
+                          "–" +
+                          secondsToString(searchResult.endingTimestamp) +
                           ")"}
                       </span>
+                      {!searchResult.available && (
+                        <span class="search-result-offline"> · Offline</span>
+                      )}
+                      {searchResult.otherLocations.length > 0 && (
+                        <span
+                          class="search-result-copies"
+                          title={searchResult.otherLocations.join("\n")}
+                        >
+                          {` · ${searchResult.otherLocations.length + 1} copies`}
+                        </span>
+                      )}
                     </p>
                   </div>
-                  <div>
-                    <AudioPlayer
+                  {searchResult.available && (
+                    <WaveformOverview
+                      segmentId={searchResult.segmentId}
                       src={searchResult.fullPath}
+                      queryEmbedding={queryEmbedding()}
                       startingTimestamp={searchResult.startingTimestamp}
+                      endingTimestamp={searchResult.endingTimestamp}
                     />
-                  </div>
+                  )}
+                  {searchResult.available && (
+                    <div>
+                      <AudioPlayer
+                        src={searchResult.fullPath}
+                        startingTimestamp={searchResult.startingTimestamp}
+                      />
+                    </div>
+                  )}
                 </div>
                 <div class="search-result-right">
+                  <a
+                    class="search-result-folder"
+                    title="More like this"
+                    onClick={(e) => {
+                      e.preventDefault();
+                      toggleFeedback(searchResult.segmentId, true);
+                    }}
+                  >
+                    {feedback().get(searchResult.segmentId) === true ? (
+                      <AiFillLike />
+                    ) : (
+                      <AiOutlineLike />
+                    )}
+                  </a>
+                  <a
+                    class="search-result-folder"
+                    title="Less like this"
+                    onClick={(e) => {
+                      e.preventDefault();
+                      toggleFeedback(searchResult.segmentId, false);
+                    }}
+                  >
+                    {feedback().get(searchResult.segmentId) === false ? (
+                      <AiFillDislike />
+                    ) : (
+                      <AiOutlineDislike />
+                    )}
+                  </a>
+                  {searchResult.available && (
+                    <a
+                      class="search-result-folder"
+                      title="Export this region as a clip"
+                      onClick={(e) => {
+                        e.preventDefault();
+                        exportClip(searchResult);
+                      }}
+                    >
+                      <AiOutlineScissor />
+                    </a>
+                  )}
                   <a
                     class="search-result-folder"
                     onClick={(e) => {
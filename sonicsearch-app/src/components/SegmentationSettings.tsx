@@ -0,0 +1,55 @@
+import { createSignal, onMount } from "solid-js";
+import { SegmentationStrategy, commands } from "../lib/specta-bindings";
+
+export function SegmentationSettingsSection() {
+  const [strategy, setStrategy] = createSignal<SegmentationStrategy | null>(
+    null
+  );
+
+  async function syncStrategy() {
+    try {
+      const strategyRes = await commands.getSegmentationStrategy();
+      if (strategyRes.status === "error") {
+        console.error(strategyRes.error);
+      } else {
+        setStrategy(strategyRes.data);
+      }
+    } catch (e) {
+      console.error(e);
+    }
+  }
+
+  async function saveStrategy(newStrategy: SegmentationStrategy) {
+    const strategyRes = await commands.setSegmentationStrategy(newStrategy);
+    if (strategyRes.status === "error") {
+      console.error(strategyRes.error);
+      await syncStrategy();
+    } else {
+      setStrategy(newStrategy);
+    }
+  }
+
+  onMount(syncStrategy);
+
+  return (
+    <>
+      {strategy() && (
+        <div class="segmentation-settings">
+          <label>
+            Segments{" "}
+            <select
+              value={strategy()!}
+              onChange={(e) =>
+                saveStrategy(e.currentTarget.value as SegmentationStrategy)
+              }
+            >
+              <option value="FixedWindow">Fixed windows</option>
+              <option value="EventAligned">Aligned to sounds</option>
+            </select>
+          </label>
+          <p class="small">Only applies to files indexed from now on</p>
+        </div>
+      )}
+    </>
+  );
+}
@@ -2,8 +2,11 @@ import { listen, TauriEvent, UnlistenFn } from "@tauri-apps/api/event";
 import { onMount, onCleanup, createSignal } from "solid-js";
 import { AiOutlineClose, AiOutlineDelete } from "solid-icons/ai";
 import { currentlyIndexedPaths, setCurrentlyIndexedPaths } from "../App";
-import { commands } from "../lib/specta-bindings";
+import { commands, IndexRoot } from "../lib/specta-bindings";
+import { HttpApiSettingsSection } from "./HttpApiSettings";
+import { IndexErrors } from "./IndexErrors";
 import { IndexingButton } from "./IndexingButton";
+import { SegmentationSettingsSection } from "./SegmentationSettings";
 
 function getLastPortionOfPath(path: string) {
   const splitPath = path.split("/");
@@ -24,6 +27,45 @@ export function SettingsModal({ onClose }: { onClose: () => void }) {
     null
   );
 
+  const [indexRoots, setIndexRoots] = createSignal<IndexRoot[]>([]);
+
+  async function refreshIndexRoots() {
+    const rootsRes = await commands.getIndexRoots();
+    if (rootsRes.status === "error") {
+      console.error(rootsRes.error);
+    } else {
+      setIndexRoots(rootsRes.data);
+    }
+  }
+
+  function isPathEnabled(path: string) {
+    const root = indexRoots().find((root) => root.path === path);
+    return root?.options.enabled ?? true;
+  }
+
+  function isPathOnline(path: string) {
+    const root = indexRoots().find((root) => root.path === path);
+    return root?.online ?? true;
+  }
+
+  async function setPathEnabled(path: string, enabled: boolean) {
+    const root = indexRoots().find((root) => root.path === path);
+    if (!root) return;
+    try {
+      const rootsRes = await commands.setRootOptions(path, {
+        ...root.options,
+        enabled,
+      });
+      if (rootsRes.status === "error") {
+        console.error(rootsRes.error);
+      } else {
+        setIndexRoots(rootsRes.data);
+      }
+    } catch (e) {
+      console.error(e);
+    }
+  }
+
   async function addPathsToIndex(paths: string[]) {
     try {
       const currentPathsRes = await commands.addPathsToIndex(paths);
@@ -31,6 +73,7 @@ export function SettingsModal({ onClose }: { onClose: () => void }) {
         console.error(currentPathsRes.error);
       } else {
         setCurrentlyIndexedPaths(currentPathsRes.data);
+        await refreshIndexRoots();
       }
     } catch (e) {
       console.error(e);
@@ -44,6 +87,7 @@ export function SettingsModal({ onClose }: { onClose: () => void }) {
         console.error(deleteRes.error);
       } else {
         setCurrentlyIndexedPaths(deleteRes.data);
+        await refreshIndexRoots();
       }
     } catch (e) {
       console.error(e);
@@ -90,6 +134,7 @@ export function SettingsModal({ onClose }: { onClose: () => void }) {
     }
 
     registerFileDragListeners();
+    refreshIndexRoots();
   });
 
   onCleanup(() => {
@@ -137,10 +182,23 @@ export function SettingsModal({ onClose }: { onClose: () => void }) {
           <ul>
             {currentlyIndexedPaths().map((path) => (
               <li>
-                <div class="indexed-path" aria-describedby="path-tooltip">
+                <div
+                  class={
+                    isPathOnline(path) ? "indexed-path" : "indexed-path offline"
+                  }
+                  aria-describedby="path-tooltip"
+                >
                   <div role="tooltip" id="path-tooltip">
-                    {path}
+                    {isPathOnline(path) ? path : `${path} (offline)`}
                   </div>
+                  <input
+                    type="checkbox"
+                    title="Include this folder when indexing"
+                    checked={isPathEnabled(path)}
+                    onChange={(e) =>
+                      setPathEnabled(path, e.currentTarget.checked)
+                    }
+                  />
                   <p>{getLastPortionOfPath(path)}</p>
                   <AiOutlineDelete onClick={() => deletePathFromIndex(path)} />
                 </div>
@@ -149,6 +207,9 @@ export function SettingsModal({ onClose }: { onClose: () => void }) {
           </ul>
         </div>
         <IndexingButton />
+        <IndexErrors />
+        <HttpApiSettingsSection />
+        <SegmentationSettingsSection />
       </div>
     </div>
   );
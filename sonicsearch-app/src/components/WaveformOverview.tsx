@@ -0,0 +1,103 @@
+import { createSignal, onMount } from "solid-js";
+import { FileOverview, commands } from "../lib/specta-bindings";
+
+const WIDTH = 512;
+const HEIGHT = 40;
+
+/**
+ * A file's waveform, shaded by how similar each segment is to the search,
+ * with the matched region outlined
+ */
+export function WaveformOverview({
+  segmentId,
+  src,
+  queryEmbedding,
+  startingTimestamp,
+  endingTimestamp,
+}: {
+  segmentId: number;
+  src: string;
+  queryEmbedding: number[] | null;
+  startingTimestamp: number;
+  endingTimestamp: number;
+}) {
+  const [overview, setOverview] = createSignal<FileOverview | null>(null);
+
+  onMount(async () => {
+    try {
+      const overviewRes = await commands.getFileOverview(
+        segmentId,
+        src,
+        queryEmbedding
+      );
+      if (overviewRes.status === "error") {
+        console.error(overviewRes.error);
+      } else {
+        setOverview(overviewRes.data);
+      }
+    } catch (e) {
+      console.error(e);
+    }
+  });
+
+  function toX(seconds: number) {
+    const duration = overview()?.duration_seconds;
+    return duration ? (seconds / duration) * WIDTH : 0;
+  }
+
+  function bars(levels: number[]) {
+    const barWidth = WIDTH / Math.max(levels.length, 1);
+    return levels.map((level, i) => ({
+      x: i * barWidth,
+      width: barWidth,
+      y: ((1 - level) * HEIGHT) / 2,
+      height: Math.max(level * HEIGHT, 1),
+    }));
+  }
+
+  // Shade segments relative to each other, since raw similarities span a narrow range
+  function segmentOpacity(similarity: number) {
+    const similarities = overview()!.segments.map((s) => s.similarity);
+    const min = Math.min(...similarities);
+    const max = Math.max(...similarities);
+    return max > min ? (0.6 * (similarity - min)) / (max - min) : 0.3;
+  }
+
+  return (
+    <>
+      {overview() && (
+        <svg
+          class="waveform-overview"
+          viewBox={`0 0 ${WIDTH} ${HEIGHT}`}
+          preserveAspectRatio="none"
+        >
+          {overview()!.segments.map((segment) => (
+            <rect
+              class="waveform-similarity"
+              x={toX(segment.starting_timestamp)}
+              width={toX(segment.ending_timestamp - segment.starting_timestamp)}
+              y={0}
+              height={HEIGHT}
+              opacity={segmentOpacity(segment.similarity)}
+            >
+              <title>{segment.similarity.toFixed(3)}</title>
+            </rect>
+          ))}
+          {bars(overview()!.waveform.peaks).map((bar) => (
+            <rect class="waveform-peak" {...bar} />
+          ))}
+          {bars(overview()!.waveform.rms).map((bar) => (
+            <rect class="waveform-rms" {...bar} />
+          ))}
+          <rect
+            class="waveform-match"
+            x={toX(startingTimestamp)}
+            width={toX(endingTimestamp - startingTimestamp)}
+            y={0}
+            height={HEIGHT}
+          />
+        </svg>
+      )}
+    </>
+  );
+}
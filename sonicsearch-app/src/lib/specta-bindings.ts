@@ -1,7 +1,7 @@
          // This file was generated by [tauri-specta](https://github.com/oscartbeaumont/tauri-specta). Do not edit this file manually.
 
          export const commands = {
-async searchIndex(searchString: string) : Promise<__Result__<{ file_path: string; starting_timestamp: number; distance: number }[], string>> {
+async searchIndex(searchString: string) : Promise<__Result__<{ segment_id: number; file_path: string; other_locations: string[]; starting_timestamp: number; ending_timestamp: number; distance: number; available: boolean }[], string>> {
 try {
     return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|search_index", { searchString }) };
 } catch (e) {
@@ -40,7 +40,8 @@ try {
 }
 },
 /**
- * Delete a path from the index
+ * Delete a path from the index. The path doesn't need to exist,
+ * so roots on volumes that are gone for good can still be removed
  */
 async deletePathFromIndex(path: string) : Promise<__Result__<string[], string>> {
 try {
@@ -63,6 +64,218 @@ try {
     if(e instanceof Error) throw e;
     else return { status: "error", error: e  as any };
 }
+},
+/**
+ * Get the audio embedder's queue depth, batch size and throughput
+ */
+async getEmbedderMetrics() : Promise<__Result__<EmbedderMetrics, string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|get_embedder_metrics") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Get every file that failed to index, most recent first
+ */
+async getIndexErrors() : Promise<__Result__<IndexError[], string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|get_index_errors") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Retry every failed file now, regardless of backoff, by updating the index.
+ * An interrupted job is dropped rather than resumed, since only a full scan finds
+ * the failed files. Its unfinished files aren't indexed, so the scan finds them again.
+ * If indexing is already running, returns false and the failed files are retried by the next run.
+ */
+async retryFailedFiles() : Promise<__Result__<boolean, string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|retry_failed_files") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Get the files, duration and segments in the index, broken down by root and format,
+ * along with the size of the database and the latest indexing runs
+ */
+async getLibraryStats() : Promise<__Result__<LibraryStats, string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|get_library_stats") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Get all paths from the index, along with how each is scanned
+ */
+async getIndexRoots() : Promise<__Result__<IndexRoot[], string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|get_index_roots") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Set how a path in the index is scanned. Takes effect on the next index update
+ */
+async setRootOptions(path: string, options: RootOptions) : Promise<__Result__<IndexRoot[], string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|set_root_options", { path, options }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Group the indexed files that are at least `min_similarity` alike.
+ * Groups are ordered by size, largest first.
+ */
+async findDuplicates(minSimilarity: number | null) : Promise<__Result__<DuplicateGroup[], string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|find_duplicates", { minSimilarity }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Get whether the local API is enabled, along with its port and token
+ */
+async getHttpApiSettings() : Promise<__Result__<HttpApiSettings, string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|get_http_api_settings") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Enable or disable the local API, restarting it on the new port
+ */
+async setHttpApiSettings(enabled: boolean, port: number) : Promise<__Result__<HttpApiSettings, string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|set_http_api_settings", { enabled, port }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Replace the API token, so clients with the old one are locked out
+ */
+async resetHttpApiToken() : Promise<__Result__<HttpApiSettings, string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|reset_http_api_token") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Get how files are split into segments when they're indexed
+ */
+async getSegmentationStrategy() : Promise<__Result__<SegmentationStrategy, string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|get_segmentation_strategy") };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Choose how files are split into segments.
+ * Only files indexed from now on are affected; files already in the index keep their segments.
+ */
+async setSegmentationStrategy(strategy: SegmentationStrategy) : Promise<__Result__<null, string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|set_segmentation_strategy", { strategy }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Write search results to `destination`, in the order given
+ */
+async exportResults(results: { segment_id: number; file_path: string; other_locations: string[]; starting_timestamp: number; ending_timestamp: number; distance: number; available: boolean }[], format: ExportFormat, destination: string) : Promise<__Result__<null, string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|export_results", { results, format, destination }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Write the part of a result's file between `start_seconds` and `end_seconds` to `destination`.
+ * `file_path` is the result's path as shown, which is looked up among the locations
+ * of the file that `segment_id` was cut from
+ */
+async exportRegion(segmentId: number, filePath: string, startSeconds: number, endSeconds: number, options: RegionOptions, destination: string) : Promise<__Result__<null, string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|export_region", { segmentId, filePath, startSeconds, endSeconds, options, destination }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Get the waveform of a result's file, and the similarity of each of its segments to `query_embedding`,
+ * the embedding the results were searched with.
+ * `file_path` is the result's path as shown, which is looked up among the locations
+ * of the file that `segment_id` was cut from
+ */
+async getFileOverview(segmentId: number, filePath: string, queryEmbedding: number[] | null) : Promise<__Result__<FileOverview, string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|get_file_overview", { segmentId, filePath, queryEmbedding }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Search with weighted text and audio terms, summed into one query embedding
+ */
+async searchIndexStructured(query: StructuredQuery) : Promise<__Result__<{ segment_id: number; file_path: string; other_locations: string[]; starting_timestamp: number; ending_timestamp: number; distance: number; available: boolean }[], string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|search_index_structured", { query }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * The embedding a structured query searches with, to refine it with feedback later
+ */
+async embedSearchQuery(query: StructuredQuery) : Promise<__Result__<number[], string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|embed_search_query", { query }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
+},
+/**
+ * Search again with the original query refined by the segments marked so far, in every round.
+ * `query_embedding` is the query that found the results the user is looking at,
+ * which is saved along with the original query if `persist_feedback` is set.
+ * Segments marked irrelevant are left out of the new results.
+ */
+async refineSearch(originalQueryEmbedding: number[], queryEmbedding: number[], relevantSegmentIds: number[], irrelevantSegmentIds: number[], persistFeedback: boolean) : Promise<__Result__<RefinedSearch, string>> {
+try {
+    return { status: "ok", data: await TAURI_INVOKE("plugin:tauri-specta|refine_search", { originalQueryEmbedding, queryEmbedding, relevantSegmentIds, irrelevantSegmentIds, persistFeedback }) };
+} catch (e) {
+    if(e instanceof Error) throw e;
+    else return { status: "error", error: e  as any };
+}
 }
 }
 
@@ -76,12 +289,222 @@ updateAudioIndex: "plugin:tauri-specta:update-audio-index"
 
 /** user-defined types **/
 
-export type IndexingProgress = { started_indexing: string; newly_indexed: number; total_to_index: number }
+export type AudioTerm = { file_path: string; weight: number }
+export type DuplicateGroup = { 
+/**
+ * The longest file comes first, since the others are often trimmed or converted copies of it
+ */
+members: DuplicateMember[]; 
+/**
+ * The lowest similarity of any member to the first
+ */
+min_similarity: number }
+export type DuplicateMember = { file_path: string; duration_seconds: number | null; 
+/**
+ * Cosine similarity to the first member of the group, 1 for identical copies
+ */
+similarity: number }
+export type EmbedderMetrics = { 
+/**
+ * Inputs waiting to be embedded
+ */
+queue_depth: number; 
+/**
+ * Memory held by queued and in-flight inputs
+ */
+queue_memory_mb: number; queue_memory_limit_mb: number; batch_size: number; max_batch_size: number; batches_embedded: number; inputs_embedded: number; inputs_failed: number; last_batch_latency_ms: number; 
+/**
+ * Moving average over recent batches
+ */
+inputs_per_second: number }
+export type ExportFormat = 
+/**
+ * An extended M3U playlist in UTF-8, for .m3u or .m3u8 files
+ */
+"M3u" | "Csv" | "Json" | 
+/**
+ * A Reaper project with one item and one region per result
+ */
+"ReaperProject" | 
+/**
+ * A CMX 3600 edit decision list, which most DAWs and video editors import
+ */
+"Edl"
+export type FileCounts = { n_files: number; duration_seconds: number; n_segments: number }
+export type FileOverview = { duration_seconds: number | null; waveform: WaveformOverview; 
+/**
+ * Every segment of the file in order, or none without a query
+ */
+segments: SegmentSimilarity[] }
+export type FormatStats = { 
+/**
+ * Lowercase file extension, without the dot
+ */
+extension: string; counts: FileCounts }
+export type HttpApiSettings = { enabled: boolean; 
+/**
+ * The server only listens on localhost, at this port
+ */
+port: number; token: string }
+export type IndexError = { file_path: string; stage: string; kind: IndexErrorKind; message: string; attempts: number; last_failed_at: string; 
+/**
+ * When the file will next be tried, or null if it waits for the file to change
+ */
+next_retry_at: string | null }
+export type IndexErrorKind = 
+/**
+ * The file couldn't be read, e.g. it was deleted or is on an unplugged drive
+ */
+"Io" | 
+/**
+ * The file is in a format we can't decode. Only retried once the file changes
+ */
+"UnsupportedFormat" | 
+/**
+ * The file is corrupt or otherwise failed to decode
+ */
+"Decode" | 
+/**
+ * Resampling or feature extraction failed
+ */
+"Processing" | 
+/**
+ * The model failed to embed the file
+ */
+"Embedding" | 
+/**
+ * The file couldn't be written to the index
+ */
+"Database"
+export type IndexRoot = { path: string; options: RootOptions; 
+/**
+ * The innermost other root this one is nested in, if any.
+ * A nested root's options override its parent's for the nested subtree
+ */
+parent: string | null; 
+/**
+ * Offline roots, e.g. on an unmounted drive, aren't scanned but stay indexed
+ */
+online: boolean; last_seen_at: string | null }
+export type IndexRun = { id: number; started_at: string; 
+/**
+ * Null if the run was interrupted, or is still going
+ */
+ended_at: string | null; resumed: boolean; 
+/**
+ * For a resumed run, the unfinished files it picked up from the interrupted one
+ */
+files_scanned: number; files_new: number; files_moved: number; 
+/**
+ * Locations of indexed files that the scan didn't find, which were removed
+ */
+files_removed: number; files_failed: number; segments_embedded: number; 
+/**
+ * Why the run stopped early, or null if it finished
+ */
+error: string | null }
+export type IndexingPhase = 
+/**
+ * Walking the user's directories for audio files
+ */
+"Scanning" | 
+/**
+ * Hashing files to find the ones that aren't indexed yet
+ */
+"Hashing" | 
+/**
+ * Decoding the first files, before any have reached the model
+ */
+"Decoding" | "Embedding" | 
+/**
+ * Making the newly indexed files searchable
+ */
+"SyncingIndex"
+export type IndexingProgress = { started_indexing: string; newly_indexed: number; failed: number; total_to_index: number; segments_embedded: number; 
+/**
+ * Moving average of files finished per second, once there have been enough to tell
+ */
+files_per_second: number | null; eta_seconds: number | null }
 export type IndexingStatusChanged = Status
+export type LibraryStats = { total: FileCounts; 
+/**
+ * Files in the index that are under none of the roots are only counted in the total
+ */
+roots: RootStats[]; formats: FormatStats[]; database_size_mb: number; embeddings_size_mb: number; 
+/**
+ * Segments currently searchable in the vector index
+ */
+vector_index_segments: number; recent_runs: IndexRun[] }
 export type PreIndexingProgress = { started_preindexing: string; preindexed: number }
-export type Progress = { preindexing: PreIndexingProgress; indexing: IndexingProgress | null; total: number }
-export type Status = "Started" | { InProgress: Progress } | "Idle"
+export type Progress = { phase: IndexingPhase; preindexing: PreIndexingProgress; indexing: IndexingProgress | null; total: number; 
+/**
+ * Whether this run is picking up a job that was interrupted before it finished
+ */
+resumed: boolean; current_file: string | null }
+export type RefinedSearch = { 
+/**
+ * The refined query, which found the results
+ */
+query_embedding: number[]; results: { segment_id: number; file_path: string; other_locations: string[]; starting_timestamp: number; ending_timestamp: number; distance: number; available: boolean }[] }
+export type RegionOptions = { 
+/**
+ * Extra audio kept before the region, if the source has it
+ */
+pre_roll_seconds: number; 
+/**
+ * Extra audio kept after the region, if the source has it
+ */
+post_roll_seconds: number; 
+/**
+ * Linear fades at the edges of the clip, including the pre- and post-roll
+ */
+fade_in_seconds: number; fade_out_seconds: number }
+export type RootOptions = { 
+/**
+ * Disabled roots are skipped when scanning, but stay indexed
+ */
+enabled: boolean; 
+/**
+ * If any are given, only files matching one of these are indexed.
+ * Globs are matched against the path relative to the root, e.g. `**\/Foley/**`
+ */
+include_globs: string[]; 
+/**
+ * Files matching any of these are skipped, e.g. `**\/Bounces/**` or `*_preview.wav`
+ */
+exclude_globs: string[]; 
+/**
+ * How many directories deep to look. The root's own files are at depth 1
+ */
+max_depth: number | null; follow_symlinks: boolean; min_duration_seconds: number | null; max_duration_seconds: number | null }
+export type RootStats = { path: string; counts: FileCounts }
+export type SegmentSimilarity = { starting_timestamp: number; ending_timestamp: number; 
+/**
+ * Cosine similarity to the query, higher is closer
+ */
+similarity: number }
+export type SegmentationStrategy = 
+/**
+ * Fixed-length windows at a fixed step, regardless of content
+ */
+"FixedWindow" | 
+/**
+ * Skip silence and start windows on onsets
+ */
+"EventAligned"
+export type Status = { InProgress: Progress } | "Idle"
+export type StructuredQuery = { text_terms: TextTerm[]; audio_terms: AudioTerm[] }
+export type TextTerm = { text: string; weight: number }
 export type UpdateAudioIndex = null
+export type WaveformOverview = { 
+/**
+ * The largest absolute sample in each bucket
+ */
+peaks: number[]; 
+/**
+ * The RMS level of each bucket
+ */
+rms: number[] }
 
 /** tauri-specta globals **/
 